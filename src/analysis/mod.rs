@@ -0,0 +1,308 @@
+use super::engine::{Engine, PvLine, SearchLimits};
+use super::game::{self, Game};
+use super::mancala::{Move, Node, Player, Score};
+use super::minimax::{EngineOptions, Searcher};
+use std::fmt;
+
+// how good a played move was, by how much it gave away against the engine's choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Classification {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Classification::Best => "best",
+            Classification::Good => "good",
+            Classification::Inaccuracy => "inaccuracy",
+            Classification::Mistake => "mistake",
+            Classification::Blunder => "blunder"
+        })
+    }
+}
+
+// the most a move can lose, in stones, and still get each classification.
+// anything losing more than `mistake` is a blunder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub good: Score,
+    pub inaccuracy: Score,
+    pub mistake: Score,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds { good: 1, inaccuracy: 3, mistake: 6 }
+    }
+}
+
+impl Thresholds {
+    pub fn classify(&self, loss: Score) -> Classification {
+        match loss {
+            loss if loss <= 0 => Classification::Best,
+            loss if loss <= self.good => Classification::Good,
+            loss if loss <= self.inaccuracy => Classification::Inaccuracy,
+            loss if loss <= self.mistake => Classification::Mistake,
+            _ => Classification::Blunder
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis {
+    pub player: Player,
+    pub played: Move,
+    // the engine's choice
+    pub best: Option<Move>,
+    // evaluations from White's point of view, in stones: with the best move, and after the one played
+    pub before: Score,
+    pub after: Score,
+    // what the move gave away, for the side that played it (never negative)
+    pub loss: Score,
+    pub classification: Classification,
+}
+
+// the limits for judging the position after a move: one move less deep, so both
+// evaluations look equally far ahead
+fn after_limits(limits: SearchLimits) -> SearchLimits {
+    SearchLimits { depth: limits.depth.map(|depth| depth.saturating_sub(1).max(1)), ..limits }
+}
+
+// compares `played` against what `engine` would have played in `node`
+pub fn analyze_move(engine: &mut dyn Engine, node: &Node, played: &Move, limits: SearchLimits, thresholds: &Thresholds) -> MoveAnalysis {
+    let result = engine.search(node, limits);
+    let after = match result.best_move.as_ref() == Some(played) {
+        true => result.score,
+        false => {
+            let mut child = node.clone();
+            child.full_move(played).expect("the move was played in this position");
+            match child.is_over() {
+                true => child.final_score(),
+                false => engine.search(&child, after_limits(limits)).score
+            }
+        }
+    };
+    let sign = match node.get_turn() {
+        Player::White => 1,
+        Player::Black => -1
+    };
+    let loss = (sign * (result.score - after)).max(0);
+    MoveAnalysis {
+        player: node.get_turn().clone(),
+        played: played.clone(),
+        best: result.best_move,
+        before: result.score,
+        after,
+        loss,
+        classification: thresholds.classify(loss),
+    }
+}
+
+// every move of the game, in order
+pub fn analyze_game(engine: &mut dyn Engine, game: &Game, limits: SearchLimits, thresholds: &Thresholds) -> Vec<MoveAnalysis> {
+    game.history().iter().zip(game.moves()).map(|(node, played)| analyze_move(engine, node, played, limits, thresholds)).collect()
+}
+
+// a capture the engine's move would have made and the played move didn't
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissedCapture {
+    // index into Report::moves
+    pub index: usize,
+    pub best: Move,
+    // how many more stones the best move captured
+    pub stones: Score,
+}
+
+// everything a post-game look at a game turns up
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub moves: Vec<MoveAnalysis>,
+    // the evaluation (White's point of view, in stones) after each move, for drawing a graph
+    pub evals: Vec<Score>,
+    // the moves that gave away the most, biggest first: indices into `moves`, only ones that
+    // were worse than good
+    pub turning_points: Vec<usize>,
+    pub missed_captures: Vec<MissedCapture>,
+}
+
+// turning points kept in a report
+const TURNING_POINTS: usize = 3;
+
+fn captured(node: &Node, mv: &Move) -> Score {
+    node.children_with_info().into_iter().find(|(child_move, _, _)| child_move == mv).map_or(0, |(_, _, info)| info.captured)
+}
+
+pub fn report(engine: &mut dyn Engine, game: &Game, limits: SearchLimits, thresholds: &Thresholds) -> Report {
+    let moves = analyze_game(engine, game, limits, thresholds);
+    let evals = moves.iter().map(|analysis| analysis.after).collect();
+    let mut turning_points: Vec<usize> = (0..moves.len()).filter(|&index| moves[index].classification > Classification::Good).collect();
+    // a stable sort, so earlier moves come first on a tie
+    turning_points.sort_by_key(|&index| std::cmp::Reverse(moves[index].loss));
+    turning_points.truncate(TURNING_POINTS);
+    let mut missed_captures = Vec::new();
+    for (index, (node, analysis)) in game.history().iter().zip(&moves).enumerate() {
+        let Some(best) = analysis.best.as_ref().filter(|_| analysis.classification > Classification::Good) else { continue; };
+        let stones = captured(node, best) - captured(node, &analysis.played);
+        if stones > 0 {
+            missed_captures.push(MissedCapture { index, best: best.clone(), stones });
+        }
+    }
+    Report { moves, evals, turning_points, missed_captures }
+}
+
+impl Report {
+    // the percentage of `player`'s moves that were best or good, None if they didn't play any
+    pub fn accuracy(&self, player: &Player) -> Option<f64> {
+        let moves: Vec<&MoveAnalysis> = self.moves.iter().filter(|analysis| analysis.player == *player).collect();
+        let good = moves.iter().filter(|analysis| analysis.classification <= Classification::Good).count();
+        Some(100.0 * good as f64 / moves.len() as f64).filter(|_| !moves.is_empty())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, analysis) in self.moves.iter().enumerate() {
+            write!(f, "{:3}. {} {:8} {:+3} {}", index + 1, analysis.player, game::format_move(&analysis.played), analysis.after, analysis.classification)?;
+            match analysis.best.as_ref().filter(|_| analysis.classification != Classification::Best) {
+                Some(best) => writeln!(f, " (best {} {:+})", game::format_move(best), analysis.before)?,
+                None => writeln!(f)?
+            }
+        }
+        let evals: Vec<String> = self.evals.iter().map(|eval| format!("{:+}", eval)).collect();
+        writeln!(f, "Evaluation: {}", evals.join(" "))?;
+        match self.turning_points.is_empty() {
+            true => writeln!(f, "No turning points.")?,
+            false => {
+                writeln!(f, "Turning points:")?;
+                for &index in &self.turning_points {
+                    let analysis = &self.moves[index];
+                    writeln!(f, "  move {}: {} lost {} with {} ({})", index + 1, analysis.player, analysis.loss, game::format_move(&analysis.played), analysis.classification)?;
+                }
+            }
+        }
+        for missed in &self.missed_captures {
+            let analysis = &self.moves[missed.index];
+            writeln!(f, "Missed capture: move {}: {} could have taken {} more with {}", missed.index + 1, analysis.player, missed.stones, game::format_move(&missed.best))?;
+        }
+        Ok(())
+    }
+}
+
+// how many moves of each line a ranking shows
+const SHORT_PV: usize = 5;
+
+// every legal move in a position with the score the engine gives it, best first for the
+// side to move
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranking {
+    pub turn: Player,
+    // scores are White's point of view, as in SearchResult
+    pub lines: Vec<PvLine>,
+}
+
+// searches every root move with a line of its own (multipv as wide as the position), so each
+// score is exact rather than just "worse than the best"
+pub fn rank_moves(node: &Node, limits: SearchLimits) -> Ranking {
+    let mut engine = Searcher::new(EngineOptions { multipv: node.legal_moves().len(), ..EngineOptions::default() });
+    let lines = match node.is_over() {
+        true => Vec::new(),
+        false => engine.search(node, limits).lines
+    };
+    Ranking { turn: node.get_turn().clone(), lines }
+}
+
+impl fmt::Display for Ranking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  #  move      score  line (scores for {})", self.turn)?;
+        for (rank, line) in self.lines.iter().enumerate() {
+            let score = if self.turn == Player::White { line.score } else { -line.score };
+            let mut pv: Vec<String> = line.pv.iter().take(SHORT_PV).map(game::format_move).collect();
+            if line.pv.len() > SHORT_PV {
+                pv.push("...".to_string());
+            }
+            writeln!(f, "{:3}  {:8} {:+6}  {}", rank + 1, line.pv.first().map(game::format_move).unwrap_or_default(), score, pv.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::RuleSet;
+
+    #[test]
+    fn test_classify() {
+        let thresholds = Thresholds::default();
+        let classes: Vec<_> = [0, 1, 2, 3, 5, 6, 7, 20].into_iter().map(|loss| thresholds.classify(loss)).collect();
+        use Classification::*;
+        assert_eq!(classes, vec![Best, Good, Inaccuracy, Inaccuracy, Mistake, Mistake, Blunder, Blunder]);
+    }
+
+    #[test]
+    fn test_analyze_move() {
+        // White can capture the 10 stones in pit 11 by playing pit 0 into the empty pit 1
+        let board = [1, 0, 0, 0, 3, 5, 10, 1, 1, 1, 1, 10, 0, 12];
+        let node = Node::from_board(&board, Player::White, RuleSet::default()).unwrap();
+        let mut engine = Searcher::new(EngineOptions { endgame_stones: 0, ..EngineOptions::default() });
+        let limits = SearchLimits::depth(4);
+        let thresholds = Thresholds::default();
+        let best = analyze_move(&mut engine, &node, &vec![0], limits, &thresholds);
+        assert_eq!((best.best.clone(), best.loss, best.classification), (Some(vec![0]), 0, Classification::Best));
+        assert_eq!(best.before, best.after);
+        let missed = analyze_move(&mut engine, &node, &vec![4], limits, &thresholds);
+        assert_eq!(missed.best, Some(vec![0]));
+        assert!(missed.after < missed.before);
+        assert_eq!(missed.classification, Classification::Blunder);
+
+        let game = Game::from_record(RuleSet::default(), "0 7 4").unwrap();
+        let moves = analyze_game(&mut engine, &game, SearchLimits::depth(2), &thresholds);
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[1].player, Player::Black);
+        assert!(moves.iter().all(|analysis| analysis.loss >= 0));
+    }
+
+    #[test]
+    fn test_report() {
+        // the capture from test_analyze_move, missed by playing pit 4
+        let board = [1, 0, 0, 0, 3, 5, 10, 1, 1, 1, 1, 10, 0, 12];
+        let node = Node::from_board(&board, Player::White, RuleSet::default()).unwrap();
+        let mut game = Game::from_node(node);
+        game.play(&vec![4]).unwrap();
+        let mut engine = Searcher::new(EngineOptions { endgame_stones: 0, ..EngineOptions::default() });
+        let report = report(&mut engine, &game, SearchLimits::depth(4), &Thresholds::default());
+        assert_eq!(report.moves.len(), 1);
+        assert_eq!(report.evals, vec![report.moves[0].after]);
+        assert_eq!(report.turning_points, vec![0]);
+        assert_eq!(report.missed_captures.len(), 1);
+        assert_eq!((report.missed_captures[0].index, &report.missed_captures[0].best), (0, &vec![0]));
+        assert_eq!(report.missed_captures[0].stones, 11);
+        assert_eq!((report.accuracy(&Player::White), report.accuracy(&Player::Black)), (Some(0.0), None));
+        let text = report.to_string();
+        assert!(text.contains("blunder") && text.contains("Missed capture"));
+    }
+
+    #[test]
+    fn test_rank_moves() {
+        // the capture from test_analyze_move comes out on top
+        let board = [1, 0, 0, 0, 3, 5, 10, 1, 1, 1, 1, 10, 0, 12];
+        let node = Node::from_board(&board, Player::White, RuleSet::default()).unwrap();
+        let ranking = rank_moves(&node, SearchLimits::depth(4));
+        let mut first: Vec<Move> = ranking.lines.iter().map(|line| line.pv[0].clone()).collect();
+        assert_eq!(first[0], vec![0]);
+        assert!(ranking.lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        first.sort();
+        assert_eq!(first, node.legal_moves());
+        assert!(ranking.to_string().contains("scores for White"));
+
+        let black = Node::from_fen("4,4,4,4,4,4,0,4,4,4,4,4,4,0 b", RuleSet::default()).unwrap();
+        let ranking = rank_moves(&black, SearchLimits::depth(3));
+        assert_eq!(ranking.lines.len(), black.legal_moves().len());
+        assert!(ranking.lines.windows(2).all(|pair| pair[0].score <= pair[1].score));
+    }
+}
@@ -0,0 +1,792 @@
+use super::endgame::EndgameDb;
+use super::engine::{Engine, ResignPolicy, Resigner, SearchLimits, SearchResult};
+use super::eval::EvalWeights;
+use super::game::Game;
+use super::log::{self, Level};
+use super::mancala::{Node, Outcome, Player, Score, TerminationReason};
+use super::mcts::{MctsConfig, MctsEngine};
+use super::minimax::{Difficulty, EngineOptions, Personality, Searcher};
+use super::tune::Adjudication;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// how deep a contestant searches when it's given no limit at all
+const DEPTH: usize = 10;
+
+// one engine configuration to pit against others. on the command line it's written as
+// comma-separated settings, e.g. "depth=6,personality=hoarder" or "mcts,movetime=500";
+// in a file, as a TOML table with the same keys.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Contestant {
+    // what it's called in results (made up from the settings if not given)
+    pub name: Option<String>,
+    // one of the minimax::Difficulty presets, for both its options and its limits
+    pub difficulty: Option<String>,
+    pub personality: Option<String>,
+    pub depth: Option<usize>,
+    // per move, in milliseconds
+    pub movetime: Option<u64>,
+    pub nodes: Option<u64>,
+    // Monte Carlo tree search instead of alpha-beta
+    pub mcts: bool,
+    // a TOML file of evaluation weights, for alpha-beta
+    pub weights: Option<PathBuf>,
+    pub seed: u64,
+    pub eval_noise: Score,
+    pub random_margin: Option<Score>,
+    pub threads: Option<usize>,
+}
+
+impl Contestant {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut table = toml::Table::new();
+        for setting in spec.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let value = match setting.split_once('=') {
+                Some((key, value)) => {
+                    let value = match (value.parse::<i64>(), value.parse::<bool>()) {
+                        (Ok(number), _) => toml::Value::Integer(number),
+                        (_, Ok(flag)) => toml::Value::Boolean(flag),
+                        _ => toml::Value::String(value.to_string())
+                    };
+                    (key, value)
+                }
+                None => (setting, toml::Value::Boolean(true))
+            };
+            table.insert(value.0.to_string(), value.1);
+        }
+        Self::from_table(table)
+    }
+
+    pub fn from_table(table: toml::Table) -> Result<Self, String> {
+        let contestant: Contestant = toml::Value::Table(table).try_into().map_err(|error: toml::de::Error| error.message().to_string())?;
+        contestant.check()?;
+        Ok(contestant)
+    }
+
+    // the names serde can't check
+    fn check(&self) -> Result<(), String> {
+        if let Some(name) = self.difficulty.as_deref().filter(|name| Difficulty::from_name(name).is_none()) {
+            return Err(format!("unknown difficulty {}", name));
+        }
+        if let Some(name) = self.personality.as_deref().filter(|name| Personality::from_name(name).is_none()) {
+            return Err(format!("unknown personality {}", name));
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let mut parts = Vec::new();
+        if self.mcts {
+            parts.push("mcts".to_string());
+        }
+        parts.extend(self.difficulty.clone());
+        parts.extend(self.personality.clone());
+        parts.extend(self.depth.map(|depth| format!("d{}", depth)));
+        parts.extend(self.movetime.map(|movetime| format!("{}ms", movetime)));
+        parts.extend(self.nodes.map(|nodes| format!("{}n", nodes)));
+        parts.extend(self.weights.as_ref().and_then(|path| path.file_stem()).map(|stem| stem.to_string_lossy().into_owned()));
+        match parts.is_empty() {
+            true => "default".to_string(),
+            false => parts.join("-")
+        }
+    }
+
+    // the engine with `seed` (the --seed everything is run with) on top of its own, above where
+    // match_game adds the game's number, so that no two seeds play the same games and 0 changes
+    // nothing
+    pub fn with_seed(&self, seed: u64) -> Self {
+        Contestant { seed: self.seed.wrapping_add(seed << 32), ..self.clone() }
+    }
+
+    fn preset(&self) -> Option<Difficulty> {
+        self.difficulty.as_deref().and_then(Difficulty::from_name)
+    }
+
+    // a depth, time or node limit replaces the preset's
+    pub fn limits(&self) -> SearchLimits {
+        if self.depth.is_none() && self.movetime.is_none() && self.nodes.is_none() {
+            return self.preset().map_or(SearchLimits::depth(DEPTH), Difficulty::limits);
+        }
+        SearchLimits {
+            depth: self.depth,
+            movetime: self.movetime.map(std::time::Duration::from_millis),
+            nodes: self.nodes,
+            ..SearchLimits::default()
+        }
+    }
+
+    // only fails if the weights can't be read
+    pub fn engine(&self) -> io::Result<Box<dyn Engine>> {
+        let threads = self.threads.unwrap_or(1);
+        if self.mcts {
+            return Ok(Box::new(MctsEngine::new(MctsConfig { threads, seed: self.seed, reuse_tree: true, ..MctsConfig::default() })));
+        }
+        let options = EngineOptions { threads, seed: self.seed, ..self.preset().map_or(EngineOptions::default(), Difficulty::options) };
+        let options = EngineOptions {
+            eval_noise: options.eval_noise.max(self.eval_noise),
+            random_margin: self.random_margin.or(options.random_margin),
+            ..options
+        };
+        let options = match self.personality.as_deref().and_then(Personality::from_name) {
+            Some(personality) => personality.apply(options),
+            None => options
+        };
+        let options = match &self.weights {
+            Some(path) => EngineOptions { weights: EvalWeights::load(path)?, ..options },
+            None => options
+        };
+        Ok(Box::new(Searcher::new(options)))
+    }
+}
+
+// how a game between two engines is run
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSettings {
+    pub start: Node,
+    pub adjudication: Adjudication,
+    // None to play every game out
+    pub resign: Option<ResignPolicy>,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        GameSettings { start: Node::default(), adjudication: Adjudication::OFF, resign: None }
+    }
+}
+
+// plays a game to the end, calling `observer` with the game after every move and the search
+// that chose it
+pub fn play(white: &mut dyn Engine, black: &mut dyn Engine, limits: [SearchLimits; 2], settings: &GameSettings, observer: impl FnMut(&Game, &SearchResult)) -> Game {
+    play_on(Game::from_node(settings.start.clone()), white, black, limits, settings, observer)
+}
+
+// the same, carrying on a game that's already under way (settings.start isn't used)
+pub fn play_on(game: Game, white: &mut dyn Engine, black: &mut dyn Engine, limits: [SearchLimits; 2], settings: &GameSettings, observer: impl FnMut(&Game, &SearchResult)) -> Game {
+    let game = play_out(game, white, black, limits, settings, None, observer);
+    if let Some(outcome) = game.outcome() {
+        log::event("game", Level::Info, || format!("{} after {} moves ({}): {}", outcome.winner.map_or("draw".to_string(), |winner| format!("{} wins", winner)), game.moves().len(), outcome.reason, game.record()));
+    }
+    game
+}
+
+// the loop under play_on and tune::play_adjudicated: each side searches and plays in turn until
+// the game is over, adjudicated or resigned. with a database, a position it knows is called
+// before anybody searches it.
+pub fn play_out(mut game: Game, white: &mut dyn Engine, black: &mut dyn Engine, limits: [SearchLimits; 2], settings: &GameSettings, db: Option<&EndgameDb>, mut observer: impl FnMut(&Game, &SearchResult)) -> Game {
+    let mut resigners = [settings.resign, settings.resign].map(|policy| policy.map(Resigner::new));
+    // each search's score (White's point of view) and whether it was proven
+    let mut scores: Vec<(Score, bool)> = Vec::new();
+    while game.outcome().is_none() {
+        let node = game.node().clone();
+        if let Some(score) = db.and_then(|db| db.probe(&node)) {
+            game.end(node.outcome_with(score, TerminationReason::Adjudication)).expect("the game isn't over");
+            break;
+        }
+        let side = node.get_turn().clone();
+        let result = match side {
+            Player::White => white.search(&node, limits[0]),
+            Player::Black => black.search(&node, limits[1])
+        };
+        scores.push((result.score, result.exact));
+        if let Some(score) = settings.adjudication.verdict(&scores) {
+            game.end(node.outcome_with(score, TerminationReason::Adjudication)).expect("the game isn't over");
+            break;
+        }
+        if resigners[side.index()].as_mut().is_some_and(|resigner| resigner.should_resign(&result, &side)) {
+            game.resign(side).expect("the game isn't over");
+            break;
+        }
+        let Some(mv) = result.best_move.clone() else { break; };
+        game.play(&mv).expect("the engine plays legal moves");
+        observer(&game, &result);
+    }
+    game
+}
+
+// one game of a match
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchGame {
+    // which of the two contestants had White: 0 for the first
+    pub white: usize,
+    pub outcome: Outcome,
+    pub record: String,
+}
+
+impl MatchGame {
+    // what the first contestant got from it: 1 for a win, 0.5 for a draw
+    pub fn first_points(&self) -> f64 {
+        match self.outcome.winner.as_ref().map(|winner| winner.index() == self.white) {
+            Some(true) => 1.0,
+            Some(false) => 0.0,
+            None => 0.5
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub names: [String; 2],
+    pub games: Vec<MatchGame>,
+}
+
+impl Match {
+    // each contestant's points so far
+    pub fn score(&self) -> [f64; 2] {
+        let first: f64 = self.games.iter().map(MatchGame::first_points).sum();
+        [first, self.games.len() as f64 - first]
+    }
+
+    pub fn results(&self) -> Vec<GameResult> {
+        self.games.iter().map(|game| GameResult {
+            white: self.names[game.white].clone(),
+            black: self.names[1 - game.white].clone(),
+            points: match game.outcome.winner {
+                Some(Player::White) => 1.0,
+                Some(Player::Black) => 0.0,
+                None => 0.5
+            },
+            record: game.record.clone(),
+        }).collect()
+    }
+
+    // the first contestant's wins, draws and losses
+    pub fn wdl(&self) -> [usize; 3] {
+        let mut wdl = [0; 3];
+        for game in &self.games {
+            wdl[(2.0 - 2.0 * game.first_points()) as usize] += 1;
+        }
+        wdl
+    }
+}
+
+// the expected score against an opponent `elo` points weaker
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// the other way round
+fn elo_for_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// how much stronger one player looks than another, with a 95% confidence interval of
+// `elo - below` to `elo + above` (infinite when so few games were played that the interval
+// reaches a score of 0 or 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EloEstimate {
+    pub elo: f64,
+    pub below: f64,
+    pub above: f64,
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+.0} (-{:.0}, +{:.0})", self.elo, self.below, self.above)
+    }
+}
+
+// from the first player's wins, draws and losses. None unless there are some of each of at
+// least two kinds, since an unbeaten score says nothing about how much stronger someone is.
+pub fn elo_difference([wins, draws, losses]: [usize; 3]) -> Option<EloEstimate> {
+    let games = (wins + draws + losses) as f64;
+    let (win, draw) = (wins as f64 / games, draws as f64 / games);
+    let score = win + draw / 2.0;
+    if games == 0.0 || score <= 0.0 || score >= 1.0 {
+        return None;
+    }
+    let variance = win * (1.0 - score).powi(2) + draw * (0.5 - score).powi(2) + (1.0 - win - draw) * score.powi(2);
+    let margin = 1.96 * (variance / games).sqrt();
+    let elo = elo_for_score(score);
+    // scores of 0 or 1 are infinitely far off
+    let bound = |score: f64| if score <= 0.0 { f64::NEG_INFINITY } else if score >= 1.0 { f64::INFINITY } else { elo_for_score(score) };
+    Some(EloEstimate {
+        elo,
+        below: elo - bound(score - margin),
+        above: bound(score + margin) - elo,
+    })
+}
+
+// a finished game as written to a results file: one a line, tab-separated, as
+// "<white>\t<black>\t<1-0, 0-1 or 1/2-1/2>\t<record>"
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameResult {
+    pub white: String,
+    pub black: String,
+    // White's points
+    pub points: f64,
+    pub record: String,
+}
+
+impl GameResult {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [white, black, result, record] = fields.as_slice() else { return None; };
+        let points = match *result {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            _ => { return None; }
+        };
+        Some(GameResult { white: white.to_string(), black: black.to_string(), points, record: record.to_string() })
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self.points {
+            1.0 => "1-0",
+            0.0 => "0-1",
+            _ => "1/2-1/2"
+        };
+        write!(f, "{}\t{}\t{}\t{}", self.white, self.black, result, self.record)
+    }
+}
+
+// adds the games to the end of a results file
+pub fn save_results(path: &Path, results: &[GameResult]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    for result in results {
+        writeln!(file, "{}", result)?;
+    }
+    Ok(())
+}
+
+pub fn load_results(path: &Path) -> io::Result<Vec<GameResult>> {
+    let text = fs::read_to_string(path)?;
+    text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(number, line)| {
+        GameResult::parse(line).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("line {} isn't a game result", number + 1)))
+    }).collect()
+}
+
+// everyone's rating from all the games between them, the way Ordo or BayesElo would: the
+// ratings that make the results most likely, averaging 0. every pair who met starts with a
+// draw between them, so that nobody who won (or lost) every game gets an infinite rating.
+pub fn ratings(results: &[GameResult]) -> Vec<(String, f64)> {
+    let mut names: Vec<String> = Vec::new();
+    for result in results {
+        for name in [&result.white, &result.black] {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    let index = |name: &String| names.iter().position(|known| known == name).expect("everyone has a place");
+    let count = names.len();
+    // games between each pair, and each player's points
+    let mut games = vec![vec![0.0; count]; count];
+    let mut points = vec![0.0; count];
+    for result in results {
+        let (white, black) = (index(&result.white), index(&result.black));
+        games[white][black] += 1.0;
+        games[black][white] += 1.0;
+        points[white] += result.points;
+        points[black] += 1.0 - result.points;
+    }
+    for (i, row) in games.iter_mut().enumerate() {
+        for (j, played) in row.iter_mut().enumerate() {
+            if i != j && *played > 0.0 {
+                *played += 1.0;
+                points[i] += 0.5;
+            }
+        }
+    }
+    // minorization-maximization for the Bradley-Terry model, on strengths 10^(rating / 400)
+    let mut strength = vec![1.0; count];
+    for _ in 0..1000 {
+        let next: Vec<f64> = (0..count).map(|i| {
+            let expected: f64 = (0..count).filter(|&j| j != i).map(|j| games[i][j] / (strength[i] + strength[j])).sum();
+            if expected > 0.0 { points[i] / expected } else { strength[i] }
+        }).collect();
+        let mean = next.iter().map(|value: &f64| value.log10()).sum::<f64>() / count as f64;
+        strength = next.iter().map(|value| value / 10f64.powf(mean)).collect();
+    }
+    let mut ratings: Vec<(String, f64)> = names.into_iter().zip(strength.iter().map(|value| 400.0 * value.log10())).collect();
+    ratings.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ratings
+}
+
+// a sequential probability ratio test: keep playing until the results make it clear enough
+// whether the first contestant is `elo1` Elo stronger than the second (H1) or only `elo0` (H0),
+// being wrong about H1 at most `alpha` of the time and about H0 at most `beta` of the time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Sprt { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 }
+    }
+}
+
+impl Sprt {
+    // the log-likelihood ratio of H1 to H0 after these results (the normal approximation
+    // to the trinomial test, as fishtest does it). 0 until there's been both a win and a loss.
+    pub fn llr(&self, [wins, draws, losses]: [usize; 3]) -> f64 {
+        if wins == 0 || losses == 0 {
+            return 0.0;
+        }
+        let games = (wins + draws + losses) as f64;
+        let (win, draw, loss) = (wins as f64 / games, draws as f64 / games, losses as f64 / games);
+        let score = win + draw / 2.0;
+        let variance = win * (1.0 - score).powi(2) + draw * (0.5 - score).powi(2) + loss * score.powi(2);
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        games * (score1 - score0) * (2.0 * score - score0 - score1) / (2.0 * variance)
+    }
+
+    // the LLR below which H0 is accepted, and above which H1 is
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    // Some(true) once H1 is accepted, Some(false) once H0 is
+    pub fn decision(&self, wdl: [usize; 3]) -> Option<bool> {
+        let llr = self.llr(wdl);
+        let (lower, upper) = self.bounds();
+        match (llr <= lower, llr >= upper) {
+            (true, _) => Some(false),
+            (_, true) => Some(true),
+            _ => None
+        }
+    }
+}
+
+// game `number` of a match, the first contestant with White in the even ones. every game gets
+// fresh engines, seeded with the game's number on top of their own seeds, so that engines
+// playing at random don't repeat the same game.
+fn match_game(contestants: [&Contestant; 2], number: usize, settings: &GameSettings) -> io::Result<MatchGame> {
+    let white = number % 2;
+    let [first, second] = [white, 1 - white].map(|index| Contestant { seed: contestants[index].seed.wrapping_add(number as u64), ..contestants[index].clone() });
+    let game = play(&mut *first.engine()?, &mut *second.engine()?, [first.limits(), second.limits()], settings, |_, _| {});
+    let outcome = game.outcome().expect("games are played to the end");
+    Ok(MatchGame { white, outcome, record: game.record() })
+}
+
+// plays `games` games between two contestants, calling `progress` after each
+pub fn play_match(contestants: &[Contestant; 2], games: usize, settings: &GameSettings, mut progress: impl FnMut(&Match)) -> io::Result<Match> {
+    let mut result = Match { names: contestants.clone().map(|contestant| contestant.name()), games: Vec::new() };
+    for number in 0..games {
+        result.games.push(match_game([&contestants[0], &contestants[1]], number, settings)?);
+        progress(&result);
+    }
+    Ok(result)
+}
+
+// plays games until `sprt` decides, or `max_games` have been played without it deciding,
+// calling `progress` after each
+pub fn play_sprt(contestants: &[Contestant; 2], sprt: &Sprt, max_games: usize, settings: &GameSettings, mut progress: impl FnMut(&Match)) -> io::Result<(Match, Option<bool>)> {
+    let mut result = Match { names: contestants.clone().map(|contestant| contestant.name()), games: Vec::new() };
+    for number in 0..max_games {
+        result.games.push(match_game([&contestants[0], &contestants[1]], number, settings)?);
+        progress(&result);
+        if let Some(decision) = sprt.decision(result.wdl()) {
+            return Ok((result, Some(decision)));
+        }
+    }
+    Ok((result, None))
+}
+
+// a round robin, read from a TOML file:
+//
+//   games = 4
+//
+//   [[engine]]
+//   depth = 4
+//
+//   [[engine]]
+//   name = "hoarder"
+//   depth = 6
+//   personality = "hoarder"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TournamentConfig {
+    // between each pair of engines
+    #[serde(default = "TournamentConfig::default_games")]
+    pub games: usize,
+    #[serde(rename = "engine")]
+    pub engines: Vec<Contestant>,
+}
+
+impl TournamentConfig {
+    fn default_games() -> usize {
+        2
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let config: TournamentConfig = toml::from_str(&fs::read_to_string(path)?).map_err(|error| invalid(error.message().to_string()))?;
+        if config.engines.len() < 2 {
+            return Err(invalid("a tournament needs at least two engines".to_string()));
+        }
+        for contestant in &config.engines {
+            contestant.check().map_err(invalid)?;
+        }
+        Ok(config)
+    }
+}
+
+// the games between two of a tournament's engines, by their places in the list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pairing {
+    pub players: [usize; 2],
+    pub result: Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tournament {
+    pub names: Vec<String>,
+    pub pairings: Vec<Pairing>,
+}
+
+impl Tournament {
+    // points[i][j]: what engine i scored against engine j
+    pub fn points(&self) -> Vec<Vec<f64>> {
+        let mut points = vec![vec![0.0; self.names.len()]; self.names.len()];
+        for pairing in &self.pairings {
+            let [first, second] = pairing.result.score();
+            let [i, j] = pairing.players;
+            points[i][j] += first;
+            points[j][i] += second;
+        }
+        points
+    }
+
+    pub fn totals(&self) -> Vec<f64> {
+        self.points().iter().map(|row| row.iter().sum()).collect()
+    }
+
+    pub fn results(&self) -> Vec<GameResult> {
+        self.pairings.iter().flat_map(|pairing| pairing.result.results()).collect()
+    }
+}
+
+// every engine plays every other `config.games` times, the games spread over `threads` threads.
+// `progress` is called (on this thread) with every finished game and the pair that played it.
+pub fn play_tournament(config: &TournamentConfig, settings: &GameSettings, threads: usize, mut progress: impl FnMut([usize; 2], &MatchGame)) -> io::Result<Tournament> {
+    let names: Vec<String> = config.engines.iter().map(Contestant::name).collect();
+    let count = config.engines.len();
+    let pairs: Vec<[usize; 2]> = (0..count).flat_map(|i| (i + 1..count).map(move |j| [i, j])).collect();
+    let jobs: Vec<([usize; 2], usize)> = pairs.iter().flat_map(|&pair| (0..config.games).map(move |number| (pair, number))).collect();
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut games: Vec<Option<MatchGame>> = vec![None; jobs.len()];
+    thread::scope(|scope| -> io::Result<()> {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (jobs, next) = (&jobs, &next);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&([i, j], number)) = jobs.get(index) else { break; };
+                    let game = match_game([&config.engines[i], &config.engines[j]], number, settings);
+                    let failed = game.is_err();
+                    if sender.send((index, game)).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (index, game) in receiver {
+            let game = game?;
+            progress(jobs[index].0, &game);
+            games[index] = Some(game);
+        }
+        Ok(())
+    })?;
+    let mut games = games.into_iter().map(|game| game.expect("every game was played"));
+    let pairings = pairs.into_iter().map(|players| Pairing {
+        players,
+        result: Match { names: players.map(|index| names[index].clone()), games: games.by_ref().take(config.games).collect() },
+    }).collect();
+    Ok(Tournament { names, pairings })
+}
+
+// a crosstable, best first: each row is what that engine scored against each of the others
+impl fmt::Display for Tournament {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points = self.points();
+        let totals = self.totals();
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|&a, &b| totals[b].total_cmp(&totals[a]));
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(6);
+        write!(f, "    {:width$}  Points", "Engine", width = width)?;
+        for rank in 1..=order.len() {
+            write!(f, "  {:>5}", rank)?;
+        }
+        writeln!(f)?;
+        for (rank, &i) in order.iter().enumerate() {
+            write!(f, "{:>2}. {:width$}  {:>6}", rank + 1, self.names[i], totals[i], width = width)?;
+            for &j in &order {
+                match i == j {
+                    true => write!(f, "  {:>5}", "-")?,
+                    false => write!(f, "  {:>5}", points[i][j])?
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// a table of the games, then the final score
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(5);
+        writeln!(f, "{:>4}  {:width$}  {:width$}  {:7}  {:>5}  Reason", "Game", "White", "Black", "Result", "Moves", width = width)?;
+        for (number, game) in self.games.iter().enumerate() {
+            let result = match game.outcome.winner {
+                Some(Player::White) => "1-0",
+                Some(Player::Black) => "0-1",
+                None => "1/2-1/2"
+            };
+            let moves = game.record.split_whitespace().count();
+            writeln!(f, "{:>4}  {:width$}  {:width$}  {:7}  {:>5}  {}", number + 1, self.names[game.white], self.names[1 - game.white], result, moves, game.outcome.reason, width = width)?;
+        }
+        let [first, second] = self.score();
+        write!(f, "{} {} - {} {}", self.names[0], first, second, self.names[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::RuleSet;
+
+    #[test]
+    fn test_contestant() {
+        let contestant = Contestant::parse("depth=3, personality=hoarder,mcts=false").unwrap();
+        assert_eq!((contestant.depth, contestant.personality.as_deref(), contestant.mcts), (Some(3), Some("hoarder"), false));
+        assert_eq!(contestant.name(), "hoarder-d3");
+        assert_eq!(contestant.limits(), SearchLimits::depth(3));
+        assert_eq!(Contestant::parse("difficulty=easy").unwrap().limits(), Difficulty::Easy.limits());
+        assert!(Contestant::parse("mcts,name=fast").unwrap().mcts);
+        assert!(Contestant::parse("deep=3").is_err());
+        assert!(Contestant::parse("difficulty=impossible").is_err());
+        assert!(Contestant::parse("depth=deep").is_err());
+    }
+
+    #[test]
+    fn test_play() {
+        let first = Contestant::parse("depth=2").unwrap();
+        let second = Contestant::parse("depth=4").unwrap();
+        let mut moves = 0;
+        let game = play(&mut *first.engine().unwrap(), &mut *second.engine().unwrap(), [first.limits(), second.limits()], &GameSettings::default(), |game, result| {
+            moves += 1;
+            assert_eq!(game.moves().last(), result.best_move.as_ref());
+        });
+        assert!(game.outcome().is_some());
+        assert_eq!(moves, game.moves().len());
+
+        let settings = GameSettings { resign: Some(ResignPolicy { threshold: 1, moves: 1, max_chance: 1.0 }), ..GameSettings::default() };
+        let game = play(&mut *first.engine().unwrap(), &mut *second.engine().unwrap(), [first.limits(), second.limits()], &settings, |_, _| {});
+        assert_eq!(game.outcome().unwrap().reason, TerminationReason::Resignation);
+
+        // carrying on keeps the moves already played
+        let opening = Game::from_record(RuleSet::default(), "2-5 9").unwrap();
+        let game = play_on(opening, &mut *first.engine().unwrap(), &mut *second.engine().unwrap(), [first.limits(), second.limits()], &GameSettings::default(), |_, _| {});
+        assert!(game.outcome().is_some() && game.record().starts_with("2-5 9 "));
+    }
+
+    #[test]
+    fn test_match() {
+        let contestants = [Contestant::parse("depth=1,name=shallow").unwrap(), Contestant::parse("depth=3,random_margin=1").unwrap()];
+        let mut reports = 0;
+        let result = play_match(&contestants, 4, &GameSettings::default(), |_| { reports += 1; }).unwrap();
+        assert_eq!((reports, result.games.len()), (4, 4));
+        assert_eq!(result.games.iter().map(|game| game.white).collect::<Vec<_>>(), vec![0, 1, 0, 1]);
+        let [first, second] = result.score();
+        assert_eq!(first + second, 4.0);
+        let table = result.to_string();
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.ends_with(&format!("shallow {} - {} d3", first, second)));
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let contestants = [Contestant::parse("depth=2,random_margin=2").unwrap(), Contestant::parse("depth=2,random_margin=2,seed=1").unwrap()];
+        assert_eq!(contestants[0].with_seed(0), contestants[0]);
+        assert_eq!(contestants[1].with_seed(3).seed, (3 << 32) + 1);
+        // the same seed plays the same games
+        let records = |seed: u64| play_match(&contestants.clone().map(|contestant| contestant.with_seed(seed)), 2, &GameSettings::default(), |_| {}).unwrap().games.into_iter().map(|game| game.record).collect::<Vec<_>>();
+        assert_eq!(records(7), records(7));
+        assert!((1..8).any(|seed| records(seed) != records(0)));
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 1e-3 && (lower + 2.944).abs() < 1e-3);
+        assert_eq!(sprt.llr([10, 0, 0]), 0.0);
+        // scoring 60% is far more likely if it's 10 Elo stronger than if it's equal
+        assert!(sprt.llr([60, 0, 40]) > 0.0);
+        assert!(sprt.llr([40, 20, 40]) < 0.0);
+        assert_eq!(sprt.decision([600, 0, 400]), Some(true));
+        assert_eq!(sprt.decision([4000, 2000, 4000]), Some(false));
+        assert_eq!(sprt.decision([6, 0, 4]), None);
+
+        // a depth 4 engine is a lot stronger than a depth 1 one
+        let contestants = [Contestant::parse("depth=4,random_margin=1").unwrap(), Contestant::parse("depth=1,random_margin=1").unwrap()];
+        let sprt = Sprt { elo0: 0.0, elo1: 200.0, ..Sprt::default() };
+        let (result, decision) = play_sprt(&contestants, &sprt, 100, &GameSettings::default(), |_| {}).unwrap();
+        assert_eq!(decision, Some(true));
+        assert!(result.games.len() < 100);
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(elo_difference([5, 0, 0]), None);
+        let even = elo_difference([10, 10, 10]).unwrap();
+        assert!(even.elo.abs() < 1e-9 && (even.below - even.above).abs() < 1e-9);
+        // 75% is about 191 Elo
+        let better = elo_difference([60, 30, 10]).unwrap();
+        assert!((better.elo - 190.8).abs() < 0.1);
+        // more games, a narrower interval
+        assert!(elo_difference([600, 300, 100]).unwrap().below < better.below);
+
+        let game = |white: &str, black: &str, points: f64| GameResult { white: white.to_string(), black: black.to_string(), points, record: "2-5 9".to_string() };
+        let results = vec![game("a", "b", 1.0), game("b", "a", 0.0), game("b", "c", 1.0), game("c", "b", 0.5), game("a", "c", 1.0)];
+        let path = std::env::temp_dir().join(format!("mancala-results-{}.txt", std::process::id()));
+        save_results(&path, &results[..2]).unwrap();
+        save_results(&path, &results[2..]).unwrap();
+        let loaded = load_results(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, results);
+        let ratings = ratings(&results);
+        assert_eq!(ratings.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert!(ratings.iter().map(|(_, rating)| rating).sum::<f64>().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tournament() {
+        let path = std::env::temp_dir().join(format!("mancala-tournament-{}.toml", std::process::id()));
+        fs::write(&path, "games = 2\n[[engine]]\ndepth = 1\n[[engine]]\ndepth = 2\n[[engine]]\nname = \"three\"\ndepth = 3\n").unwrap();
+        let config = TournamentConfig::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut played = 0;
+        let tournament = play_tournament(&config, &GameSettings::default(), 2, |_, _| { played += 1; }).unwrap();
+        assert_eq!((played, tournament.pairings.len()), (6, 3));
+        assert_eq!(tournament.totals().iter().sum::<f64>(), 6.0);
+        // the same games as a match between the same two
+        let single = play_match(&[config.engines[0].clone(), config.engines[2].clone()], 2, &GameSettings::default(), |_| {}).unwrap();
+        assert_eq!(tournament.pairings[1].result, single);
+        let table = tournament.to_string();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.contains("three"));
+    }
+}
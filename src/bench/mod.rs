@@ -0,0 +1,97 @@
+use super::engine::SearchLimits;
+use super::game::{self, Game};
+use super::mancala::{Move, RuleSet, Score};
+use super::minimax::{EngineOptions, Searcher};
+use super::rng;
+use std::fmt;
+use std::time::Duration;
+
+// the benchmark's positions, as move records from the start of a standard game, each with the
+// depth it's searched to: the opening, a few middlegames and an endgame that gets solved.
+// changing these changes every signature, so they stay put.
+pub const POSITIONS: [(&str, usize); 6] = [
+    ("", 10),
+    ("2-5 8-7 4", 10),
+    ("0 12 5 8-12-11 3 12-7-12-10", 12),
+    ("4 9-8-10 1-5 7-9 3 10-12 0-2 8-12-10 5 8 4 11", 13),
+    ("2-5 7 4 9 5-1-5-3 12 5-0-5-2-5-3-5-1 8 1 7", 13),
+    ("0 12 5 8-12-11 3 12-7-12-10 4 11-10 5 12 0 7 2 11 1 8 5-3", 12),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchLine {
+    pub record: &'static str,
+    pub depth: usize,
+    pub best_move: Option<Move>,
+    // White's point of view
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bench {
+    pub lines: Vec<BenchLine>,
+    pub nodes: u64,
+    pub time: Duration,
+    // a hash of every search's move, score and node count: two engine builds that search
+    // exactly the same way have the same signature, whatever their speed
+    pub signature: u64,
+}
+
+impl Bench {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.time.as_secs_f64().max(1e-9)
+    }
+}
+
+// searches every position with a fresh searcher, to `depth` if given instead of the built-in
+// depths. the options should use one thread, or the node counts (and the signature) change
+// from run to run.
+pub fn run(options: EngineOptions, depth: Option<usize>) -> Bench {
+    let mut lines = Vec::new();
+    let mut signature = 0;
+    for (record, built_in) in POSITIONS {
+        let game = Game::from_record(RuleSet::default(), record).expect("the benchmark positions are legal");
+        let depth = depth.unwrap_or(built_in);
+        let result = Searcher::new(options).search(game.node(), SearchLimits::depth(depth));
+        for value in result.best_move.iter().flatten().map(|&pocket| pocket as u64).chain([result.score as u64, result.nodes]) {
+            signature = rng::splitmix64(signature ^ value);
+        }
+        lines.push(BenchLine { record, depth, best_move: result.best_move, score: result.score, nodes: result.nodes, time: result.time });
+    }
+    Bench {
+        nodes: lines.iter().map(|line| line.nodes).sum(),
+        time: lines.iter().map(|line| line.time).sum(),
+        lines,
+        signature,
+    }
+}
+
+impl fmt::Display for Bench {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, line) in self.lines.iter().enumerate() {
+            let best = line.best_move.as_ref().map_or("-".to_string(), game::format_move);
+            writeln!(f, "{:2}. depth {:2} best {:10} score {:+3} {:10} nodes {:8.1?}", index + 1, line.depth, best, line.score, line.nodes, line.time)?;
+        }
+        writeln!(f, "Total: {} nodes in {:.1?} ({:.0} nodes/s)", self.nodes, self.time, self.nodes_per_second())?;
+        writeln!(f, "Signature: {:016x}", self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench() {
+        let bench = run(EngineOptions::default(), Some(3));
+        assert_eq!(bench.lines.len(), POSITIONS.len());
+        assert!(bench.lines.iter().all(|line| line.depth == 3 && line.best_move.is_some()));
+        assert_eq!(bench.nodes, bench.lines.iter().map(|line| line.nodes).sum::<u64>());
+        // the same search, the same signature
+        assert_eq!(run(EngineOptions::default(), Some(3)).signature, bench.signature);
+        assert_ne!(run(EngineOptions::default(), Some(2)).signature, bench.signature);
+        assert!(bench.to_string().contains(&format!("{:016x}", bench.signature)));
+    }
+}
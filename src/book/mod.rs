@@ -0,0 +1,253 @@
+use super::game::Game;
+use super::mancala::{Move, Node};
+use super::rng::Rng;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"MKBK";
+const VERSION: u8 = 1;
+
+// a few plies of well-searched moves from the start of a default Kalah game
+const STARTER: &[u8] = include_bytes!("../../books/kalah.bin");
+
+// which games and moves go into a book built from game records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildOptions {
+    // full moves from the start of each game
+    pub max_ply: usize,
+    // moves played in fewer games than this are left out
+    pub min_games: u32,
+    // only learn the winner's moves (drawn games are skipped)
+    pub winners_only: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            max_ply: 12,
+            min_games: 2,
+            winners_only: false,
+        }
+    }
+}
+
+// opening moves, keyed by Zobrist hash (Node::hash). each position can have several moves,
+// weighted by how much the book likes them. the hash doesn't cover the rules, so a book only
+// makes sense for the rules it was made for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Book {
+    entries: HashMap<u64, Vec<(Move, u32)>>,
+}
+
+impl Book {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the book that ships with the crate, for RuleSet::default()
+    pub fn starter() -> Self {
+        Self::from_bytes(STARTER).expect("the starter book is valid")
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // adds a move for a position, or adds to its weight if it's already there
+    pub fn add(&mut self, node: &Node, mv: Move, weight: u32) {
+        let moves = self.entries.entry(node.hash()).or_default();
+        match moves.iter_mut().find(|(known, _)| *known == mv) {
+            Some((_, total)) => { *total = total.saturating_add(weight); }
+            None => { moves.push((mv, weight)); }
+        }
+    }
+
+    // the book's moves for a position that are legal there (a hash collision could make
+    // another position's moves show up), with their weights
+    pub fn moves(&self, node: &Node) -> Vec<(Move, u32)> {
+        let Some(moves) = self.entries.get(&node.hash()) else { return Vec::new(); };
+        let legal = node.legal_moves();
+        moves.iter().filter(|(mv, weight)| *weight > 0 && legal.contains(mv)).cloned().collect()
+    }
+
+    // the book's favourite move (the first one added among equals)
+    pub fn probe(&self, node: &Node) -> Option<Move> {
+        let moves = self.moves(node);
+        let best = moves.iter().map(|(_, weight)| *weight).max()?;
+        moves.into_iter().find(|(_, weight)| *weight == best).map(|(mv, _)| mv)
+    }
+
+    // a move picked at random in proportion to the weights, for variety
+    pub fn pick(&self, node: &Node, rng: &mut Rng) -> Option<Move> {
+        let moves = self.moves(node);
+        let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut target = rng.next_u64() % total;
+        for (mv, weight) in moves {
+            if target < weight as u64 {
+                return Some(mv);
+            }
+            target -= weight as u64;
+        }
+        None
+    }
+
+    // a book from finished games (unfinished ones are ignored). each move is weighted by how
+    // well it did for the player who made it: the percentage of points scored with it, counting
+    // a draw as half a win, so a move that never won gets no weight at all.
+    pub fn from_games(games: &[Game], options: BuildOptions) -> Self {
+        // (games, half points) per position and move
+        let mut stats: HashMap<u64, Vec<(Move, u32, u32)>> = HashMap::new();
+        for game in games {
+            let Some(outcome) = game.outcome() else { continue; };
+            if options.winners_only && outcome.winner.is_none() {
+                continue;
+            }
+            for (node, mv) in game.history().iter().zip(game.moves()).take(options.max_ply) {
+                let half_points = match &outcome.winner {
+                    Some(winner) if winner == node.get_turn() => 2,
+                    Some(_) => 0,
+                    None => 1
+                };
+                if options.winners_only && half_points == 0 {
+                    continue;
+                }
+                let moves = stats.entry(node.hash()).or_default();
+                match moves.iter_mut().find(|(known, _, _)| known == mv) {
+                    Some((_, played, points)) => {
+                        *played += 1;
+                        *points += half_points;
+                    }
+                    None => { moves.push((mv.clone(), 1, half_points)); }
+                }
+            }
+        }
+        let mut book = Book::new();
+        for (key, moves) in stats {
+            let moves: Vec<_> = moves.into_iter()
+                .filter(|(_, played, points)| *played >= options.min_games && *points > 0)
+                .map(|(mv, played, points)| (mv, (50 * points).div_ceil(played)))
+                .collect();
+            if !moves.is_empty() {
+                book.entries.insert(key, moves);
+            }
+        }
+        book
+    }
+
+    // magic, version, entry count (u32), then per move: key (u64), weight (u32),
+    // sub-move count (u8) and the pockets (u8 each). little-endian, sorted by key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut keys: Vec<_> = self.entries.keys().copied().collect();
+        keys.sort_unstable();
+        let moves: Vec<_> = keys.iter().flat_map(|key| self.entries[key].iter().map(move |entry| (key, entry))).collect();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(moves.len() as u32).to_le_bytes());
+        for (key, (mv, weight)) in moves {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&weight.to_le_bytes());
+            bytes.push(mv.len() as u8);
+            bytes.extend(mv.iter().map(|&pocket| pocket as u8));
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a valid opening book");
+        if bytes.len() < 9 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(invalid());
+        }
+        let count = u32::from_le_bytes(bytes[5..9].try_into().unwrap());
+        let mut rest = &bytes[9..];
+        let mut book = Book::new();
+        for _ in 0..count {
+            if rest.len() < 13 {
+                return Err(invalid());
+            }
+            let key = u64::from_le_bytes(rest[..8].try_into().unwrap());
+            let weight = u32::from_le_bytes(rest[8..12].try_into().unwrap());
+            let length = rest[12] as usize;
+            let mv = rest.get(13..13 + length).ok_or_else(invalid)?.iter().map(|&pocket| pocket as usize).collect();
+            book.entries.entry(key).or_default().push((mv, weight));
+            rest = &rest[13 + length..];
+        }
+        Ok(book)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mancala::{Player, RuleSet};
+
+    #[test]
+    fn test_book() {
+        let node = Node::default();
+        let mut book = Book::new();
+        assert_eq!(book.probe(&node), None);
+        book.add(&node, vec![2, 5], 3);
+        book.add(&node, vec![4], 2);
+        book.add(&node, vec![4], 2);
+        // not legal here
+        book.add(&node, vec![2], 9);
+        assert_eq!(book.probe(&node), Some(vec![4]));
+        assert_eq!(book.moves(&node).len(), 2);
+        let mut rng = Rng::new(1);
+        assert!(book.pick(&node, &mut rng).is_some_and(|mv| mv == vec![4] || mv == vec![2, 5]));
+
+        let copy = Book::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(copy, book);
+        assert!(Book::from_bytes(&book.to_bytes()[..20]).is_err());
+    }
+
+    #[test]
+    fn test_from_games() {
+        let rules = RuleSet { move_limit: Some(2), ..RuleSet::default() };
+        // all end on the move limit after two moves, with Black ahead (the stones on each side count)
+        let games: Vec<_> = ["2-5 9", "2-5 10", "2-5 9", "4 7"].iter().map(|record| Game::from_record(rules, record).unwrap()).collect();
+        assert!(games.iter().all(|game| game.outcome().unwrap().winner == Some(Player::Black)));
+
+        let book = Book::from_games(&games, BuildOptions::default());
+        let node = Node::new(rules);
+        // White lost every time
+        assert!(book.moves(&node).is_empty());
+        let mut after = node.clone();
+        after.full_move(&vec![2, 5]).unwrap();
+        // "10" was only played once
+        assert_eq!(book.moves(&after), vec![(vec![9], 100)]);
+        assert_eq!(book.len(), 1);
+
+        let options = BuildOptions { min_games: 1, ..BuildOptions::default() };
+        assert_eq!(Book::from_games(&games, options).moves(&after).len(), 2);
+        let options = BuildOptions { max_ply: 1, min_games: 1, ..BuildOptions::default() };
+        assert!(Book::from_games(&games, options).is_empty());
+    }
+
+    #[test]
+    fn test_starter() {
+        let book = Book::starter();
+        let node = Node::default();
+        let mv = book.probe(&node).unwrap();
+        let mut after = node.clone();
+        after.full_move(&mv).unwrap();
+        assert!(book.probe(&after).is_some());
+    }
+}
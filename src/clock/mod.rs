@@ -0,0 +1,314 @@
+use super::engine::SearchLimits;
+use super::game::Game;
+use super::mancala::{Move, Player};
+use super::minimax::Searcher;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// how deep the quick move played for someone whose time is up looks
+const FAST_DEPTH: usize = 2;
+
+// time given back for each move
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Bonus {
+    #[default]
+    None,
+    // Fischer: added to the clock after every move
+    Increment(Duration),
+    // US (simple) delay: the clock only starts running this long into the move
+    Delay(Duration),
+    // Bronstein: the clock runs at once, and gets back what the move took up to this much
+    Bronstein(Duration),
+}
+
+impl Bonus {
+    // how much each move gets on top of the base time, whichever way it's given
+    pub fn per_move(&self) -> Duration {
+        match *self {
+            Bonus::None => Duration::ZERO,
+            Bonus::Increment(time) | Bonus::Delay(time) | Bonus::Bronstein(time) => time
+        }
+    }
+}
+
+// how much time each side gets for the whole game, and back for each move
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Bonus,
+}
+
+impl TimeControl {
+    // the base time in minutes ("5", "0.5") or minutes and seconds ("2:30"), then optionally seconds
+    // of increment ("5+3"), US delay ("5d3") or Bronstein delay ("5b3")
+    pub fn parse(text: &str) -> Option<Self> {
+        let (base, bonus) = match text.find(['+', 'd', 'b']) {
+            Some(split) => {
+                let seconds = Duration::try_from_secs_f64(text[split + 1..].parse().ok()?).ok()?;
+                let bonus = match &text[split..split + 1] {
+                    "+" => Bonus::Increment(seconds),
+                    "d" => Bonus::Delay(seconds),
+                    _ => Bonus::Bronstein(seconds)
+                };
+                (&text[..split], bonus)
+            }
+            None => (text, Bonus::None)
+        };
+        let base = match base.split_once(':') {
+            Some((minutes, seconds)) => Duration::from_secs(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok().filter(|&seconds| seconds < 60)?),
+            None => Duration::try_from_secs_f64(base.parse::<f64>().ok()? * 60.0).ok()?
+        };
+        Some(TimeControl { base, bonus }).filter(|control| !control.base.is_zero())
+    }
+}
+
+// what happens when someone's time runs out
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum OnTimeout {
+    // they lose on time
+    #[default]
+    Lose,
+    // casual: a person at the keyboard gets a quick engine move played for them instead, and so
+    // on for every move after it unless a bonus gives them time back. an engine still loses.
+    Move,
+}
+
+impl OnTimeout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lose" => Some(OnTimeout::Lose),
+            "move" => Some(OnTimeout::Move),
+            _ => None
+        }
+    }
+}
+
+// a clock as a saved game keeps it (see Game::save): how it was set and the time each side had
+// left, White's first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavedClock {
+    pub control: TimeControl,
+    pub on_timeout: OnTimeout,
+    pub remaining: [Duration; 2],
+}
+
+// both players' clocks. only the side to move's runs; `start` hands over to the other one.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    control: TimeControl,
+    on_timeout: OnTimeout,
+    remaining: [Duration; 2],
+    // whose clock is running, and since when
+    running: Option<(Player, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl, on_timeout: OnTimeout) -> Self {
+        Clock { control, on_timeout, remaining: [control.base; 2], running: None }
+    }
+
+    // a saved clock going again, stopped until someone's is started
+    pub fn from_saved(saved: &SavedClock) -> Self {
+        Clock { control: saved.control, on_timeout: saved.on_timeout, remaining: saved.remaining, running: None }
+    }
+
+    // what to save of it. the running clock's time so far is taken off, but nothing's given back
+    // for a move that isn't finished.
+    pub fn saved(&self) -> SavedClock {
+        SavedClock { control: self.control, on_timeout: self.on_timeout, remaining: [self.remaining(&Player::White), self.remaining(&Player::Black)] }
+    }
+
+    // starts `player`'s clock, stopping the other's. a clock that's already running keeps going,
+    // so this can be called before every sub-move of a turn.
+    pub fn start(&mut self, player: &Player) {
+        if self.running.as_ref().is_some_and(|(running, _)| running == player) {
+            return;
+        }
+        self.stop();
+        self.running = Some((player.clone(), Instant::now()));
+    }
+
+    // stops whichever clock is running
+    pub fn stop(&mut self) {
+        if let Some((player, since)) = self.running.take() {
+            self.charge(&player, since.elapsed());
+        }
+    }
+
+    // takes a finished move's time off `player`'s clock, with what the bonus gives back. (a
+    // clock that ran out stays out, unless running out only means a move is played for them.)
+    fn charge(&mut self, player: &Player, elapsed: Duration) {
+        let left = self.left(player, elapsed);
+        self.remaining[player.index()] = match self.control.bonus {
+            _ if left.is_zero() && self.on_timeout == OnTimeout::Lose => left,
+            Bonus::None | Bonus::Delay(_) => left,
+            Bonus::Increment(increment) => left + increment,
+            Bonus::Bronstein(delay) => left + elapsed.min(delay)
+        };
+    }
+
+    // what `player` has left `elapsed` into a move
+    fn left(&self, player: &Player, elapsed: Duration) -> Duration {
+        let counted = match self.control.bonus {
+            Bonus::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed
+        };
+        self.remaining[player.index()].saturating_sub(counted)
+    }
+
+    // what `player` has left, counting the time on a running clock
+    pub fn remaining(&self, player: &Player) -> Duration {
+        match &self.running {
+            Some((running, since)) if running == player => self.left(player, since.elapsed()),
+            _ => self.remaining[player.index()]
+        }
+    }
+
+    // whoever's clock is running, if it's run out. (a delay's seconds are still free on an
+    // empty clock.)
+    pub fn flagged(&self) -> Option<Player> {
+        let (player, since) = self.running.as_ref()?;
+        let delayed = matches!(self.control.bonus, Bonus::Delay(delay) if since.elapsed() < delay);
+        Some(player.clone()).filter(|player| !delayed && self.remaining(player).is_zero())
+    }
+
+    // `limits` for `player`'s search, with their time and what each move gets back on it
+    pub fn limits(&self, player: &Player, limits: SearchLimits) -> SearchLimits {
+        let increment = Some(self.control.bonus.per_move()).filter(|increment| !increment.is_zero());
+        SearchLimits { remaining: Some(self.remaining(player)), increment, ..limits }
+    }
+}
+
+// deals with the player to move having run out of time, as `on_timeout` says. returns the move
+// played for them if one was, and otherwise the game is over.
+pub fn time_up(game: &mut Game, on_timeout: OnTimeout, human: bool) -> Option<Move> {
+    let player = game.node().get_turn().clone();
+    if on_timeout == OnTimeout::Move && human {
+        // from wherever they'd got to in their move
+        if let Some(mv) = Searcher::default().search(game.node(), SearchLimits::depth(FAST_DEPTH)).best_move {
+            for &pocket in &mv {
+                game.sub_move(pocket).expect("the engine's move is legal");
+            }
+            return Some(mv);
+        }
+    }
+    game.time_out(player).expect("the game isn't over");
+    None
+}
+
+// minutes and seconds, with tenths under a minute
+fn format_time(time: Duration) -> String {
+    match time.as_secs() {
+        seconds if seconds >= 60 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        _ => format!("0:{:04.1}", time.as_secs_f64())
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let running = |player: &Player| if self.running.as_ref().is_some_and(|(running, _)| running == player) { "*" } else { " " };
+        write!(f, "White {}{}  Black {}{}", format_time(self.remaining(&Player::White)), running(&Player::White), format_time(self.remaining(&Player::Black)), running(&Player::Black))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::{RuleSet, TerminationReason};
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(TimeControl::parse("5"), Some(TimeControl { base: Duration::from_secs(300), bonus: Bonus::None }));
+        assert_eq!(TimeControl::parse("2:30"), Some(TimeControl { base: Duration::from_secs(150), bonus: Bonus::None }));
+        assert_eq!(TimeControl::parse("0.5").map(|control| control.base), Some(Duration::from_secs(30)));
+        assert!(TimeControl::parse("1:75").is_none() && TimeControl::parse("0").is_none() && TimeControl::parse("soon").is_none());
+
+        let mut clock = Clock::new(TimeControl::parse("1").unwrap(), OnTimeout::Lose);
+        clock.charge(&Player::White, Duration::from_millis(2500));
+        assert_eq!(clock.remaining(&Player::White), Duration::from_millis(57_500));
+        assert_eq!(clock.to_string(), "White 0:57.5   Black 1:00 ");
+        clock.start(&Player::Black);
+        assert!(clock.to_string().ends_with('*'));
+        clock.charge(&Player::Black, Duration::from_secs(90));
+        assert_eq!(clock.remaining(&Player::Black), Duration::ZERO);
+        let limits = clock.limits(&Player::White, SearchLimits::depth(6));
+        assert_eq!((limits.depth, limits.remaining), (Some(6), Some(Duration::from_millis(57_500))));
+        assert_eq!(limits.time_for_move(), Some(Duration::from_millis(2875)));
+    }
+
+    #[test]
+    fn test_bonus() {
+        let seconds = Duration::from_secs;
+        assert_eq!(TimeControl::parse("3+2"), Some(TimeControl { base: seconds(180), bonus: Bonus::Increment(seconds(2)) }));
+        assert_eq!(TimeControl::parse("1:30d5").map(|control| control.bonus), Some(Bonus::Delay(seconds(5))));
+        assert_eq!(TimeControl::parse("1b0.5").map(|control| control.bonus), Some(Bonus::Bronstein(Duration::from_millis(500))));
+        assert!(TimeControl::parse("1+").is_none() && TimeControl::parse("1x2").is_none());
+
+        // a 3 second move, then a 10 second one, under each kind of bonus
+        let left = |text: &str| {
+            let mut clock = Clock::new(TimeControl::parse(text).unwrap(), OnTimeout::Lose);
+            clock.charge(&Player::White, seconds(3));
+            let first = clock.remaining(&Player::White);
+            clock.charge(&Player::White, seconds(10));
+            (first, clock.remaining(&Player::White))
+        };
+        assert_eq!(left("1"), (seconds(57), seconds(47)));
+        assert_eq!(left("1+5"), (seconds(62), seconds(57)));
+        assert_eq!(left("1d5"), (seconds(60), seconds(55)));
+        assert_eq!(left("1b5"), (seconds(60), seconds(55)));
+        // a delay isn't a bank: running out is running out
+        let mut clock = Clock::new(TimeControl::parse("0:10+5").unwrap(), OnTimeout::Lose);
+        clock.charge(&Player::Black, seconds(11));
+        assert_eq!(clock.remaining(&Player::Black), Duration::ZERO);
+
+        // for casual games, the time given back gets someone going again after they ran out
+        let out = |text: &str, on_timeout: OnTimeout| {
+            let mut clock = Clock::new(TimeControl::parse(text).unwrap(), on_timeout);
+            clock.charge(&Player::White, seconds(3));
+            clock.remaining(&Player::White)
+        };
+        assert_eq!(out("0:01+5", OnTimeout::Lose), Duration::ZERO);
+        assert_eq!(out("0:01+5", OnTimeout::Move), seconds(5));
+        assert_eq!(out("0:01b5", OnTimeout::Move), seconds(3));
+        // and a delay's seconds don't count on the next move either
+        let mut clock = Clock::new(TimeControl::parse("0:01d5").unwrap(), OnTimeout::Move);
+        clock.remaining[0] = Duration::ZERO;
+        clock.start(&Player::White);
+        assert_eq!(clock.flagged(), None);
+
+        let limits = Clock::new(TimeControl::parse("1+2").unwrap(), OnTimeout::Lose).limits(&Player::White, SearchLimits::default());
+        assert_eq!(limits.increment, Some(seconds(2)));
+        assert_eq!(limits.time_for_move(), Some(seconds(5)));
+    }
+
+    #[test]
+    fn test_saved() {
+        let mut clock = Clock::new(TimeControl::parse("1+5").unwrap(), OnTimeout::Move);
+        clock.charge(&Player::White, Duration::from_secs(10));
+        clock.start(&Player::Black);
+        let saved = clock.saved();
+        assert_eq!(saved.remaining[0], Duration::from_secs(55));
+        assert!(saved.remaining[1] <= Duration::from_secs(60));
+        // stopped, until the side to move's clock is started
+        let resumed = Clock::from_saved(&saved);
+        assert_eq!(resumed.saved(), saved);
+        assert!(!resumed.to_string().contains('*'));
+    }
+
+    #[test]
+    fn test_time_up() {
+        let mut clock = Clock::new(TimeControl::parse("1").unwrap(), OnTimeout::Lose);
+        clock.start(&Player::White);
+        assert_eq!(clock.flagged(), None);
+        clock.remaining[0] = Duration::ZERO;
+        assert_eq!(clock.flagged(), Some(Player::White));
+
+        let mut game = Game::new(RuleSet::default());
+        assert!(time_up(&mut game, OnTimeout::Move, true).is_some());
+        assert_eq!((game.moves().len(), game.outcome()), (1, None));
+        // an engine loses on time either way
+        assert_eq!(time_up(&mut game, OnTimeout::Move, false), None);
+        assert_eq!(game.outcome().map(|outcome| (outcome.winner, outcome.reason)), Some((Some(Player::White), TerminationReason::Timeout)));
+    }
+}
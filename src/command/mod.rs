@@ -0,0 +1,134 @@
+use super::mancala::Pocket;
+use std::path::PathBuf;
+
+pub const HELP: &str = "Commands:
+  <pit>         sow from a pit, by its letter on your side (e.g. c) or its number (e.g. 2)
+  hint [eval]   a quick suggestion from the engine, with its score if asked
+  analyze [depth]
+                every legal move with its score and line, best first
+  undo          take back your last move and the AI's reply (or takeback)
+  redo          play them again
+  show          the board again
+  moves         the legal moves
+  fen           the position as text
+  verbose on|off
+                show each depth of the AI's search while it thinks, or not
+  setboard <fen>
+                start again from a position written by fen
+  save <file>   write the game to a file, to carry on later with --load
+  offer draw    offer to end the game drawn
+  resign        give up the game
+  quit          leave without finishing
+  help          this list";
+
+// what can be typed at the move prompt during a game
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Move(Pocket),
+    // by letter: the player's own pits from A, in the order they're sown
+    Pit(usize),
+    // with the score the engine gives it
+    Hint { eval: bool },
+    // to this depth, or the hint's if not given
+    Analyze(Option<usize>),
+    Undo,
+    Redo,
+    Show,
+    Moves,
+    Fen,
+    Verbose(bool),
+    SetBoard(String),
+    Save(PathBuf),
+    OfferDraw,
+    Resign,
+    Quit,
+    Help,
+}
+
+impl Command {
+    // the error is something to show the player
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut words = text.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err("Enter a move, or help for the commands.".to_string());
+        };
+        let rest: Vec<&str> = words.collect();
+        let command = match name.to_lowercase().as_str() {
+            "hint" => match rest.as_slice() {
+                [] => Command::Hint { eval: false },
+                ["eval"] => { return Ok(Command::Hint { eval: true }); }
+                _ => { return Err("hint only takes eval after it.".to_string()); }
+            },
+            "analyze" => match rest.as_slice() {
+                [] => Command::Analyze(None),
+                [depth] => match depth.parse() {
+                    Ok(depth) if depth > 0 => { return Ok(Command::Analyze(Some(depth))); }
+                    _ => { return Err("analyze takes a depth of at least 1.".to_string()); }
+                },
+                _ => { return Err("analyze only takes a depth after it.".to_string()); }
+            },
+            "undo" | "takeback" => Command::Undo,
+            "redo" => Command::Redo,
+            "show" => Command::Show,
+            "moves" => Command::Moves,
+            "fen" => Command::Fen,
+            "verbose" => match rest.as_slice() {
+                ["on"] => { return Ok(Command::Verbose(true)); }
+                ["off"] => { return Ok(Command::Verbose(false)); }
+                _ => { return Err("verbose takes on or off.".to_string()); }
+            },
+            "setboard" => match rest.as_slice() {
+                [] => { return Err("setboard needs a position, as fen writes it.".to_string()); }
+                _ => { return Ok(Command::SetBoard(rest.join(" "))); }
+            },
+            "save" => match rest.as_slice() {
+                [] => { return Err("save needs a file to write to.".to_string()); }
+                _ => { return Ok(Command::Save(PathBuf::from(rest.join(" ")))); }
+            },
+            "offer" => match rest.as_slice() {
+                ["draw"] => { return Ok(Command::OfferDraw); }
+                _ => { return Err("offer draw to offer a draw.".to_string()); }
+            },
+            "draw" => Command::OfferDraw,
+            "resign" => Command::Resign,
+            "quit" | "exit" => Command::Quit,
+            "help" | "?" => Command::Help,
+            letter if letter.len() == 1 && letter.as_bytes()[0].is_ascii_lowercase() => Command::Pit((letter.as_bytes()[0] - b'a') as usize),
+            _ => match name.parse() {
+                Ok(pocket) => Command::Move(pocket),
+                Err(_) => { return Err(format!("Unknown command {}: try help.", name)); }
+            }
+        };
+        match rest.is_empty() {
+            true => Ok(command),
+            false => Err(format!("{} doesn't take anything after it.", name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse(" 3\n"), Ok(Command::Move(3)));
+        assert_eq!((Command::parse("Undo"), Command::parse("takeback")), (Ok(Command::Undo), Ok(Command::Undo)));
+        assert_eq!((Command::parse("a"), Command::parse("F")), (Ok(Command::Pit(0)), Ok(Command::Pit(5))));
+        assert!(Command::parse("c 2").is_err());
+        assert_eq!(Command::parse("save my game.txt"), Ok(Command::Save(PathBuf::from("my game.txt"))));
+        assert!(Command::parse("save").is_err());
+        assert_eq!(Command::parse("setboard 1,2 w"), Ok(Command::SetBoard("1,2 w".to_string())));
+        assert_eq!(Command::parse("hint eval"), Ok(Command::Hint { eval: true }));
+        assert!(Command::parse("hint 3").is_err());
+        assert_eq!(Command::parse("analyze"), Ok(Command::Analyze(None)));
+        assert_eq!(Command::parse("analyze 8"), Ok(Command::Analyze(Some(8))));
+        assert!(Command::parse("analyze 0").is_err());
+        assert_eq!((Command::parse("offer draw"), Command::parse("draw")), (Ok(Command::OfferDraw), Ok(Command::OfferDraw)));
+        assert!(Command::parse("offer").is_err());
+        assert_eq!(Command::parse("verbose off"), Ok(Command::Verbose(false)));
+        assert!(Command::parse("verbose").is_err());
+        assert!(Command::parse("castle").is_err());
+        assert!(Command::parse("").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// defaults for the command line, from a TOML file of flag names (without the dashes) and values:
+//
+//   variant = "oware"
+//   depth = 12
+//   difficulty = "hard"
+//   no-book = true
+//
+// a flag given on the command line wins over the file. `true` turns a switch on and `false`
+// leaves it off. the file can only set flags, so anything the command line can do it can too.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    // each flag with its value, if it takes one
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: toml::Table = toml::from_str(text).map_err(|error| error.to_string())?;
+        let mut flags = Vec::new();
+        for (name, value) in table {
+            let flag = format!("--{}", name);
+            match value {
+                toml::Value::Boolean(true) => flags.push((flag, None)),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(text) => flags.push((flag, Some(text))),
+                toml::Value::Integer(number) => flags.push((flag, Some(number.to_string()))),
+                toml::Value::Float(number) => flags.push((flag, Some(number.to_string()))),
+                _ => { return Err(format!("{} has to be a string, a number or true/false", name)); }
+            }
+        }
+        Ok(Config { flags })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    // ~/.config/mancala-rs/config.toml, or the same under $XDG_CONFIG_HOME if that's set
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("mancala-rs").join("config.toml"))
+    }
+
+    // adds the flags that aren't on the command line already
+    pub fn apply(&self, args: &mut Vec<String>) {
+        for (flag, value) in &self.flags {
+            if args.contains(flag) {
+                continue;
+            }
+            args.push(flag.clone());
+            args.extend(value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let config = Config::parse("variant = \"oware\"\ndepth = 12\nno-book = true\nmust-feed = false\n").unwrap();
+        let mut args: Vec<String> = ["mancala-rs", "play", "--depth", "6"].iter().map(|arg| arg.to_string()).collect();
+        config.apply(&mut args);
+        assert_eq!(args, ["mancala-rs", "play", "--depth", "6", "--no-book", "--variant", "oware"]);
+        assert!(Config::parse("depth = [1, 2]").is_err());
+        assert!(Config::parse("depth = ").is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use super::mancala::{Move, Node, Player, Pocket, Score};
+use std::env;
+use std::process::{Command, Stdio};
+
+// the shape of the drawing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    // pits in brackets on two lines, as Node's Display has it
+    #[default]
+    Ascii,
+    // a board with a box-drawn border around each pit
+    Unicode,
+    // a line a side with the stores tucked in at the ends, for narrow terminals
+    Compact,
+}
+
+impl Style {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Style::Ascii),
+            "unicode" => Some(Style::Unicode),
+            "compact" => Some(Style::Compact),
+            _ => None
+        }
+    }
+}
+
+// how the board is drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    // whose side is along the bottom (their store on the right), the way they'd sit at the board
+    pub bottom: Player,
+    pub style: Style,
+    // ANSI colors: each side's pits in its own color, the stores picked out and the last move's
+    // pits shown inverted (sown from) or underlined (captured)
+    pub colors: bool,
+    // the pits the last move was sown from, marked with a * beside the count
+    pub last_move: Move,
+    // and the ones it captured from, marked with an x (see with_last_move)
+    pub captured: Vec<Pocket>,
+    // what to type for each pit, over the top row and under the bottom one
+    pub labels: bool,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View { bottom: Player::White, style: Style::default(), colors: false, last_move: Vec::new(), captured: Vec::new(), labels: false }
+    }
+}
+
+impl View {
+    // marks `mv`, played from `before`: where it was sown from and the pits a capture emptied
+    // (any that had stones before and none after, other than those sown from)
+    pub fn with_last_move(self, before: &Node, mv: &Move) -> Self {
+        let mut after = before.clone();
+        if after.full_move(mv).is_err() {
+            return self;
+        }
+        let pits = before.pits(&Player::White).chain(before.pits(&Player::Black));
+        let captured = pits.filter(|pocket| !mv.contains(pocket) && before.get_board()[*pocket] > 0 && after.get_board()[*pocket] == 0).collect();
+        View { last_move: mv.clone(), captured, ..self }
+    }
+
+    // the character beside a pit's count: * if the last move was sown from it, x if it captured from it
+    fn mark(&self, pocket: Pocket) -> char {
+        match (self.last_move.contains(&pocket), self.captured.contains(&pocket)) {
+            (true, _) => '*',
+            (false, true) => 'x',
+            (false, false) => ' '
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const STORE: &str = "\x1b[1;33m";
+const INVERT: &str = "\x1b[7m";
+const UNDERLINE: &str = "\x1b[4m";
+
+fn side_color(player: &Player) -> &'static str {
+    match player {
+        Player::White => "\x1b[36m",
+        Player::Black => "\x1b[31m"
+    }
+}
+
+fn paint(view: &View, text: String, color: &str) -> String {
+    match view.colors {
+        true => format!("{}{}{}", color, text, RESET),
+        false => text
+    }
+}
+
+// `player`'s pits in the order they're shown: the top player's run right to left, in the
+// direction they're sown
+pub fn pit_order(node: &Node, view: &View, player: &Player) -> Vec<Pocket> {
+    let mut pockets: Vec<Pocket> = node.pits(player).collect();
+    if *player != view.bottom {
+        pockets.reverse();
+    }
+    pockets
+}
+
+// each of `player`'s pits drawn by `draw` from its count and mark, in the order they're shown
+fn pits(node: &Node, view: &View, player: &Player, draw: impl Fn(Score, char) -> String) -> Vec<String> {
+    pit_order(node, view, player).into_iter().map(|pocket| {
+        let pit = paint(view, draw(node.get_board()[pocket], view.mark(pocket)), side_color(player));
+        match (view.colors, view.mark(pocket)) {
+            (true, '*') => format!("{}{}", INVERT, pit),
+            (true, 'x') => format!("{}{}", UNDERLINE, pit),
+            _ => pit
+        }
+    }).collect()
+}
+
+// a pit's letter on its owner's side: A for the first one they sow from, then on in the
+// direction they sow (see Command::Pit)
+pub fn pit_label(node: &Node, pocket: Pocket) -> String {
+    let owner = if node.pits(&Player::White).contains(&pocket) { Player::White } else { Player::Black };
+    ((b'A' + (pocket - node.pits(&owner).start) as u8) as char).to_string()
+}
+
+// a move played from `node` by its pits' letters, written like game::format_move
+pub fn move_label(node: &Node, mv: &Move) -> String {
+    mv.iter().map(|&pocket| pit_label(node, pocket)).collect::<Vec<_>>().join("-")
+}
+
+// what to type for each of `player`'s pits, centred under (or over) the pits drawn on a
+// line starting with `prefix` and separated by `separator`
+fn labels(node: &Node, view: &View, player: &Player, prefix: &str, cells: &[String], separator: &str) -> String {
+    let labels: Vec<String> = pit_order(node, view, player).into_iter().zip(cells).map(|(pocket, cell)| {
+        let width = visible_width(cell);
+        let label = pit_label(node, pocket);
+        let left = width.saturating_sub(label.len()).div_ceil(2);
+        format!("{}{:<2$}", " ".repeat(left), label, width - left)
+    }).collect();
+    let line = format!("{}{}", " ".repeat(visible_width(prefix)), labels.join(&" ".repeat(visible_width(separator))));
+    line.trim_end().to_string()
+}
+
+// the board as text. the ascii style from White's side is the same as Node's Display.
+pub fn render(node: &Node, view: &View) -> String {
+    let top = view.bottom.toggled();
+    let bottom = &view.bottom;
+    // the lines of the board, with the pits along the top and bottom and how they're laid out
+    let (mut lines, (top_prefix, top_pits), (bottom_prefix, bottom_pits), separator) = match view.style {
+        Style::Ascii => {
+            let store = |player: &Player| paint(view, format!("[ {} ]", node.score(player)), STORE);
+            let draw = |stones: Score, mark: char| format!("({}{} )", mark, stones);
+            let (board_top, board_bottom) = (pits(node, view, &top, draw), pits(node, view, bottom, draw));
+            let (top_prefix, bottom_prefix) = (format!("{}  ", store(&top)), "       ".to_string());
+            let lines = vec![
+                format!("{}{}", top_prefix, board_top.join("  ")),
+                String::new(),
+                format!("{}{}  {}", bottom_prefix, board_bottom.join("  "), store(bottom)),
+            ];
+            (lines, (top_prefix, board_top), (bottom_prefix, board_bottom), "  ")
+        }
+        Style::Unicode => {
+            // the stores at either end, as tall as both rows of pits
+            let cell = |stones: Score, mark: char| format!("{:>3}{}", stones, mark);
+            let store = |player: &Player| paint(view, cell(node.score(player), ' '), STORE);
+            let count = node.pits(&top).len();
+            let line = |left: &str, middle: &str, right: &str| format!("{}{}{}", left, vec!["────"; count + 2].join(middle), right);
+            let blank = "    ";
+            let inner = vec!["────"; count].join("┼");
+            let (board_top, board_bottom) = (pits(node, view, &top, cell), pits(node, view, bottom, cell));
+            let prefix = format!("│{}│", blank);
+            let lines = vec![
+                line("┌", "┬", "┐"),
+                format!("{}{}│{}│", prefix, board_top.join("│"), blank),
+                format!("│{}├{}┤{}│", store(&top), inner, store(bottom)),
+                format!("{}{}│{}│", prefix, board_bottom.join("│"), blank),
+                line("└", "┴", "┘"),
+            ];
+            (lines, (prefix.clone(), board_top), (prefix, board_bottom), "│")
+        }
+        Style::Compact => {
+            let store = |player: &Player| paint(view, node.score(player).to_string(), STORE);
+            let draw = |stones: Score, mark: char| format!("{:>2}{}", stones, mark);
+            let (board_top, board_bottom) = (pits(node, view, &top, draw), pits(node, view, bottom, draw));
+            let top_prefix = format!("{}{}|", " ".repeat(3usize.saturating_sub(node.score(&top).to_string().len())), store(&top));
+            let bottom_prefix = "   |".to_string();
+            let lines = vec![
+                format!("{}{}", top_prefix, board_top.concat()),
+                format!("{}{}|{}", bottom_prefix, board_bottom.concat(), store(bottom)),
+            ];
+            (lines, (top_prefix, board_top), (bottom_prefix, board_bottom), "")
+        }
+    };
+    if view.labels {
+        lines.insert(0, labels(node, view, &top, &top_prefix, &top_pits, separator));
+        lines.push(labels(node, view, bottom, &bottom_prefix, &bottom_pits, separator));
+    }
+    format!("{}\n{} to move", lines.join("\n"), node.get_turn())
+}
+
+// how many columns a line takes up on the terminal, leaving out color escapes
+pub fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for c in line.chars() {
+        match (escape, c) {
+            (false, '\x1b') => { escape = true; }
+            (false, _) => { width += 1; }
+            (true, 'm') => { escape = false; }
+            (true, _) => {}
+        }
+    }
+    width
+}
+
+// the board in `view`'s style, or the compact one if that wouldn't fit in `columns`
+pub fn render_within(node: &Node, view: &View, columns: usize) -> String {
+    let text = render(node, view);
+    match text.lines().any(|line| visible_width(line) > columns) {
+        true => render(node, &View { style: Style::Compact, ..view.clone() }),
+        false => text
+    }
+}
+
+// the width of the terminal: $COLUMNS if the shell exports it, or what stty reports for the
+// terminal on stdin. None when there isn't one.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()) {
+        return Some(columns);
+    }
+    let output = Command::new("stty").arg("size").stdin(Stdio::inherit()).stderr(Stdio::null()).output().ok()?;
+    String::from_utf8(output.stdout).ok()?.split_whitespace().nth(1)?.parse().ok()
+}
+
+// cells in the eval bar
+const BAR_WIDTH: usize = 10;
+
+// White's chances as a bar, filled from White's end, with the score beside it:
+// "W ███████░░░ B  +3". both are White's point of view, as in SearchResult.
+pub fn eval_bar(score: Score, win_prob: f64) -> String {
+    let filled = (win_prob.clamp(0.0, 1.0) * BAR_WIDTH as f64).round() as usize;
+    format!("W {}{} B  {:+}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled), score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::RuleSet;
+
+    #[test]
+    fn test_render() {
+        let node = Node::from_fen("1,2,3,4,5,6,7,8,9,10,11,12,13,14 b", RuleSet::default()).unwrap();
+        assert_eq!(render(&node, &View::default()), node.to_string());
+        let flipped = render(&node, &View { bottom: Player::Black, ..View::default() });
+        assert_eq!(flipped, "[ 7 ]  ( 6 )  ( 5 )  ( 4 )  ( 3 )  ( 2 )  ( 1 )\n\n       ( 8 )  ( 9 )  ( 10 )  ( 11 )  ( 12 )  ( 13 )  [ 14 ]\nBlack to move");
+        let colored = render(&node, &View { colors: true, last_move: vec![2], ..View::default() });
+        assert!(colored.starts_with("\x1b[1;33m[ 14 ]\x1b[0m"));
+        assert!(colored.contains("\x1b[7m\x1b[36m(*3 )\x1b[0m"));
+        let plain: String = colored.split('\x1b').map(|part| part.split_once('m').map_or(part, |(_, rest)| rest)).collect();
+        assert_eq!(plain, node.to_string().replace("( 3 )", "(*3 )"));
+        let unicode = render(&Node::new(RuleSet::default()), &View { style: Style::Unicode, ..View::default() });
+        assert_eq!(unicode, "\
+┌────┬────┬────┬────┬────┬────┬────┬────┐
+│    │  4 │  4 │  4 │  4 │  4 │  4 │    │
+│  0 ├────┼────┼────┼────┼────┼────┤  0 │
+│    │  4 │  4 │  4 │  4 │  4 │  4 │    │
+└────┴────┴────┴────┴────┴────┴────┴────┘
+White to move");
+        let compact = render(&node, &View { style: Style::Compact, ..View::default() });
+        assert_eq!(compact, " 14|13 12 11 10  9  8 \n   | 1  2  3  4  5  6 |7\nBlack to move");
+        let labeled = render(&node, &View { bottom: Player::Black, labels: true, ..View::default() });
+        assert!(labeled.starts_with("         F      E      D      C      B      A\n"));
+        assert!(labeled.contains("[ 14 ]\n         A      B       C       D       E       F\n"));
+        assert_eq!(move_label(&node, &vec![9, 12]), "C-F");
+        assert_eq!(render_within(&node, &View::default(), 80), node.to_string());
+        assert_eq!(render_within(&node, &View { colors: true, ..View::default() }, 30).lines().count(), 3);
+        // White sows from pit 0 into the empty pit 1 and captures pit 11
+        let board = [1, 0, 0, 0, 3, 5, 10, 1, 1, 1, 1, 10, 0, 12];
+        let before = Node::from_board(&board, Player::White, RuleSet::default()).unwrap();
+        let mut after = before.clone();
+        after.full_move(&vec![0]).unwrap();
+        let view = View::default().with_last_move(&before, &vec![0]);
+        assert_eq!((&view.last_move, &view.captured), (&vec![0], &vec![11]));
+        let marked = render(&after, &view);
+        assert!(marked.contains("(x0 )") && marked.contains("(*0 )"));
+        assert_eq!(eval_bar(3, 0.7), "W ███████░░░ B  +3");
+        assert_eq!(eval_bar(-20, 0.0), "W ░░░░░░░░░░ B  -20");
+    }
+}
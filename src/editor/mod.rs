@@ -0,0 +1,381 @@
+use super::config::Config;
+use super::tui;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+// line editing for the prompts of a game played in the terminal: left and right (or home and end)
+// move along the line, up and down go through the lines typed before, and backspace, delete,
+// ctrl-w and ctrl-u rub out a character, a word or everything before the cursor. the lines are
+// kept in a history file between runs. like the TUI it puts the terminal into raw mode through
+// stty; input that isn't a terminal (a file or a pipe), or a terminal stty can't handle, is read
+// a line at a time as it comes.
+
+// how many lines the history keeps, the oldest going first
+const HISTORY_LINES: usize = 500;
+
+// a key that does something to a line being edited, as a terminal in raw mode sends it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Left,
+    Right,
+    Up,
+    Down,
+    // also ctrl-a and ctrl-e
+    Home,
+    End,
+    Backspace,
+    Delete,
+    // ctrl-w: the word before the cursor
+    DeleteWord,
+    // ctrl-u: everything before the cursor
+    DeleteToStart,
+    Enter,
+    // ctrl-c, which raw mode delivers as a byte instead of a signal
+    Interrupt,
+    // ctrl-d: the end of the input on an empty line, and otherwise the same as delete
+    EndOfInput,
+    Other,
+}
+
+impl Key {
+    // the key at the start of `bytes` and how many bytes it used. None if there isn't a whole
+    // one yet.
+    pub fn parse(bytes: &[u8]) -> Option<(Key, usize)> {
+        match bytes {
+            [] | [0x1b] | [0x1b, b'[' | b'O'] => None,
+            [0x1b, b'[' | b'O', b'A', ..] => Some((Key::Up, 3)),
+            [0x1b, b'[' | b'O', b'B', ..] => Some((Key::Down, 3)),
+            [0x1b, b'[' | b'O', b'C', ..] => Some((Key::Right, 3)),
+            [0x1b, b'[' | b'O', b'D', ..] => Some((Key::Left, 3)),
+            [0x1b, b'[' | b'O', b'H', ..] => Some((Key::Home, 3)),
+            [0x1b, b'[' | b'O', b'F', ..] => Some((Key::End, 3)),
+            // the ones with a number: ESC [ 3 ~ for delete and so on
+            [0x1b, b'[', rest @ ..] if rest[0].is_ascii_digit() => {
+                let end = rest.iter().position(|byte| !byte.is_ascii_digit() && *byte != b';')?;
+                let key = match (&rest[..end], rest[end]) {
+                    (b"1" | b"7", b'~') => Key::Home,
+                    (b"4" | b"8", b'~') => Key::End,
+                    (b"3", b'~') => Key::Delete,
+                    _ => Key::Other
+                };
+                Some((key, 2 + end + 1))
+            }
+            [0x1b, b'[' | b'O', _, ..] => Some((Key::Other, 3)),
+            [0x1b, ..] => Some((Key::Other, 1)),
+            [b'\r' | b'\n', ..] => Some((Key::Enter, 1)),
+            [0x7f | 0x08, ..] => Some((Key::Backspace, 1)),
+            [0x01, ..] => Some((Key::Home, 1)),
+            [0x05, ..] => Some((Key::End, 1)),
+            [0x17, ..] => Some((Key::DeleteWord, 1)),
+            [0x15, ..] => Some((Key::DeleteToStart, 1)),
+            [0x03, ..] => Some((Key::Interrupt, 1)),
+            [0x04, ..] => Some((Key::EndOfInput, 1)),
+            [byte, ..] => {
+                // a character can take up to four bytes of UTF-8
+                let length = match byte.leading_ones() {
+                    0 => 1,
+                    ones @ 2..=4 => ones as usize,
+                    _ => return Some((Key::Other, 1))
+                };
+                if bytes.len() < length {
+                    return None;
+                }
+                match std::str::from_utf8(&bytes[..length]).ok().and_then(|text| text.chars().next()) {
+                    Some(character) if !character.is_control() => Some((Key::Char(character), length)),
+                    _ => Some((Key::Other, length))
+                }
+            }
+        }
+    }
+}
+
+// where a line is after a key
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Editing,
+    Entered(String),
+    // ctrl-c, or ctrl-d on an empty line
+    Ended,
+}
+
+// a line being edited
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Line {
+    text: Vec<char>,
+    cursor: usize,
+    // how far back in the history up has gone (1 for the latest line), and what had been typed
+    // before it did
+    recalled: Option<usize>,
+    draft: Vec<char>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    // in characters from the start
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn key(&mut self, key: Key, history: &[String]) -> Step {
+        match key {
+            Key::Char(character) => {
+                self.text.insert(self.cursor, character);
+                self.cursor += 1;
+            }
+            Key::Left => { self.cursor = self.cursor.saturating_sub(1); }
+            Key::Right => { self.cursor = (self.cursor + 1).min(self.text.len()); }
+            Key::Home => { self.cursor = 0; }
+            Key::End => { self.cursor = self.text.len(); }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            Key::EndOfInput if self.text.is_empty() => { return Step::Ended; }
+            Key::Delete | Key::EndOfInput if self.cursor < self.text.len() => { self.text.remove(self.cursor); }
+            Key::DeleteWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.text[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.text[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::DeleteToStart => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Up if self.recalled.unwrap_or(0) < history.len() => {
+                if self.recalled.is_none() {
+                    self.draft = std::mem::take(&mut self.text);
+                }
+                let back = self.recalled.map_or(1, |back| back + 1);
+                self.recalled = Some(back);
+                self.text = history[history.len() - back].chars().collect();
+                self.cursor = self.text.len();
+            }
+            Key::Down => {
+                match self.recalled {
+                    Some(1) => {
+                        self.recalled = None;
+                        self.text = std::mem::take(&mut self.draft);
+                    }
+                    Some(back) => {
+                        self.recalled = Some(back - 1);
+                        self.text = history[history.len() - back + 1].chars().collect();
+                    }
+                    None => { return Step::Editing; }
+                }
+                self.cursor = self.text.len();
+            }
+            Key::Enter => { return Step::Entered(self.text()); }
+            Key::Interrupt => { return Step::Ended; }
+            _ => {}
+        }
+        Step::Editing
+    }
+}
+
+// the lines entered before, oldest first, and the file they're kept in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    lines: Vec<String>,
+    file: Option<PathBuf>,
+}
+
+impl History {
+    // an empty one, kept nowhere
+    pub const fn new() -> Self {
+        History { lines: Vec::new(), file: None }
+    }
+
+    // history beside the config file (see Config::default_path)
+    pub fn default_path() -> Option<PathBuf> {
+        Some(Config::default_path()?.with_file_name("history"))
+    }
+
+    // the lines in the file at `path`, which lines entered from now on are added to. a file that
+    // hasn't been written yet is an empty history.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => { return Err(error); }
+        };
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        lines.drain(..lines.len().saturating_sub(HISTORY_LINES));
+        Ok(History { lines, file: Some(path.to_path_buf()) })
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    // a line that was entered. blank ones and repeats of the one before aren't kept.
+    pub fn add(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim();
+        if line.is_empty() || self.lines.last().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+        self.lines.push(line.to_string());
+        let full = self.lines.len() > HISTORY_LINES;
+        if full {
+            self.lines.remove(0);
+        }
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+        // (written out again whole only when the oldest line has to go)
+        match full {
+            true => fs::write(path, self.lines.iter().map(|line| format!("{}\n", line)).collect::<String>()),
+            false => fs::OpenOptions::new().create(true).append(true).open(path)?.write_all(format!("{}\n", line).as_bytes())
+        }
+    }
+}
+
+// the terminal in raw mode, without echo, until this is dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> io::Result<Self> {
+        let saved = tui::stty(&["-g"])?;
+        tui::stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tui::stty(&[&self.saved]);
+    }
+}
+
+// draws the line again after whatever was printed before it, with the cursor `before` characters
+// into it
+fn redraw(line: &Line, before: usize) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    if before > 0 {
+        write!(stdout, "\x1b[{}D", before)?;
+    }
+    write!(stdout, "{}\x1b[K", line.text())?;
+    let after = line.text.len() - line.cursor;
+    if after > 0 {
+        write!(stdout, "\x1b[{}D", after)?;
+    }
+    stdout.flush()
+}
+
+// a line typed in answer to whatever was just printed, edited with the keys above and added to
+// `history`. None once the input runs out, or after ctrl-c.
+pub fn read_line(history: &mut History) -> io::Result<Option<String>> {
+    let raw = match io::stdin().is_terminal() {
+        true => RawMode::enter().ok(),
+        false => None
+    };
+    let Some(_raw) = raw else {
+        let mut buffer = String::new();
+        if io::stdin().read_line(&mut buffer)? == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(buffer));
+    };
+    let mut line = Line::default();
+    let mut pending = Vec::new();
+    let mut buffer = [0; 64];
+    loop {
+        let read = io::stdin().read(&mut buffer)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        pending.extend_from_slice(&buffer[..read]);
+        while let Some((key, used)) = Key::parse(&pending) {
+            pending.drain(..used);
+            let before = line.cursor();
+            match line.key(key, history.lines()) {
+                Step::Editing => redraw(&line, before)?,
+                Step::Entered(text) => {
+                    print!("\r\n");
+                    // (a history that can't be written isn't worth stopping the game for)
+                    let _ = history.add(&text);
+                    return Ok(Some(text));
+                }
+                Step::Ended => {
+                    print!("\r\n");
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(Key::parse(b"\x1b[A"), Some((Key::Up, 3)));
+        assert_eq!(Key::parse(b"\x1b[3~x"), Some((Key::Delete, 4)));
+        assert_eq!(Key::parse(b"\x1b["), None);
+        assert_eq!(Key::parse("é".as_bytes()), Some((Key::Char('é'), 2)));
+        assert_eq!(Key::parse(&"é".as_bytes()[..1]), None);
+        assert_eq!(Key::parse(b"\x7f"), Some((Key::Backspace, 1)));
+        assert_eq!(Key::parse(b"\x17\x15"), Some((Key::DeleteWord, 1)));
+    }
+
+    #[test]
+    fn test_line() {
+        let history = vec!["hint".to_string(), "save my game".to_string()];
+        let mut line = Line::default();
+        let type_in = |line: &mut Line, keys: &[Key]| keys.iter().map(|&key| line.key(key, &history)).last();
+        let text = |text: &str| text.chars().map(Key::Char).collect::<Vec<_>>();
+        type_in(&mut line, &text("undo a"));
+        type_in(&mut line, &[Key::Left, Key::Left, Key::Backspace, Key::End, Key::Char('b')]);
+        assert_eq!((line.text(), line.cursor()), ("und ab".to_string(), 6));
+        type_in(&mut line, &[Key::DeleteWord]);
+        assert_eq!(line.text(), "und ");
+        type_in(&mut line, &[Key::Home, Key::Delete, Key::EndOfInput]);
+        assert_eq!(line.text(), "d ");
+        type_in(&mut line, &[Key::End, Key::DeleteToStart]);
+        assert_eq!(line.text(), "");
+        assert_eq!(type_in(&mut line, &[Key::EndOfInput]), Some(Step::Ended));
+
+        // up and down through the history, and back to what was being typed
+        type_in(&mut line, &text("sh"));
+        type_in(&mut line, &[Key::Up, Key::Up, Key::Up]);
+        assert_eq!(line.text(), "hint");
+        type_in(&mut line, &[Key::Down]);
+        assert_eq!(line.text(), "save my game");
+        assert_eq!(type_in(&mut line, &[Key::Down, Key::Char('o'), Key::Char('w'), Key::Enter]), Some(Step::Entered("show".to_string())));
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join(format!("mancala-rs-history-{}", std::process::id())).join("history");
+        let mut history = History::open(&path).unwrap();
+        assert!(history.lines().is_empty());
+        for line in ["hint", "hint", " ", "undo"] {
+            history.add(line).unwrap();
+        }
+        assert_eq!(history.lines(), ["hint", "undo"]);
+        assert_eq!(History::open(&path).unwrap(), history);
+        for number in 1..HISTORY_LINES {
+            history.add(&number.to_string()).unwrap();
+        }
+        let reopened = History::open(&path).unwrap();
+        assert_eq!((reopened.lines().len(), reopened.lines()[0].as_str()), (HISTORY_LINES, "undo"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use super::mancala::{self, CaptureRule, Node, Player, RuleSet, Score, SweepRule};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"MKDB";
+const VERSION: u8 = 2;
+// a slot that hasn't been solved yet (only while building)
+const UNKNOWN: i8 = i8::MIN;
+// values are stored as i8, and can't be bigger than the stones left
+pub const MAX_STONES: usize = 127;
+
+// the parts of the rules that change endgame values, as stored in a database file
+pub(crate) fn fingerprint(rules: &RuleSet) -> [u8; 8] {
+    let layout = &rules.layout;
+    let store = |store: Option<mancala::Pocket>| store.map_or(u8::MAX, |pocket| pocket as u8);
+    let flags = (rules.capture == CaptureRule::Oware) as u8
+        | (rules.chain_captures as u8) << 1
+        | ((rules.sweep == SweepRule::Emptier) as u8) << 2
+        | (rules.must_feed as u8) << 3;
+    [layout.size as u8, layout.pits as u8, layout.first_pit[0] as u8, layout.first_pit[1] as u8, store(layout.stores[0]), store(layout.stores[1]), flags, 0]
+}
+
+// whether the rules allow an endgame database. the value of a position can only depend on the
+// pits (not on what's already in the stores) and the positions with a given number of stones
+// in play can only be solved after the ones with fewer if the game can't go in circles, which
+// rules out store-less boards and avalanche sowing. the move limit is ignored.
+pub fn supports(rules: &RuleSet) -> bool {
+    let layout = &rules.layout;
+    layout.stores.iter().all(Option::is_some) && !rules.avalanche && layout.size < u8::MAX as usize
+}
+
+// exact values of every position with at most `max_stones` stones in the pits, for one rule set.
+// positions are indexed by the pit contents (White's pits, then Black's) and the side to move;
+// the value is what White will gain on Black from there to the end of the game with perfect play.
+// on a symmetric board (see Layout::is_symmetric) only White-to-move positions are kept: one
+// with Black to move is worth minus its mirror image.
+pub struct EndgameDb {
+    fingerprint: [u8; 8],
+    max_stones: usize,
+    pits: usize,
+    symmetric: bool,
+    // binomial[n][k] = n choose k
+    binomial: Vec<Vec<usize>>,
+    // index of the first position with that many stones
+    offsets: Vec<usize>,
+    // per position, White to move, then (unless symmetric) Black to move
+    values: Vec<i8>,
+}
+
+impl EndgameDb {
+    fn empty(rules: &RuleSet, max_stones: usize) -> Self {
+        let pits = 2 * rules.layout.pits;
+        let mut binomial = vec![vec![0; pits + 1]; max_stones + pits + 1];
+        for n in 0..binomial.len() {
+            binomial[n][0] = 1;
+            for k in 1..=pits.min(n) {
+                binomial[n][k] = binomial[n - 1][k - 1] + if k < n { binomial[n - 1][k] } else { 0 };
+            }
+        }
+        let mut db = EndgameDb {
+            fingerprint: fingerprint(rules),
+            max_stones,
+            pits,
+            symmetric: rules.layout.is_symmetric(),
+            binomial,
+            offsets: Vec::new(),
+            values: Vec::new(),
+        };
+        let mut total = 0;
+        for stones in 0..=max_stones {
+            db.offsets.push(total);
+            total += db.compositions(stones, pits);
+        }
+        db.values = vec![UNKNOWN; db.turns() * total];
+        db
+    }
+
+    // ways to spread `stones` over `pits` pits
+    fn compositions(&self, stones: usize, pits: usize) -> usize {
+        match pits {
+            0 => (stones == 0) as usize,
+            _ => self.binomial[stones + pits - 1][pits - 1]
+        }
+    }
+
+    // entries per position
+    fn turns(&self) -> usize {
+        match self.symmetric {
+            true => 1,
+            false => 2
+        }
+    }
+
+    // where a position goes in `values`, if it's in the database at all, and whether what's
+    // there is for its mirror image instead
+    fn index(&self, node: &Node) -> Option<(usize, bool)> {
+        if fingerprint(node.get_rules()) != self.fingerprint {
+            return None;
+        }
+        if self.symmetric && *node.get_turn() == Player::Black {
+            let (index, _) = self.index(&node.mirrored().expect("the layout is symmetric"))?;
+            return Some((index, true));
+        }
+        let board = node.get_board();
+        let pits: Vec<usize> = node.pits(&Player::White).chain(node.pits(&Player::Black)).map(|pocket| board[pocket] as usize).collect();
+        let stones: usize = pits.iter().sum();
+        if stones > self.max_stones {
+            return None;
+        }
+        // rank among the compositions of `stones`: count the ones that put fewer stones in an earlier pit
+        let mut rank = 0;
+        let mut left = stones;
+        for (i, &count) in pits.iter().enumerate().take(self.pits - 1) {
+            for smaller in 0..count {
+                rank += self.compositions(left - smaller, self.pits - i - 1);
+            }
+            left -= count;
+        }
+        Some((self.turns() * (self.offsets[stones] + rank) + node.get_turn().index(), false))
+    }
+
+    // solves every position with at most `max_stones` stones in play, fewest stones first.
+    // None if the rules don't allow a database (see `supports`).
+    pub fn build(rules: RuleSet, max_stones: usize) -> Option<Self> {
+        if !supports(&rules) || max_stones > MAX_STONES {
+            return None;
+        }
+        let mut db = Self::empty(&rules, max_stones);
+        let mut pits = vec![0; db.pits];
+        for stones in 0..=max_stones {
+            db.solve_all(&rules, &mut pits, 0, stones);
+        }
+        Some(db)
+    }
+
+    // goes through every way to put `left` stones into pits[first..]
+    fn solve_all(&mut self, rules: &RuleSet, pits: &mut [Score], first: usize, left: usize) {
+        if first == pits.len() - 1 {
+            pits[first] = left as Score;
+            let mut board = vec![0; rules.layout.size];
+            let half = pits.len() / 2;
+            for (offset, pocket) in (rules.layout.first_pit[0]..).take(half).enumerate() {
+                board[pocket] = pits[offset];
+            }
+            for (offset, pocket) in (rules.layout.first_pit[1]..).take(half).enumerate() {
+                board[pocket] = pits[half + offset];
+            }
+            // (Black-to-move positions of a symmetric board get solved as mirror images)
+            for turn in [Player::White, Player::Black].into_iter().take(self.turns()) {
+                let node = Node::from_board(&board, turn, *rules).expect("board fits the layout");
+                self.solve(&node);
+            }
+            return;
+        }
+        for count in 0..=left {
+            pits[first] = count as Score;
+            self.solve_all(rules, pits, first + 1, left - count);
+        }
+    }
+
+    // White's gain from here on. every child has at most as many stones in play, and one with
+    // the same number got there by moving stones towards their store without reaching it, so
+    // the recursion always ends.
+    fn solve(&mut self, node: &Node) -> Score {
+        let (index, mirrored) = self.index(node).expect("positions never gain stones in play");
+        let sign = if mirrored { -1 } else { 1 };
+        if self.values[index] != UNKNOWN {
+            return sign * self.values[index] as Score;
+        }
+        let children = node.children();
+        let value = if children.is_empty() {
+            node.final_score() - node.eval()
+        }
+        else {
+            let gains = children.iter().map(|(_, child)| child.eval() - node.eval() + self.solve(child)).collect::<Vec<_>>();
+            match node.get_turn() {
+                Player::White => *gains.iter().max().unwrap(),
+                Player::Black => *gains.iter().min().unwrap()
+            }
+        };
+        self.values[index] = (sign * value) as i8;
+        value
+    }
+
+    // the final score (White minus Black) of the game from here with perfect play,
+    // if the position is in the database
+    pub fn probe(&self, node: &Node) -> Option<Score> {
+        let (index, mirrored) = self.index(node)?;
+        let value = *self.values.get(index)?;
+        let sign = if mirrored { -1 } else { 1 };
+        (value != UNKNOWN).then(|| node.eval() + sign * value as Score)
+    }
+
+    pub fn max_stones(&self) -> usize {
+        self.max_stones
+    }
+
+    // magic, version, rules fingerprint, stone count, then one byte per entry
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(14 + self.values.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.fingerprint);
+        bytes.push(self.max_stones as u8);
+        bytes.extend(self.values.iter().map(|&value| value as u8));
+        fs::write(path, bytes)
+    }
+
+    // loads a database built for `rules`
+    pub fn load(path: &Path, rules: &RuleSet) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let bytes = fs::read(path)?;
+        if bytes.len() < 14 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(invalid("not an endgame database"));
+        }
+        if bytes[5..13] != fingerprint(rules) {
+            return Err(invalid("endgame database was built for different rules"));
+        }
+        let mut db = Self::empty(rules, bytes[13] as usize);
+        if bytes.len() - 14 != db.values.len() {
+            return Err(invalid("endgame database is truncated"));
+        }
+        db.values = bytes[14..].iter().map(|&value| value as i8).collect();
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plain minimax to the end of the game
+    fn solve(node: &Node) -> Score {
+        let children = node.children();
+        if children.is_empty() {
+            return node.final_score();
+        }
+        let scores = children.iter().map(|(_, child)| solve(child));
+        match node.get_turn() {
+            Player::White => scores.max().unwrap(),
+            Player::Black => scores.min().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_matches_search() {
+        let rules = RuleSet::default();
+        let db = EndgameDb::build(rules, 5).unwrap();
+        assert!(db.values.iter().all(|&value| value != UNKNOWN));
+        let boards = [
+            [0, 2, 0, 1, 0, 1, 20, 0, 0, 1, 0, 0, 0, 23],
+            [1, 0, 0, 0, 0, 1, 21, 0, 1, 0, 2, 0, 0, 22],
+            [0, 0, 0, 0, 0, 0, 24, 0, 3, 0, 0, 0, 1, 20],
+        ];
+        for board in boards {
+            for turn in [Player::White, Player::Black] {
+                let node = Node::from_board(&board, turn, rules).unwrap();
+                assert_eq!(db.probe(&node), Some(solve(&node)));
+            }
+        }
+        // without symmetry, both sides to move are kept
+        let adjacent = RuleSet { layout: mancala::Layout::adjacent_stores(), ..rules };
+        let adjacent_db = EndgameDb::build(adjacent, 4).unwrap();
+        assert_eq!(adjacent_db.values.len(), 2 * EndgameDb::build(rules, 4).unwrap().values.len());
+        for turn in [Player::White, Player::Black] {
+            let node = Node::from_board(&[0, 1, 0, 2, 0, 0, 20, 24, 0, 1, 0, 0, 0, 0], turn, adjacent).unwrap();
+            assert_eq!(adjacent_db.probe(&node), Some(solve(&node)));
+        }
+        let node = Node::default();
+        assert_eq!(db.probe(&node), None);
+        let oware = RuleSet { capture: CaptureRule::Oware, ..rules };
+        assert_eq!(db.probe(&Node::from_board(&boards[0], Player::White, oware).unwrap()), None);
+        assert!(EndgameDb::build(RuleSet { layout: mancala::Layout::storeless(), ..rules }, 5).is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let rules = RuleSet::default();
+        let db = EndgameDb::build(rules, 3).unwrap();
+        let path = std::env::temp_dir().join(format!("mancala-endgame-{}.db", std::process::id()));
+        db.save(&path).unwrap();
+        let loaded = EndgameDb::load(&path, &rules).unwrap();
+        assert_eq!(loaded.values, db.values);
+        assert!(EndgameDb::load(&path, &RuleSet { must_feed: true, ..rules }).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
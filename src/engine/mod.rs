@@ -0,0 +1,269 @@
+use super::mancala;
+use super::tree::SearchTree;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// how much the engine is allowed to think. a limit that isn't set doesn't constrain the search.
+// with no limits at all, alpha-beta keeps deepening until the game tree is exhausted;
+// other engines fall back to a default budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub movetime: Option<Duration>,
+    // positions searched (alpha-beta) or playouts (MCTS). unlike a time limit this doesn't depend on
+    // the machine: a single-threaded search limited by nodes and/or depth always plays the same move.
+    pub nodes: Option<u64>,
+    // time left on the engine's clock in a timed game. the search takes a share of it
+    // (see time_for_move).
+    #[serde(default)]
+    pub remaining: Option<Duration>,
+    // what the clock gives back for each move (an increment, or a delay's free time)
+    #[serde(default)]
+    pub increment: Option<Duration>,
+}
+
+// how many more moves the time left on a clock is spread over
+const MOVES_TO_GO: u32 = 20;
+
+impl SearchLimits {
+    pub fn depth(depth: usize) -> Self {
+        SearchLimits { depth: Some(depth), ..SearchLimits::default() }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        SearchLimits { movetime: Some(movetime), ..SearchLimits::default() }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        SearchLimits { nodes: Some(nodes), ..SearchLimits::default() }
+    }
+
+    // how long this move may think: movetime, or a share of the clock if that's shorter. the share
+    // is what's left spread over the moves to come plus what this move gets back, but never more
+    // than half of what's left.
+    pub fn time_for_move(&self) -> Option<Duration> {
+        let increment = self.increment.unwrap_or_default();
+        let share = self.remaining.map(|remaining| (remaining / MOVES_TO_GO + increment).min(remaining / 2));
+        match (self.movetime, share) {
+            (Some(movetime), Some(share)) => Some(movetime.min(share)),
+            (movetime, share) => movetime.or(share)
+        }
+    }
+}
+
+// one of the best moves at the root, with what the search expects after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PvLine {
+    // from White's point of view
+    pub score: mancala::Score,
+    // starting with the root move
+    pub pv: Vec<mancala::Move>,
+}
+
+// everything a search found out
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    // None only if the game is already over
+    pub best_move: Option<mancala::Move>,
+    // from White's point of view
+    pub score: mancala::Score,
+    // the line of full moves the search expects, starting with best_move
+    pub pv: Vec<mancala::Move>,
+    // the deepest completed iteration (for tree searches like MCTS, the deepest node in the tree)
+    pub depth: usize,
+    pub nodes: u64,
+    pub time: Duration,
+    // transposition table probes that returned a usable entry
+    pub tt_hits: u64,
+    // the score is proven: every line was followed to the end of the game
+    pub exact: bool,
+    // for a proven win or loss, how many full moves the game has left with perfect play: the
+    // winner ends it as soon as it can and the loser holds out as long as it can. None otherwise.
+    pub ends_in: Option<usize>,
+    // White's expected points (a draw counts half) from the score and the stones left, by a
+    // calibrated eval::WinModel. 1, 0.5 or 0 once the result is proven.
+    pub win_prob: f64,
+    // the best root moves, best first (more than one only for multi-PV searches).
+    // the first one is best_move with its score and pv, unless the engine picked another
+    // nearly-as-good move at random.
+    pub lines: Vec<PvLine>,
+}
+
+impl SearchResult {
+    // the opponent's reply the search expects to best_move, if the pv goes that far. the rest of
+    // the pv after it is how the search thinks the game goes on, and `score` what it's worth.
+    pub fn expected_reply(&self) -> Option<&mancala::Move> {
+        self.pv.get(1)
+    }
+}
+
+// when an engine should give up: its score has been at least `threshold` stones behind for
+// `moves` of its moves in a row, and its chances (SearchResult::win_prob) are below `max_chance`,
+// so there's no swindle left to hope for. a proven loss is given up at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResignPolicy {
+    pub threshold: mancala::Score,
+    pub moves: usize,
+    pub max_chance: f64,
+}
+
+impl Default for ResignPolicy {
+    fn default() -> Self {
+        ResignPolicy { threshold: 10, moves: 3, max_chance: 0.02 }
+    }
+}
+
+// keeps count of the hopeless moves in a row for one side of a game
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resigner {
+    pub policy: ResignPolicy,
+    streak: usize,
+}
+
+impl Resigner {
+    pub fn new(policy: ResignPolicy) -> Self {
+        Resigner { policy, streak: 0 }
+    }
+
+    // call with the result of every search for `side`'s moves
+    pub fn should_resign(&mut self, result: &SearchResult, side: &mancala::Player) -> bool {
+        let (score, chance) = match side {
+            mancala::Player::White => (result.score, result.win_prob),
+            mancala::Player::Black => (-result.score, 1.0 - result.win_prob)
+        };
+        // (a proven draw can score below 0 with contempt, but its chance is a half)
+        if result.exact && chance == 0.0 {
+            return true;
+        }
+        match score <= -self.policy.threshold && chance < self.policy.max_chance {
+            true => { self.streak += 1; }
+            false => { self.streak = 0; }
+        }
+        self.streak >= self.policy.moves
+    }
+
+    // starts counting again, e.g. after moves are taken back
+    pub fn reset(&mut self) {
+        self.streak = 0;
+    }
+}
+
+// whether an engine playing `side` takes a draw it's offered, given its search of the position and
+// its contempt (see minimax::EngineOptions::contempt). a draw is worth -contempt to it, so it takes
+// one when it expects less than that from playing on, or when that's all it can get anyway.
+pub fn accepts_draw(result: &SearchResult, side: &mancala::Player, contempt: mancala::Score) -> bool {
+    let score = match side {
+        mancala::Player::White => result.score,
+        mancala::Player::Black => -result.score
+    };
+    score < -contempt || (result.exact && score <= -contempt)
+}
+
+// lets another thread stop a running search (on user input, a protocol "stop", ...). the search
+// polls it and returns its best result so far. clones share the same flag, and once raised it
+// stays raised, so use a fresh token for the next search.
+#[derive(Debug, Clone, Default)]
+pub struct StopToken {
+    stopped: Arc<AtomicBool>,
+}
+
+impl StopToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+// progress reported while a search is running (after every completed depth, for alpha-beta)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchInfo {
+    pub depth: usize,
+    // from White's point of view
+    pub score: mancala::Score,
+    pub pv: Vec<mancala::Move>,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+// called with each SearchInfo. to get the updates on another thread, send them down a channel.
+pub type InfoCallback = Box<dyn FnMut(&SearchInfo) + Send>;
+
+// anything that can be asked for a move: the alpha-beta Searcher, MCTS, ...
+pub trait Engine {
+    fn search(&mut self, node: &mancala::Node, limits: SearchLimits) -> SearchResult;
+
+    // where to report progress during later searches (None to stop reporting)
+    fn set_info_callback(&mut self, callback: Option<InfoCallback>);
+
+    // the token later searches poll to see if they should stop
+    fn set_stop_token(&mut self, token: StopToken);
+
+    // the tree the last search explored, for engines that can record one
+    fn tree(&self) -> Option<&SearchTree> {
+        None
+    }
+
+    // moves of the game were taken back: forget whatever was carried over from searching the
+    // line that's gone, so the next search starts as if it had never been played
+    fn takeback(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minimax::{EngineOptions, Searcher};
+
+    fn result(score: mancala::Score, win_prob: f64, exact: bool) -> SearchResult {
+        SearchResult { best_move: None, score, pv: Vec::new(), depth: 1, nodes: 1, time: Duration::ZERO, tt_hits: 0, exact, ends_in: None, win_prob, lines: Vec::new() }
+    }
+
+    #[test]
+    fn test_resigner() {
+        let mut resigner = Resigner::new(ResignPolicy { threshold: 10, moves: 2, max_chance: 0.05 });
+        let black = mancala::Player::Black;
+        // hopeless twice in a row, with a break in between
+        assert!(!resigner.should_resign(&result(12, 0.99, false), &black));
+        assert!(!resigner.should_resign(&result(-12, 0.01, false), &black));
+        assert!(!resigner.should_resign(&result(12, 0.9, false), &black));
+        assert!(!resigner.should_resign(&result(12, 0.99, false), &black));
+        assert!(resigner.should_resign(&result(15, 0.999, false), &black));
+        // far behind, but with a chance
+        let mut hopeful = Resigner::new(ResignPolicy { moves: 1, ..ResignPolicy::default() });
+        assert!(!hopeful.should_resign(&result(-20, 0.1, false), &mancala::Player::White));
+        assert!(hopeful.should_resign(&result(-1, 0.0, true), &mancala::Player::White));
+    }
+
+    #[test]
+    fn test_no_resigning_draws() {
+        // White sows its last stone into its store and the game ends 11-11
+        let board = [0, 0, 0, 0, 0, 1, 10, 1, 0, 0, 0, 0, 0, 10];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let mut engine = Searcher::new(EngineOptions { contempt: 2, ..EngineOptions::default() });
+        let drawn = engine.search(&node, SearchLimits::depth(4));
+        assert!(drawn.exact && drawn.score < 0 && drawn.win_prob == 0.5);
+        let mut resigner = Resigner::new(ResignPolicy { moves: 1, ..ResignPolicy::default() });
+        assert!(!resigner.should_resign(&drawn, &mancala::Player::White));
+        assert!(!resigner.should_resign(&result(2, 0.5, true), &mancala::Player::Black));
+    }
+
+    #[test]
+    fn test_accepts_draw() {
+        let white = mancala::Player::White;
+        assert!(accepts_draw(&result(-2, 0.3, false), &white, 0));
+        assert!(!accepts_draw(&result(0, 0.5, false), &white, 0));
+        assert!(accepts_draw(&result(0, 0.5, true), &white, 0));
+        // Black is ahead here, and even more so with contempt against draws
+        assert!(!accepts_draw(&result(-2, 0.3, false), &mancala::Player::Black, 0));
+        assert!(!accepts_draw(&result(-2, 0.3, false), &white, 3));
+        assert!(accepts_draw(&result(1, 0.55, false), &white, -2));
+    }
+}
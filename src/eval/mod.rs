@@ -0,0 +1,329 @@
+use super::mancala::{Node, Player, Score};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// one stone in the store is one stone: evaluations are in hundredths of a stone
+pub const STONE: Score = 100;
+
+// the default WinModel
+const SLOPE: f64 = 0.11;
+const ENDGAME: f64 = 3.7;
+
+// anything that can judge a position the search stops at
+pub trait Evaluator: Send + Sync {
+    // in hundredths of a stone, from White's point of view
+    fn evaluate(&self, node: &Node) -> Score;
+
+    // several positions at once. evaluators with a fixed cost per call (a model behind a
+    // runtime, a GPU, ...) can override this to do them all in one go.
+    fn evaluate_batch(&self, nodes: &[Node]) -> Vec<Score> {
+        nodes.iter().map(|node| self.evaluate(node)).collect()
+    }
+
+    // whether a position's mirror image (see Node::mirrored) always gets minus its evaluation.
+    // the search only shares table entries between the two if so.
+    fn symmetric(&self) -> bool {
+        false
+    }
+}
+
+// the things about a position WeightedEval looks at. each counts White's minus Black's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    // stones already banked
+    Store,
+    // stones still in a player's own pits, which they'll mostly get to keep or sow themselves
+    OwnSide,
+    // pits that would end in the player's store and earn another sowing
+    ExtraTurns,
+    // the biggest capture a single sowing could make
+    CaptureThreat,
+    // pits the player could sow
+    Mobility,
+    // stones in pits whose sowing reaches the player's store
+    ReachStore,
+    // stones the other side could capture (see `Node::exposed`), so a weight for this is
+    // usually negative
+    Exposed,
+}
+
+pub const TERMS: usize = 7;
+
+impl Term {
+    pub const ALL: [Term; TERMS] = [Term::Store, Term::OwnSide, Term::ExtraTurns, Term::CaptureThreat, Term::Mobility, Term::ReachStore, Term::Exposed];
+
+    // as in a weights file
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Store => "store",
+            Term::OwnSide => "own_side",
+            Term::ExtraTurns => "extra_turns",
+            Term::CaptureThreat => "capture_threat",
+            Term::Mobility => "mobility",
+            Term::ReachStore => "reach_store",
+            Term::Exposed => "exposed"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|term| term.name() == name)
+    }
+}
+
+// how much each term is worth to WeightedEval, in hundredths of a stone per stone (or per pit).
+// a term with weight 0 is switched off and not even worked out. in a weights file, any missing
+// term keeps its default, and a key that isn't a term is an error (most likely a typo).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalWeights {
+    pub store: Score,
+    pub own_side: Score,
+    pub extra_turns: Score,
+    pub capture_threat: Score,
+    pub mobility: Score,
+    pub reach_store: Score,
+    pub exposed: Score,
+}
+
+impl Default for EvalWeights {
+    // just the stones banked so far
+    fn default() -> Self {
+        EvalWeights { store: STONE, own_side: 0, extra_turns: 0, capture_threat: 0, mobility: 0, reach_store: 0, exposed: 0 }
+    }
+}
+
+impl EvalWeights {
+    pub fn get(&self, term: Term) -> Score {
+        match term {
+            Term::Store => self.store,
+            Term::OwnSide => self.own_side,
+            Term::ExtraTurns => self.extra_turns,
+            Term::CaptureThreat => self.capture_threat,
+            Term::Mobility => self.mobility,
+            Term::ReachStore => self.reach_store,
+            Term::Exposed => self.exposed
+        }
+    }
+
+    pub fn set(&mut self, term: Term, weight: Score) {
+        let field = match term {
+            Term::Store => &mut self.store,
+            Term::OwnSide => &mut self.own_side,
+            Term::ExtraTurns => &mut self.extra_turns,
+            Term::CaptureThreat => &mut self.capture_threat,
+            Term::Mobility => &mut self.mobility,
+            Term::ReachStore => &mut self.reach_store,
+            Term::Exposed => &mut self.exposed
+        };
+        *field = weight;
+    }
+
+    // the same weights with one term switched off
+    pub fn without(mut self, term: Term) -> Self {
+        self.set(term, 0);
+        self
+    }
+
+    // a TOML file, or JSON if the name ends in .json
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save(self, path)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "json")
+}
+
+fn load<T: serde::de::DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+    let text = fs::read_to_string(path)?;
+    match is_json(path) {
+        true => serde_json::from_str(&text).map_err(|error| invalid(error.to_string())),
+        false => toml::from_str(&text).map_err(|error| invalid(error.to_string()))
+    }
+}
+
+fn save<T: Serialize>(value: &T, path: &Path) -> io::Result<()> {
+    let text = match is_json(path) {
+        true => serde_json::to_string_pretty(value).map_err(io::Error::other)?,
+        false => toml::to_string(value).map_err(io::Error::other)?
+    };
+    fs::write(path, text)
+}
+
+// turns a score into White's chances: the expected points (a draw counts half) from a score in
+// stones, from White's point of view, with `stones_left` still in play. it's a logistic curve
+// that gets steeper as the stones run out, since a lead means more when there's less left to
+// win back: the logit is score * (slope + endgame / (stones_left + 1)).
+// tune::fit_win_model fits it to self-play games; the defaults come from a few hundred of
+// them on the standard rules, scored by depth 4 searches.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WinModel {
+    pub slope: f64,
+    pub endgame: f64,
+}
+
+impl Default for WinModel {
+    fn default() -> Self {
+        WinModel { slope: SLOPE, endgame: ENDGAME }
+    }
+}
+
+impl WinModel {
+    pub fn logit(&self, score: Score, stones_left: Score) -> f64 {
+        score as f64 * (self.slope + self.endgame / (stones_left.max(0) as f64 + 1.0))
+    }
+
+    pub fn probability(&self, score: Score, stones_left: Score) -> f64 {
+        1.0 / (1.0 + (-self.logit(score, stones_left)).exp())
+    }
+
+    // for a search result: an exact score is the final margin, so the result is known
+    pub fn win_prob(&self, score: Score, stones_left: Score, exact: bool) -> f64 {
+        match exact {
+            true => 0.5 + 0.5 * score.signum() as f64,
+            false => self.probability(score, stones_left)
+        }
+    }
+
+    // same file formats as EvalWeights
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save(self, path)
+    }
+}
+
+// the usual evaluator: a weighted sum of simple terms
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WeightedEval {
+    pub weights: EvalWeights,
+}
+
+impl WeightedEval {
+    pub fn new(weights: EvalWeights) -> Self {
+        WeightedEval { weights }
+    }
+
+    // the value of every term (in Term::ALL order), or 0 for the ones switched off
+    pub fn terms(&self, node: &Node) -> [Score; TERMS] {
+        let weights = &self.weights;
+        let on = |term: Term| weights.get(term) != 0;
+        let mut values = [0; TERMS];
+        if on(Term::Store) {
+            values[0] = node.eval();
+        }
+        if on(Term::OwnSide) {
+            let board = node.get_board();
+            let side = |player: &Player| -> Score { board[node.pits(player)].iter().sum() };
+            values[1] = side(&Player::White) - side(&Player::Black);
+        }
+        // the threats take a sowing per pit, so they're only worked out when they count
+        if [Term::ExtraTurns, Term::CaptureThreat, Term::Mobility, Term::ReachStore].into_iter().any(on) {
+            let (white, black) = (node.threats(&Player::White), node.threats(&Player::Black));
+            values[2] = white.extra_turns as Score - black.extra_turns as Score;
+            values[3] = white.capture - black.capture;
+            values[4] = white.moves as Score - black.moves as Score;
+            values[5] = white.reach_store - black.reach_store;
+        }
+        if on(Term::Exposed) {
+            values[6] = node.exposed(&Player::White) - node.exposed(&Player::Black);
+        }
+        values
+    }
+}
+
+impl Evaluator for WeightedEval {
+    fn evaluate(&self, node: &Node) -> Score {
+        let values = self.terms(node);
+        Term::ALL.iter().zip(values).map(|(&term, value)| self.weights.get(term) * value).sum()
+    }
+
+    // every term counts White's minus Black's
+    fn symmetric(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mancala::RuleSet;
+
+    #[test]
+    fn test_evaluate() {
+        let node = Node::from_board(&[0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 19], Player::White, RuleSet::default()).unwrap();
+        assert_eq!(WeightedEval::default().evaluate(&node), STONE);
+        let hoarder = WeightedEval::new(EvalWeights { own_side: 50, ..EvalWeights::default() });
+        // 4 stones on each side
+        assert_eq!(hoarder.evaluate(&node), STONE);
+        let node = Node::from_board(&[0, 2, 0, 1, 0, 3, 20, 1, 0, 2, 0, 0, 1, 19], Player::White, RuleSet::default()).unwrap();
+        assert_eq!(hoarder.evaluate(&node), STONE + 100);
+
+        // White's 1 reaches the store from pit 5 and 3 from pit 3; Black's 1 in pit 12 does too,
+        // and Black's 2 in pit 9 would land in the empty pit 11 and take the 2 opposite it (with itself)
+        let threats = WeightedEval::new(EvalWeights { store: 0, extra_turns: 10, capture_threat: 1, ..EvalWeights::default() });
+        let node = Node::from_board(&[0, 2, 0, 3, 0, 1, 20, 1, 0, 2, 0, 0, 1, 17], Player::White, RuleSet::default()).unwrap();
+        assert_eq!((node.threats(&Player::White).extra_turns, node.threats(&Player::Black).extra_turns), (2, 1));
+        assert_eq!((node.threats(&Player::White).capture, node.threats(&Player::Black).capture), (0, 3));
+        assert_eq!(threats.evaluate(&node), 10 - 3);
+    }
+
+    #[test]
+    fn test_terms() {
+        // three pits to sow each. White's 6, 3 and 1 all reach the store but Black's 1 in pit 12
+        // is the only one of theirs that does. White's 6 faces an empty pit, and so does that 1.
+        let node = Node::from_board(&[0, 6, 0, 3, 0, 1, 20, 1, 0, 2, 0, 0, 1, 14], Player::White, RuleSet::default()).unwrap();
+        let all = EvalWeights { store: 1, own_side: 1, extra_turns: 1, capture_threat: 1, mobility: 1, reach_store: 1, exposed: 1 };
+        let terms = WeightedEval::new(all).terms(&node);
+        assert_eq!(terms[4..], [0, (6 + 3 + 1) - 1, 6 - 1]);
+        for term in Term::ALL {
+            assert_eq!(Term::from_name(term.name()), Some(term));
+            let index = Term::ALL.iter().position(|&other| other == term).unwrap();
+            let off = WeightedEval::new(all.without(term));
+            assert_eq!(all.without(term).get(term), 0);
+            assert_eq!(off.evaluate(&node), WeightedEval::new(all).evaluate(&node) - terms[index]);
+        }
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("mancala-weights-{}.toml", std::process::id()));
+        fs::write(&toml_path, "own_side = 25\ncapture_threat = 40\n").unwrap();
+        let weights = EvalWeights::load(&toml_path).unwrap();
+        assert_eq!(weights, EvalWeights { own_side: 25, capture_threat: 40, ..EvalWeights::default() });
+
+        let json_path = dir.join(format!("mancala-weights-{}.json", std::process::id()));
+        weights.save(&json_path).unwrap();
+        assert_eq!(EvalWeights::load(&json_path).unwrap(), weights);
+        weights.save(&toml_path).unwrap();
+        assert_eq!(EvalWeights::load(&toml_path).unwrap(), weights);
+        fs::write(&toml_path, "own_side = \"lots\"").unwrap();
+        assert!(EvalWeights::load(&toml_path).is_err());
+        fs::write(&toml_path, "own_sides = 25").unwrap();
+        assert!(EvalWeights::load(&toml_path).is_err());
+        fs::remove_file(&toml_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[test]
+    fn test_win_model() {
+        let model = WinModel::default();
+        assert_eq!(model.probability(0, 30), 0.5);
+        assert!(model.probability(3, 30) > 0.5);
+        assert!((model.probability(3, 30) + model.probability(-3, 30) - 1.0).abs() < 1e-9);
+        // the same lead is worth more with fewer stones left to win it back
+        assert!(model.probability(3, 4) > model.probability(3, 30));
+        assert_eq!((model.win_prob(5, 10, true), model.win_prob(0, 10, true), model.win_prob(-1, 10, true)), (1.0, 0.5, 0.0));
+    }
+}
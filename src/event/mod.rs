@@ -0,0 +1,68 @@
+use super::engine::SearchInfo;
+use super::game::{self, Game};
+use super::mancala::{Move, Node, Outcome, Player, Score};
+use serde::Serialize;
+
+// what happens during a game at the prompt, for --output json: each one is written as a JSON
+// object on a line of its own, with "event" saying which it is, so another program can follow the
+// game without reading the board art
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    // the position: every pocket in order, as Node::get_board has them, and the fen
+    Board { board: Vec<Score>, turn: Player, fen: String },
+    // a full move, as the record writes it (e.g. "2-5"), and its sub-moves
+    Move { player: Player, notation: String, pockets: Vec<usize> },
+    // a finished depth of the AI's search. the score is White's.
+    Info { depth: usize, score: Score, nodes: u64, time_ms: u128, pv: Vec<String> },
+    // how it ended, with the whole record (see Game::annotated_record). winner is null for a draw.
+    End { winner: Option<Player>, score: Score, reason: String, record: String },
+    // anything said to the player (answers to commands, errors) that isn't one of the above
+    Message { text: String },
+}
+
+impl Event {
+    pub fn board(node: &Node) -> Self {
+        Event::Board { board: node.get_board().to_vec(), turn: node.get_turn().clone(), fen: node.fen() }
+    }
+
+    // `mv` played from `before`
+    pub fn played(before: &Node, mv: &Move) -> Self {
+        Event::Move { player: before.get_turn().clone(), notation: game::format_move(mv), pockets: mv.clone() }
+    }
+
+    pub fn info(info: &SearchInfo) -> Self {
+        Event::Info { depth: info.depth, score: info.score, nodes: info.nodes, time_ms: info.time.as_millis(), pv: info.pv.iter().map(game::format_move).collect() }
+    }
+
+    pub fn end(outcome: &Outcome, game: &Game) -> Self {
+        Event::End { winner: outcome.winner.clone(), score: outcome.score, reason: outcome.reason.to_string(), record: game.annotated_record() }
+    }
+
+    pub fn message(text: &str) -> Self {
+        Event::Message { text: text.trim_end().to_string() }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("events are plain data")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::RuleSet;
+
+    #[test]
+    fn test_to_json() {
+        let mut game = Game::new(RuleSet::default());
+        game.play(&vec![2, 5]).unwrap();
+        assert_eq!(Event::played(&game.history()[0], &game.moves()[0]).to_json(), r#"{"event":"move","player":"White","notation":"2-5","pockets":[2,5]}"#);
+        let board = Event::board(game.node()).to_json();
+        assert!(board.starts_with(r#"{"event":"board","board":[4,4,0,5,5,0,2,"#) && board.contains(r#""turn":"Black""#));
+        game.resign(Player::Black).unwrap();
+        let end = Event::end(&game.outcome().unwrap(), &game).to_json();
+        assert_eq!(end, r#"{"event":"end","winner":"White","score":-8,"reason":"resignation","record":"2-5 # resignation"}"#);
+        assert_eq!(Event::message("Nothing to undo.\n").to_json(), r#"{"event":"message","text":"Nothing to undo."}"#);
+    }
+}
@@ -0,0 +1,509 @@
+use super::clock::SavedClock;
+use super::log::{self, Level};
+use super::mancala::{Error, Move, Node, Outcome, Player, Pocket, RuleSet, TerminationReason};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const VERSION: u32 = 1;
+
+// a game written to a file to carry on later: the rules and the full moves from the start,
+// as a record (see Game::record), and the clocks if it's timed. a move that's only partly
+// played isn't kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedGame {
+    version: u32,
+    rules: RuleSet,
+    // the first position (see Node::fen), if the game didn't start from the usual one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    record: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock: Option<SavedClock>,
+}
+
+// a move in a record that couldn't be played: which one (counting full moves from 1), as it was
+// written, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordError {
+    pub number: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "move {} ({}): {}", self.number, self.text, self.reason)
+    }
+}
+
+// a game in progress: the current position plus everything that led to it.
+// Node only knows about the board, so the rules that need history
+// (move limit, repetition) are enforced here.
+#[derive(Debug, Clone)]
+pub struct Game {
+    node: Node,
+    // positions before each full move, oldest first
+    history: Vec<Node>,
+    moves: Vec<Move>,
+    // sub-moves played so far in the current (unfinished) full move
+    pending: Move,
+    // set when the game was ended by something other than the board (see `end`)
+    ended: Option<Outcome>,
+    // what `undo` took back, most recent last, until something else is played
+    undone: Vec<Move>,
+    // draws offered, by how many full moves had been played when the player to move offered
+    offers: Vec<usize>,
+}
+
+impl Game {
+    pub fn new(rules: RuleSet) -> Self {
+        Self::from_node(Node::new(rules))
+    }
+
+    pub fn from_node(node: Node) -> Self {
+        Game {
+            node,
+            history: Vec::new(),
+            moves: Vec::new(),
+            pending: Vec::new(),
+            ended: None,
+            undone: Vec::new(),
+            offers: Vec::new(),
+        }
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // where the game started
+    pub fn start(&self) -> &Node {
+        self.history.first().unwrap_or(&self.node)
+    }
+
+    // the position before each of `moves`
+    pub fn history(&self) -> &[Node] {
+        &self.history[..self.moves.len()]
+    }
+
+    // the moves as text: full moves separated by spaces, sub-moves within one by dashes
+    // (e.g. "2-5 9 1-5-0"). a draw offer is an = where it was made, before the offerer's move.
+    pub fn record(&self) -> String {
+        let mut words = Vec::new();
+        for number in 0..=self.moves.len() {
+            words.extend(self.offers.iter().filter(|&&offer| offer == number).map(|_| "=".to_string()));
+            words.extend(self.moves.get(number).map(format_move));
+        }
+        words.join(" ")
+    }
+
+    // the record with why the game ended after a #, once it has (e.g. "2-5 9 = # draw agreed").
+    // from_record reads it back, leaving off the ending.
+    pub fn annotated_record(&self) -> String {
+        match self.outcome() {
+            Some(outcome) => format!("{} # {}", self.record(), outcome.reason).trim_start().to_string(),
+            None => self.record()
+        }
+    }
+
+    // replays a record from `record` from the start
+    pub fn from_record(rules: RuleSet, record: &str) -> Result<Self, Error> {
+        Self::from_record_at(Node::new(rules), record)
+    }
+
+    // the same from some other position
+    pub fn from_record_at(start: Node, record: &str) -> Result<Self, Error> {
+        let mut game = Self::from_node(start);
+        game.play_record(record).map_err(|_| Error::IllegalError)?;
+        Ok(game)
+    }
+
+    // plays the moves of a record on from here, stopping at the first that can't be played. (what
+    // came before it stays played.)
+    pub fn play_record(&mut self, record: &str) -> Result<(), RecordError> {
+        let moves = record.split('#').next().unwrap_or_default();
+        let mut number = 0;
+        for text in moves.split_whitespace() {
+            // (an offer counts as part of the move it comes before)
+            let error = |reason: String| RecordError { number: number + 1, text: text.to_string(), reason };
+            if text == "=" {
+                self.offer_draw().map_err(|reason| error(reason.to_string()))?;
+                continue;
+            }
+            let mv = parse_move(text).ok_or_else(|| error("not a move: pits are numbers joined by dashes".to_string()))?;
+            if let Err(reason) = self.play(&mv) {
+                return Err(error(self.why_illegal(&mv).unwrap_or_else(|| reason.to_string())));
+            }
+            number += 1;
+        }
+        Ok(())
+    }
+
+    // what's wrong with `mv` here, as far as its sub-moves show
+    fn why_illegal(&self, mv: &Move) -> Option<String> {
+        let mut game = self.clone();
+        let turn = self.node.get_turn().clone();
+        for (played, &pocket) in mv.iter().enumerate() {
+            if game.moves.len() > self.moves.len() || game.outcome().is_some() {
+                return Some(format!("{}'s turn was over after {}", turn, format_move(&mv[..played].to_vec())));
+            }
+            if let Err(error) = game.sub_move(pocket) {
+                return Some(format!("{} can't sow from {}: {}", turn, pocket, error));
+            }
+        }
+        match game.pending.is_empty() {
+            true => None,
+            false => Some(format!("{}'s turn isn't over: they sow again after {}", turn, format_move(mv)))
+        }
+    }
+
+    // plays a complete move (all of its sub-moves)
+    pub fn play(&mut self, mv: &Move) -> Result<(), Error> {
+        if self.outcome().is_some() {
+            return Err(Error::GameOverError);
+        }
+        if !self.pending.is_empty() || !self.node.legal_moves().contains(mv) {
+            return Err(Error::IllegalError);
+        }
+        self.history.push(self.node.clone());
+        self.node.full_move(mv)?;
+        self.moves.push(mv.clone());
+        self.undone.clear();
+        log::event("game", Level::Trace, || format!("move {}: {} played {}", self.moves.len(), self.history.last().unwrap().get_turn(), format_move(mv)));
+        Ok(())
+    }
+
+    // plays a single sub-move, as a human would enter it.
+    // the full move is recorded once the turn passes (or the game ends).
+    pub fn sub_move(&mut self, pocket: Pocket) -> Result<(), Error> {
+        if self.outcome().is_some() {
+            return Err(Error::GameOverError);
+        }
+        let before = self.node.clone();
+        let mut after = before.clone();
+        after.sub_move(pocket)?;
+        // it also has to be on the way to a legal full move, which Node::sub_move doesn't know
+        // about for the rules that look at the whole move (must-feed)
+        let start = if self.pending.is_empty() { &self.node } else { self.history.last().expect("there's a position before every move") };
+        let played: Move = self.pending.iter().copied().chain([pocket]).collect();
+        if !start.legal_moves().iter().any(|mv| mv.starts_with(&played)) {
+            return Err(Error::IllegalError);
+        }
+        self.node = after;
+        let turn_passed = self.node.get_turn() != before.get_turn();
+        if self.pending.is_empty() {
+            self.history.push(before);
+        }
+        self.pending.push(pocket);
+        if turn_passed || self.node.is_over() {
+            self.moves.push(std::mem::take(&mut self.pending));
+            log::event("game", Level::Trace, || format!("move {}: {} played {}", self.moves.len(), self.history.last().unwrap().get_turn(), format_move(self.moves.last().unwrap())));
+        }
+        self.undone.clear();
+        Ok(())
+    }
+
+    // takes back the last full move, or the sub-moves played so far if one is unfinished, and
+    // returns what was taken back. an ending by `end` (e.g. a resignation) goes with it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = match self.pending.is_empty() {
+            true => self.moves.pop()?,
+            false => std::mem::take(&mut self.pending)
+        };
+        self.node = self.history.pop().expect("there's a position before every move");
+        self.ended = None;
+        let played = self.moves.len();
+        self.offers.retain(|&offer| offer <= played);
+        self.undone.push(mv.clone());
+        Some(mv)
+    }
+
+    // plays the move `undo` last took back again, as long as nothing else was played since
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.undone.pop()?;
+        let undone = std::mem::take(&mut self.undone);
+        for &pocket in &mv {
+            self.sub_move(pocket).expect("a move that was taken back can be played again");
+        }
+        self.undone = undone;
+        Some(mv)
+    }
+
+    // ends the game here with this result. only between full moves.
+    pub fn end(&mut self, outcome: Outcome) -> Result<(), Error> {
+        if self.outcome().is_some() {
+            return Err(Error::GameOverError);
+        }
+        if !self.pending.is_empty() {
+            return Err(Error::IllegalError);
+        }
+        log::event("game", Level::Debug, || format!("ended after {} moves: {}", self.moves.len(), outcome.reason));
+        self.ended = Some(outcome);
+        Ok(())
+    }
+
+    // ends the game with a win for the other player. the score is what each side has as it stands.
+    pub fn resign(&mut self, player: Player) -> Result<(), Error> {
+        self.forfeit(player, TerminationReason::Resignation)
+    }
+
+    // ends the game with a win for the other player because `player` ran out of time. time can
+    // run out in the middle of a move, so what was played of an unfinished one is taken back.
+    pub fn time_out(&mut self, player: Player) -> Result<(), Error> {
+        if self.outcome().is_some() {
+            return Err(Error::GameOverError);
+        }
+        if !self.pending.is_empty() {
+            self.undo();
+            self.undone.clear();
+        }
+        self.forfeit(player, TerminationReason::Timeout)
+    }
+
+    // notes that the player to move offered a draw. only between full moves.
+    pub fn offer_draw(&mut self) -> Result<(), Error> {
+        if self.outcome().is_some() {
+            return Err(Error::GameOverError);
+        }
+        if !self.pending.is_empty() {
+            return Err(Error::IllegalError);
+        }
+        self.offers.push(self.moves.len());
+        Ok(())
+    }
+
+    // how many full moves had been played at each draw offer
+    pub fn offers(&self) -> &[usize] {
+        &self.offers
+    }
+
+    // ends the game drawn, the offer having been taken
+    pub fn agree_draw(&mut self) -> Result<(), Error> {
+        let score = self.node.adjudicate(TerminationReason::Agreement).score;
+        self.end(Outcome { winner: None, score, reason: TerminationReason::Agreement })
+    }
+
+    fn forfeit(&mut self, player: Player, reason: TerminationReason) -> Result<(), Error> {
+        let score = self.node.adjudicate(reason).score;
+        self.end(Outcome { winner: Some(player.toggled()), score, reason })
+    }
+
+    // the result of the game, taking the move limit, repetitions and `end` into account
+    pub fn outcome(&self) -> Option<Outcome> {
+        if let Some(outcome) = &self.ended {
+            return Some(outcome.clone());
+        }
+        if let Some(outcome) = self.node.outcome() {
+            return Some(outcome);
+        }
+        if !self.pending.is_empty() {
+            return None;
+        }
+        if let Some(limit) = self.node.get_rules().move_limit {
+            if self.moves.len() >= limit {
+                return Some(self.node.adjudicate(TerminationReason::MoveLimit));
+            }
+        }
+        if self.history.contains(&self.node) {
+            return Some(self.node.adjudicate(TerminationReason::Repetition));
+        }
+        None
+    }
+}
+
+impl Game {
+    pub fn save(&self, path: &Path, clock: Option<SavedClock>) -> io::Result<()> {
+        let rules = *self.node.get_rules();
+        let start = Some(self.start().fen()).filter(|_| *self.start() != Node::new(rules));
+        let saved = SavedGame { version: VERSION, rules, start, record: self.record(), clock };
+        fs::write(path, serde_json::to_string_pretty(&saved).map_err(io::Error::other)?)
+    }
+
+    // the game, and its clocks as they were saved
+    pub fn load(path: &Path) -> io::Result<(Self, Option<SavedClock>)> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let saved: SavedGame = serde_json::from_str(&fs::read_to_string(path)?).map_err(|error| invalid(error.to_string()))?;
+        if saved.version != VERSION {
+            return Err(invalid(format!("saved game version {} isn't supported", saved.version)));
+        }
+        let start = match &saved.start {
+            Some(fen) => Node::from_fen(fen, saved.rules).ok_or_else(|| invalid(format!("{} isn't a position under the saved rules", fen)))?,
+            None => Node::new(saved.rules)
+        };
+        let game = Self::from_record_at(start, &saved.record).map_err(|_| invalid("the saved moves aren't a legal game".to_string()))?;
+        Ok((game, saved.clock))
+    }
+}
+
+pub fn format_move(mv: &Move) -> String {
+    mv.iter().map(|pocket| pocket.to_string()).collect::<Vec<_>>().join("-")
+}
+
+pub fn parse_move(text: &str) -> Option<Move> {
+    text.split('-').map(|pocket| pocket.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{OnTimeout, TimeControl};
+    use std::time::Duration;
+
+    #[test]
+    fn test_move_limit() {
+        let rules = RuleSet { move_limit: Some(2), ..RuleSet::default() };
+        let mut game = Game::new(rules);
+        assert!(game.sub_move(2).is_ok());
+        // landed in the store, still White's move
+        assert_eq!(game.moves().len(), 0);
+        assert!(game.sub_move(0).is_ok());
+        assert_eq!(game.moves(), &[vec![2, 0]]);
+        assert_eq!(game.outcome(), None);
+
+        let reply = game.node().legal_moves()[0].clone();
+        assert!(game.play(&reply).is_ok());
+        let outcome = game.outcome().unwrap();
+        assert_eq!(outcome.reason, TerminationReason::MoveLimit);
+        assert!(game.play(&reply).is_err());
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut game = Game::new(RuleSet::default());
+        game.play(&vec![0]).unwrap();
+        assert!(game.resign(Player::White).is_ok());
+        let outcome = game.outcome().unwrap();
+        assert_eq!((outcome.winner, outcome.reason), (Some(Player::Black), TerminationReason::Resignation));
+        assert!(game.play(&vec![7]).is_err());
+        assert!(game.resign(Player::Black).is_err());
+    }
+
+    #[test]
+    fn test_time_out() {
+        let mut game = Game::new(RuleSet::default());
+        game.play(&vec![0]).unwrap();
+        // Black's partway through a move that ends in the store when the flag falls
+        game.sub_move(9).unwrap();
+        assert!(game.time_out(Player::Black).is_ok());
+        assert_eq!(game.moves(), &[vec![0]]);
+        let outcome = game.outcome().unwrap();
+        assert_eq!((outcome.winner, outcome.reason), (Some(Player::White), TerminationReason::Timeout));
+        assert!(game.time_out(Player::White).is_err());
+    }
+
+    #[test]
+    fn test_draw_offer() {
+        let mut game = Game::new(RuleSet::default());
+        game.play(&vec![0]).unwrap();
+        game.offer_draw().unwrap();
+        game.play(&vec![7]).unwrap();
+        game.offer_draw().unwrap();
+        assert_eq!(game.record(), "0 = 7 =");
+        game.agree_draw().unwrap();
+        let outcome = game.outcome().unwrap();
+        assert_eq!((outcome.winner, outcome.reason), (None, TerminationReason::Agreement));
+        assert_eq!(game.annotated_record(), "0 = 7 = # draw agreed");
+        let replayed = Game::from_record(RuleSet::default(), &game.annotated_record()).unwrap();
+        assert_eq!((replayed.offers(), replayed.outcome()), (&[1, 2][..], None));
+        // taking a move back forgets the offers made since
+        game.undo();
+        assert_eq!(game.record(), "0 =");
+    }
+
+    #[test]
+    fn test_record() {
+        let mut game = Game::new(RuleSet::default());
+        for mv in [vec![2, 5], vec![9], vec![3]] {
+            game.play(&mv).unwrap();
+        }
+        assert_eq!(game.record(), "2-5 9 3");
+        let replayed = Game::from_record(RuleSet::default(), &game.record()).unwrap();
+        assert_eq!(replayed.node(), game.node());
+        assert_eq!(replayed.history().len(), 3);
+        assert!(Game::from_record(RuleSet::default(), "2-5 2").is_err());
+        assert!(Game::from_record(RuleSet::default(), "2-x").is_err());
+
+        // where a record goes wrong, and how
+        let error = |record: &str| Game::new(RuleSet::default()).play_record(record).unwrap_err().to_string();
+        assert_eq!(error("2-5 9 3 9"), "move 4 (9): Black can't sow from 9: the pit is empty");
+        assert_eq!(error("2"), "move 1 (2): White's turn isn't over: they sow again after 2");
+        assert_eq!(error("2-5-1"), "move 1 (2-5-1): White's turn was over after 2-5");
+        assert_eq!(error("2-5 4"), "move 2 (4): Black can't sow from 4: not a pit of the player to move");
+        assert_eq!(error("0 = 7 two"), "move 3 (two): not a move: pits are numbers joined by dashes");
+    }
+
+    #[test]
+    fn test_save() {
+        let rules = RuleSet::variant("oware").unwrap();
+        let mut game = Game::new(rules);
+        for _ in 0..6 {
+            let mv = game.node().legal_moves()[0].clone();
+            game.play(&mv).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("mancala-save-{}.json", std::process::id()));
+        game.save(&path, None).unwrap();
+        let (loaded, clock) = Game::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.node(), loaded.record(), clock), (game.node(), game.record(), None));
+
+        // from somewhere else than the start
+        let start = Node::from_fen("0,0,3,0,1,0,10,2,0,0,0,1,0,31 b", RuleSet::default()).unwrap();
+        let mut game = Game::from_node(start.clone());
+        game.play(&vec![7]).unwrap();
+        game.save(&path, None).unwrap();
+        let (loaded, _) = Game::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.start(), loaded.node()), (&start, game.node()));
+
+        // with the clocks as they were
+        let clock = SavedClock { control: TimeControl::parse("5+3").unwrap(), on_timeout: OnTimeout::Move, remaining: [Duration::from_millis(61_500), Duration::from_secs(240)] };
+        game.save(&path, Some(clock)).unwrap();
+        let (_, loaded) = Game::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Some(clock));
+    }
+
+    #[test]
+    fn test_must_feed() {
+        // Black has nothing, and only the 2 stones in pit 5 reach them
+        let node = Node::from_fen("1,0,0,0,0,2,0,0,0,0,0,0 w 0 0", RuleSet::variant("oware").unwrap()).unwrap();
+        let mut game = Game::from_node(node.clone());
+        assert_eq!(game.sub_move(0), Err(Error::IllegalError));
+        assert_eq!((game.node(), game.outcome()), (&node, None));
+        game.sub_move(5).unwrap();
+        assert_eq!(game.moves(), [vec![5]]);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut game = Game::from_record(RuleSet::default(), "2-5 9").unwrap();
+        game.sub_move(1).unwrap();
+        let before = game.node().clone();
+        assert_eq!(game.undo(), Some(vec![1]));
+        assert_eq!(game.undo(), Some(vec![9]));
+        assert_eq!((game.record(), game.history().len()), ("2-5".to_string(), 1));
+        game.play(&vec![9]).unwrap();
+        game.sub_move(1).unwrap();
+        assert_eq!(game.node(), &before);
+        assert_eq!(Game::new(RuleSet::default()).undo(), None);
+
+        game.undo();
+        game.undo();
+        assert_eq!(game.redo(), Some(vec![9]));
+        assert_eq!(game.redo(), Some(vec![1]));
+        assert_eq!(game.node(), &before);
+        assert_eq!(game.redo(), None);
+        // playing something else forgets what was undone
+        game.undo();
+        game.undo();
+        game.play(&vec![7]).unwrap();
+        assert_eq!(game.redo(), None);
+    }
+}
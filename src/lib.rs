@@ -0,0 +1,32 @@
+pub mod analysis;
+pub mod arena;
+pub mod bench;
+pub mod book;
+pub mod clock;
+pub mod command;
+pub mod config;
+pub mod display;
+pub mod editor;
+pub mod endgame;
+pub mod engine;
+pub mod eval;
+pub mod event;
+pub mod game;
+pub mod log;
+pub mod mancala;
+pub mod mcts;
+pub mod minimax;
+#[cfg(feature = "nn")]
+pub mod nn;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod profile;
+pub mod repro;
+pub mod rng;
+pub mod selfplay;
+pub mod solve;
+pub mod summary;
+pub mod tree;
+pub mod tt;
+pub mod tui;
+pub mod tune;
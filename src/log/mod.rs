@@ -0,0 +1,116 @@
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// diagnostics for long runs (matches, tournaments, self-play), written to stderr so they don't mix
+// with what the program prints. which ones is up to RUST_LOG, read the way tracing's env filter
+// reads it: a level for everything and/or target=level pairs, e.g. RUST_LOG=info or
+// RUST_LOG=warn,search=debug. nothing is written without it. the targets are "search" (each
+// finished depth, and the table at the end of a search) and "game" (moves played, how games end).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE"
+        })
+    }
+}
+
+// what RUST_LOG asked for. parts that don't make sense are skipped, as tracing does.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    // for targets that aren't named
+    default: Option<Level>,
+    targets: Vec<(String, Level)>,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Filter::default();
+        for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.split_once('=') {
+                Some((target, level)) => {
+                    if let Some(level) = Level::from_name(level) {
+                        filter.targets.push((target.to_string(), level));
+                    }
+                }
+                None => match Level::from_name(part) {
+                    Some(level) => { filter.default = Some(level); }
+                    // a target on its own means all of it
+                    None => { filter.targets.push((part.to_string(), Level::Trace)); }
+                }
+            }
+        }
+        filter
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let allowed = self.targets.iter().rev().find(|(name, _)| name == target).map(|(_, level)| *level).or(self.default);
+        allowed.is_some_and(|allowed| level <= allowed)
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    start: Instant,
+}
+
+fn logger() -> &'static Logger {
+    static LOGGER: OnceLock<Logger> = OnceLock::new();
+    LOGGER.get_or_init(|| Logger { filter: Filter::parse(&env::var("RUST_LOG").unwrap_or_default()), start: Instant::now() })
+}
+
+pub fn enabled(target: &str, level: Level) -> bool {
+    logger().filter.enabled(target, level)
+}
+
+// writes one line, with the seconds since the first one. the message is only made if it's wanted.
+pub fn event(target: &str, level: Level, message: impl FnOnce() -> String) {
+    let logger = logger();
+    if logger.filter.enabled(target, level) {
+        eprintln!("{:>9.3}s {:5} {}: {}", logger.start.elapsed().as_secs_f64(), level, target, message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse("warn, search=debug,game=loud,tt");
+        assert!(filter.enabled("search", Level::Debug) && !filter.enabled("search", Level::Trace));
+        assert!(filter.enabled("game", Level::Warn) && !filter.enabled("game", Level::Info));
+        assert!(filter.enabled("tt", Level::Trace));
+        assert!(!Filter::parse("").enabled("search", Level::Error));
+        assert!(Filter::parse("info,search=error").enabled("game", Level::Info));
+        assert!(!Filter::parse("info,search=error").enabled("search", Level::Warn));
+    }
+}
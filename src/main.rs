@@ -1,94 +1,1576 @@
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-mod mancala;
-mod minimax;
+use mancala_rs::{analysis, arena, bench, book, clock, command, config, display, editor, endgame, engine, eval, event, game, mancala, mcts, minimax, profile, repro, selfplay, solve, summary, tt, tui, tune};
 
 const DEPTH: usize = 10;
 
+// what headless exits with when White wins, Black wins or it's a draw. (kept clear of 1 and 2,
+// which say something went wrong.)
+const HEADLESS_EXIT: [i32; 3] = [10, 11, 12];
+
+const USAGE: &str = "Usage: mancala-rs [command] [flags]
+
+Commands:
+  play                 play against the AI (the default; type help in a game for its commands)
+  analyze <games>      a report on every move of some recorded games, or with --fen
+                       and no games, every legal move in that position with its score
+  selfplay <file>      training data from games of the engine against itself
+  perft <depth>        count the lines of play from the start, to check move generation
+  bench                search some fixed positions, for comparing builds
+  watch <white> <black>
+                       two engine configurations play each other
+  match <first> <second>
+                       a series of games between two engine configurations
+  headless [<white> <black>] [--script <file>]
+                       one game with nobody at the keyboard: the moves in <file>, then the
+                       engines; exits with 10 if White won, 11 if Black did, 12 for a draw
+  tournament <file>    a round robin between the engine configurations in a TOML file
+  elo <results>        ratings from the games that match and tournament wrote with --results
+  stats                how you've done against the AI: results by difficulty, streaks and accuracy
+  replay-search <file> rerun a search written with --repro and compare
+  solve                prove the value of a (small) starting position
+  book, tune, spsa, evolve, rl, winmodel, build-endgame-db
+                       building books, databases and evaluation weights
+  help                 this message
+
+Common flags:
+  --variant <name>     kalah (the default), oware, avalanche or adjacent
+  --color <w|b>        the side you play, instead of being asked
+  --mode <ai|pvp>      against the AI (the default), or two people at one keyboard
+  --depth N            how deep the AI searches
+  --movetime N         or how long it thinks, in milliseconds
+  --oware-captures, --no-stores, --adjacent-stores, --avalanche, --must-feed
+                       single house rules, on top of the variant
+  --difficulty <name>  beginner, easy, medium, hard or max, instead of being asked
+  --time <minutes>     a clock for each side with that long for the game (or m:ss);
+                       the AI spends a share of its time on each move. add +S for S
+                       seconds back after every move (e.g. 5+3), dS for a delay of S
+                       seconds before the clock runs (5d3), or bS for Bronstein delay:
+                       up to S seconds of each move given back (5b3)
+  --on-timeout <what>  lose (the default) to lose on time, or move for casual games: a
+                       quick engine move is played for you when your time is up
+  --hint-depth N       how deep the hint command looks (half the AI's depth otherwise)
+  --display <style>    ascii (the default), unicode for a board drawn with box lines, or
+                       compact (used anyway when the terminal is too narrow for ascii)
+  --colors <when>      auto (the default: when writing to a terminal), always or never
+  --tui                the game on one full screen: arrow keys or a-f to choose a pit,
+                       enter to play it (needs a Unix-like terminal)
+  --no-clear           print each position after the last instead of clearing the screen,
+                       for logs and scrollback
+  --animate N          show each move sown a stone at a time, N milliseconds apart
+  --quiet              no banner, prompts or boards: moves are read from the input and only
+                       the moves played and the result are printed, one per line, for scripts
+  --output <format>    text (the default), or json for one JSON object per line for each
+                       position, move, finished depth of the AI's search, message and the
+                       end of the game (implies --quiet)
+  --verbose            show each depth the AI finishes while it thinks: score, nodes,
+                       time and line (or verbose on in the game)
+  --eval-bar           how the game stands, as a bar under the board
+                       (eval-bar = true in the config file to always have it)
+  --load <file>        carry on with a game written by the save command, clocks and all
+  --fen <position>     start from a position written by the fen command
+  --moves <file>       play the moves of a record (e.g. 2-5 9 3) first, or with - the
+                       first line of the input
+  --seed N             where the AI's random choices start from (0 by default), for this and
+                       every command: the same seed plays the same game again
+  --profile <file>     where your results against the AI are kept, instead of profile.toml
+                       beside the config file
+  --no-profile         leave this game out of them
+  --history <file>     where what's typed at the prompts is kept, for going back to with the
+                       up arrow, instead of history beside the config file
+  --no-history         keep none
+  --config <file>      default flags from a TOML file instead of
+                       ~/.config/mancala-rs/config.toml (flag = value, or true for a switch)
+  --no-config          ignore the config file
+  --repro <file>       write the AI's latest search to <file> for replay-search. every
+                       search then starts from an empty table, without the book, the
+                       endgame database or --nn, so that it can be run again the same
+
+Run a command without its arguments to see its own flags.";
+
+// set by --no-clear: everything stays in the scrollback, one position after another
+static NO_CLEAR: AtomicBool = AtomicBool::new(false);
+
+// set by --verbose or the verbose command: the AI's search is printed as it goes
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+// set by --output json: what the game says goes out as event::Event lines
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// the lines typed at the prompts so far, for going back through with the arrow keys. kept in a
+// history file unless --no-history.
+static HISTORY: Mutex<editor::History> = Mutex::new(editor::History::new());
+
+// cleared at startup on a Windows console that can't be made to take escape sequences (see
+// enable_escapes), which then gets no clearing and no colors
+static ESCAPES: AtomicBool = AtomicBool::new(true);
+
+// something to tell the player: the text, or with --output json a message event
+fn say(text: &str) {
+    match JSON.load(Ordering::Relaxed) {
+        true => println!("{}", event::Event::message(text).to_json()),
+        false => println!("{}", text)
+    }
+}
+
+// clears the screen and puts the cursor at the top. (not a full terminal reset, ESC c, which
+// Windows consoles ignore even with escapes turned on: clearing and homing work everywhere that
+// takes escapes at all.)
 fn cls() {
-    print!("{esc}c", esc = 27 as char);
+    match NO_CLEAR.load(Ordering::Relaxed) {
+        true => println!(),
+        false => print!("{esc}[2J{esc}[H", esc = 27 as char)
+    }
 }
 
-fn main() -> io::Result<()> {
-    let mut node = mancala::Node::default();
-    cls();
-    print!("Hello! I am the Mancala Rust AI. Would you like to play as White or Black? (w/b) ");
+// whether the terminal is likely to show ANSI colors
+fn ansi_terminal() -> bool {
+    io::stdout().is_terminal() && ESCAPES.load(Ordering::Relaxed) && std::env::var_os("NO_COLOR").is_none() && std::env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+// the Windows console (conhost) prints escape sequences as they are unless virtual terminal
+// processing is turned on for it, which only Windows 10 and later can do. false if it's a
+// console that can't. (output that isn't going to a console at all is left alone.)
+#[cfg(windows)]
+fn enable_escapes() -> bool {
+    use std::ffi::c_void;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(which: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+    // (safe: the handle only goes back to the console functions, which check it)
+    unsafe {
+        let console = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(console, &mut mode) == 0 {
+            return true;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 || SetConsoleMode(console, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+// everywhere else the terminal takes them as they are
+#[cfg(not(windows))]
+fn enable_escapes() -> bool {
+    true
+}
+
+// a line typed in answer to whatever was just printed, trimmed; None once the input runs out.
+// every prompt reads through here, and so gets the line editor (see editor::read_line).
+fn read_input() -> io::Result<Option<String>> {
     io::stdout().flush()?;
-    let user_player = loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
-        break match buffer.trim() as &str {
-            "w" | "W" => mancala::Player::White,
-            "b" | "Black" => mancala::Player::Black,
-            _ => {
-                print!("Invalid option. Enter 'w' (White) or 'b' (Black): ");
-                io::stdout().flush()?;
+    let line = editor::read_line(&mut HISTORY.lock().unwrap_or_else(PoisonError::into_inner))?;
+    Ok(line.map(|line| line.trim().to_string()))
+}
+
+// --nn <file>: a network in the nn format, or with the onnx feature one exported to ONNX
+#[cfg(feature = "nn")]
+fn load_network(path: &str) -> io::Result<mancala_rs::nn::NnEval> {
+    #[cfg(feature = "onnx")]
+    if path.ends_with(".onnx") {
+        return mancala_rs::onnx::load(Path::new(path));
+    }
+    mancala_rs::nn::NnEval::load(Path::new(path))
+}
+
+fn print_result(outcome: &mancala::Outcome) {
+    match &outcome.winner {
+        Some(winner) if outcome.reason == mancala::TerminationReason::Resignation => { println!("{} wins by resignation", winner); }
+        Some(winner) if outcome.reason == mancala::TerminationReason::Timeout => { println!("{} wins on time", winner); }
+        Some(winner) if outcome.score == 0 => { println!("{} wins on the tie-break", winner); }
+        Some(winner) => { println!("{} wins by {}", winner, outcome.score.abs()); }
+        None => { println!("Draw."); }
+    }
+}
+
+// how far apart the two sides of a match are
+fn print_elo(result: &arena::Match) {
+    match arena::elo_difference(result.wdl()) {
+        Some(estimate) => { println!("Elo difference: {} for {}", estimate, result.names[0]); }
+        None => { println!("Elo difference: not enough wins and losses to tell"); }
+    }
+}
+
+fn print_ratings(results: &[arena::GameResult]) {
+    println!("Ratings ({} games):", results.len());
+    for (rank, (name, rating)) in arena::ratings(results).iter().enumerate() {
+        let games = results.iter().filter(|result| result.white == *name || result.black == *name).count();
+        println!("{:>2}. {:20} {:+6.0}  ({} games)", rank + 1, name, rating, games);
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    // defaults from the config file, for the flags the command line leaves out
+    if !args.iter().any(|arg| arg == "--no-config") {
+        let explicit = args.iter().position(|arg| arg == "--config").map(|position| args.get(position + 1).map(PathBuf::from));
+        let path = match explicit {
+            Some(Some(path)) => Some(path),
+            Some(None) => {
+                println!("--config needs a file.");
+                return Ok(());
+            },
+            None => config::Config::default_path().filter(|path| path.exists())
+        };
+        if let Some(path) = path {
+            match config::Config::load(&path) {
+                Ok(config) => config.apply(&mut args),
+                Err(error) => {
+                    println!("Couldn't read the config file {}: {}", path.display(), error);
+                    return Ok(());
+                }
+            }
+        }
+    }
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    ESCAPES.store(enable_escapes(), Ordering::Relaxed);
+    NO_CLEAR.store(has_flag("--no-clear") || !ESCAPES.load(Ordering::Relaxed), Ordering::Relaxed);
+    VERBOSE.store(has_flag("--verbose"), Ordering::Relaxed);
+    let flag_string = |flag: &str| -> Option<&String> {
+        let position = args.iter().position(|arg| arg == flag)?;
+        args.get(position + 1)
+    };
+    let flag_value = |flag: &str| -> Option<u64> { flag_string(flag)?.parse().ok() };
+    // --history <file>: where the lines typed at the prompts are kept, instead of history beside
+    // the config file. (one that can't be read just means starting without.)
+    if let Some(path) = flag_string("--history").map(PathBuf::from).or_else(editor::History::default_path).filter(|_| !has_flag("--no-history")) {
+        if let Ok(history) = editor::History::open(&path) {
+            *HISTORY.lock().unwrap_or_else(PoisonError::into_inner) = history;
+        }
+    }
+    // --seed N: where everything random (the engines' choices among near-equal moves and their
+    // noise, playouts, self-play) starts from, whatever the command, so a run can be repeated
+    let seed = flag_value("--seed").unwrap_or(0);
+    // a time budget replaces the fixed depth unless both are given
+    let movetime = flag_value("--movetime").map(Duration::from_millis);
+    let limits = engine::SearchLimits {
+        depth: flag_value("--depth").map(|depth| depth as usize).or(if movetime.is_some() { None } else { Some(DEPTH) }),
+        movetime,
+        nodes: flag_value("--nodes"),
+        ..engine::SearchLimits::default()
+    };
+    // the first argument, unless it's a flag
+    let command = args.get(1).map(String::as_str).filter(|arg| !arg.starts_with("--"));
+    if command == Some("help") || has_flag("--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let variant = match flag_string("--variant") {
+        Some(name) => match mancala::RuleSet::variant(name) {
+            Some(rules) => rules,
+            None => {
+                println!("Unknown variant {}: try one of {}.", name, mancala::RuleSet::VARIANTS.join(", "));
+                return Ok(());
+            }
+        },
+        None => mancala::RuleSet::default()
+    };
+    // the single rule flags go on top of the variant
+    let oware = has_flag("--oware-captures");
+    let rules = mancala::RuleSet {
+        layout: if has_flag("--no-stores") {
+            mancala::Layout::storeless()
+        }
+        else if has_flag("--adjacent-stores") {
+            mancala::Layout::adjacent_stores()
+        }
+        else {
+            variant.layout
+        },
+        avalanche: variant.avalanche || has_flag("--avalanche"),
+        capture: if oware { mancala::CaptureRule::Oware } else { variant.capture },
+        chain_captures: variant.chain_captures || oware,
+        must_feed: variant.must_feed || has_flag("--must-feed"),
+        ..variant
+    };
+    // --fen <position>: where games start, as the fen command writes it
+    let start = match flag_string("--fen") {
+        Some(fen) => match mancala::Node::from_fen(fen, rules) {
+            Some(node) => node,
+            None => {
+                println!("{} isn't a position under these rules.", fen);
+                return Ok(());
+            }
+        },
+        None => mancala::Node::new(rules)
+    };
+
+    // mancala-rs build-endgame-db <stones> <file> [rule flags]
+    if args.get(1).is_some_and(|arg| arg == "build-endgame-db") {
+        let (Some(stones), Some(path)) = (args.get(2).and_then(|stones| stones.parse().ok()), args.get(3)) else {
+            println!("Usage: mancala-rs build-endgame-db <stones> <file> [rule flags]");
+            return Ok(());
+        };
+        let start = Instant::now();
+        let Some(db) = endgame::EndgameDb::build(rules, stones) else {
+            println!("An endgame database needs stores, no avalanche sowing and at most {} stones.", endgame::MAX_STONES);
+            return Ok(());
+        };
+        db.save(Path::new(path))?;
+        println!("Solved every position with up to {} stones in {:.1?}.", stones, start.elapsed());
+        return Ok(());
+    }
+    // mancala-rs book build <games> <book> [--max-ply N] [--min-games N] [--winners-only] [rule flags]
+    // where <games> has one game record per line
+    if args.get(1).is_some_and(|arg| arg == "book") {
+        let (Some("build"), Some(games), Some(path)) = (args.get(2).map(String::as_str), args.get(3), args.get(4)) else {
+            println!("Usage: mancala-rs book build <games> <book> [--max-ply N] [--min-games N] [--winners-only]");
+            return Ok(());
+        };
+        let mut records = Vec::new();
+        for (number, line) in std::fs::read_to_string(games)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
                 continue;
             }
+            match game::Game::from_record(rules, line) {
+                Ok(record) => { records.push(record); }
+                Err(_) => { println!("Skipping line {}: not a legal game", number + 1); }
+            }
+        }
+        let defaults = book::BuildOptions::default();
+        let options = book::BuildOptions {
+            max_ply: flag_value("--max-ply").map_or(defaults.max_ply, |ply| ply as usize),
+            min_games: flag_value("--min-games").map_or(defaults.min_games, |games| games as u32),
+            winners_only: has_flag("--winners-only"),
+        };
+        let built = book::Book::from_games(&records, options);
+        built.save(Path::new(path))?;
+        println!("Built a book of {} positions from {} games.", built.len(), records.len());
+        return Ok(());
+    }
+    // mancala-rs tune <weights> [--games <file>] [--self-play N] [--depth N] [--iterations N] [--weights <start>] [rule flags]
+    // fits evaluation weights to how the games turned out and writes them to <weights>.
+    // the games come from a file of records, or else from the engine playing itself.
+    if args.get(1).is_some_and(|arg| arg == "tune") {
+        let Some(path) = args.get(2) else {
+            println!("Usage: mancala-rs tune <weights> [--games <file>] [--self-play N] [--depth N] [--iterations N] [--weights <start>]");
+            return Ok(());
+        };
+        let games = match flag_string("--games") {
+            Some(file) => {
+                let mut games = Vec::new();
+                for (number, line) in std::fs::read_to_string(file)?.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    match game::Game::from_record(rules, line) {
+                        Ok(game) => { games.push(game); }
+                        Err(_) => { println!("Skipping line {}: not a legal game", number + 1); }
+                    }
+                }
+                games
+            }
+            None => {
+                let count = flag_value("--self-play").map_or(200, |games| games as usize);
+                println!("Playing {} games...", count);
+                tune::self_play(rules, count, flag_value("--depth").map_or(4, |depth| depth as usize), seed)
+            }
+        };
+        let samples = tune::samples(&games);
+        // fitting nothing would write the starting weights back as if they'd been tuned
+        if samples.is_empty() {
+            println!("No positions to tune on: the games have to be finished ones. Nothing was written.");
+            return Ok(());
+        }
+        let start = match flag_string("--weights") {
+            Some(file) => eval::EvalWeights::load(Path::new(file))?,
+            None => eval::EvalWeights::default()
+        };
+        let defaults = tune::TuneOptions::default();
+        let options = tune::TuneOptions {
+            iterations: flag_value("--iterations").map_or(defaults.iterations, |iterations| iterations as usize),
+            ..defaults
         };
+        let tuned = tune::fit(&samples, &start, &options);
+        println!("{} positions from {} games: error {:.4} -> {:.4}", samples.len(), games.len(), tune::loss(&samples, &start, options.scale), tune::loss(&samples, &tuned, options.scale));
+        tuned.save(Path::new(path))?;
+        return Ok(());
+    }
+    // mancala-rs spsa <state> [--steps N] [--pairs N] [--depth N] [--out <weights>] [--weights <start>] [--seed N] [rule flags]
+    // tunes the evaluation weights by self-play, saving its progress to <state> after every step.
+    // runs until stopped unless --steps is given; run it again with the same <state> to carry on.
+    if args.get(1).is_some_and(|arg| arg == "spsa") {
+        let Some(state_path) = args.get(2).map(Path::new) else {
+            println!("Usage: mancala-rs spsa <state> [--steps N] [--pairs N] [--depth N] [--out <weights>] [--weights <start>] [--seed N]");
+            return Ok(());
+        };
+        let mut state = if state_path.exists() {
+            tune::SpsaState::load(state_path)?
+        }
+        else {
+            let start = match flag_string("--weights") {
+                Some(file) => eval::EvalWeights::load(Path::new(file))?,
+                None => eval::EvalWeights::default()
+            };
+            tune::SpsaState::new(&start, seed)
+        };
+        let defaults = tune::SpsaOptions::default();
+        let options = tune::SpsaOptions {
+            pairs: flag_value("--pairs").map_or(defaults.pairs, |pairs| pairs as usize),
+            depth: flag_value("--depth").map_or(defaults.depth, |depth| depth as usize),
+            ..defaults
+        };
+        let steps = flag_value("--steps").unwrap_or(u64::MAX);
+        for _ in 0..steps {
+            let difference = state.step(rules, &options);
+            state.save(state_path)?;
+            if let Some(out) = flag_string("--out") {
+                state.eval_weights().save(Path::new(out))?;
+            }
+            println!("step {} result {:+} weights {:?}", state.steps, difference, state.eval_weights());
+        }
+        return Ok(());
+    }
+    // mancala-rs evolve <weights> [--generations N] [--population N] [--depth N] [--threads N] [--weights <start>] [--seed N] [rule flags]
+    // evolves evaluation weights for the rules given, writing each generation's champion to <weights>
+    if args.get(1).is_some_and(|arg| arg == "evolve") {
+        let Some(path) = args.get(2).map(Path::new) else {
+            println!("Usage: mancala-rs evolve <weights> [--generations N] [--population N] [--depth N] [--threads N] [--weights <start>] [--seed N]");
+            return Ok(());
+        };
+        let start = match flag_string("--weights") {
+            Some(file) => eval::EvalWeights::load(Path::new(file))?,
+            None => eval::EvalWeights::default()
+        };
+        let defaults = tune::EvolveOptions::default();
+        let options = tune::EvolveOptions {
+            population: flag_value("--population").map_or(defaults.population, |population| population as usize),
+            depth: flag_value("--depth").map_or(defaults.depth, |depth| depth as usize),
+            threads: flag_value("--threads").map_or(defaults.threads, |threads| threads as usize),
+            ..defaults
+        };
+        let mut evolution = tune::Evolution::new(&start, &options, seed);
+        for _ in 0..flag_value("--generations").unwrap_or(20) {
+            let generation = evolution.generation(rules, &options);
+            generation.champion.save(path)?;
+            println!("generation {}: champion scored {:.0}% with {:?}", generation.number, 100.0 * generation.score, generation.champion);
+        }
+        return Ok(());
+    }
+    // mancala-rs winmodel <model> [--self-play N] [--depth N] [--seed N] [rule flags]
+    // calibrates the win probabilities in search results on the engine's own games
+    // (use the model with --win-model)
+    if args.get(1).is_some_and(|arg| arg == "winmodel") {
+        let Some(path) = args.get(2).map(Path::new) else {
+            println!("Usage: mancala-rs winmodel <model> [--self-play N] [--depth N] [--seed N]");
+            return Ok(());
+        };
+        let count = flag_value("--self-play").map_or(200, |games| games as usize);
+        let depth = flag_value("--depth").map_or(4, |depth| depth as usize);
+        println!("Playing {} games...", count);
+        let games = tune::self_play(rules, count, depth, seed);
+        let samples = tune::win_samples(&games, depth);
+        let start = eval::WinModel::default();
+        let model = tune::fit_win_model(&samples, &start, 20);
+        println!("{} positions: log loss {:.4} -> {:.4} with {:?}", samples.len(), tune::win_loss(&samples, &start), tune::win_loss(&samples, &model), model);
+        model.save(path)?;
+        return Ok(());
+    }
+    // mancala-rs selfplay <file> [--games N] [--depth N] [--eval-noise N] [--temperature X] [--weights <file>] [--seed N] [rule flags]
+    // writes every position of the games, the move played and the result to <file>
+    // (the format is described in src/selfplay/mod.rs)
+    if args.get(1).is_some_and(|arg| arg == "selfplay") {
+        let Some(path) = args.get(2).map(Path::new) else {
+            println!("Usage: mancala-rs selfplay <file> [--games N] [--depth N] [--eval-noise N] [--temperature X] [--weights <file>] [--seed N]");
+            return Ok(());
+        };
+        let defaults = selfplay::SelfPlayOptions::default();
+        let options = selfplay::SelfPlayOptions {
+            games: flag_value("--games").map_or(defaults.games, |games| games as usize),
+            depth: flag_value("--depth").map_or(defaults.depth, |depth| depth as usize),
+            eval_noise: flag_value("--eval-noise").map_or(defaults.eval_noise, |noise| noise as mancala::Score),
+            temperature: flag_string("--temperature").and_then(|temperature| temperature.parse().ok()).unwrap_or(defaults.temperature),
+            seed,
+            weights: match flag_string("--weights") {
+                Some(file) => eval::EvalWeights::load(Path::new(file))?,
+                None => defaults.weights
+            },
+        };
+        let games = selfplay::play(rules, &options);
+        let records = selfplay::records(&games);
+        selfplay::save(path, &records)?;
+        println!("Wrote {} positions from {} games.", records.len(), games.len());
+        return Ok(());
+    }
+    // mancala-rs rl <state> [--generations N] [--games N] [--depth N] [--temperature X] [--out <weights>] [--data <dir>] [--weights <start>] [--seed N] [rule flags]
+    // the whole loop: self-play, fit, gating match, keep the winner. saves its progress to <state>
+    // after every generation (run it again to carry on), the best weights to <out>, and with
+    // --data each generation's games as self-play data in <dir>.
+    if args.get(1).is_some_and(|arg| arg == "rl") {
+        let Some(state_path) = args.get(2).map(Path::new) else {
+            println!("Usage: mancala-rs rl <state> [--generations N] [--games N] [--depth N] [--temperature X] [--out <weights>] [--data <dir>] [--weights <start>] [--seed N]");
+            return Ok(());
+        };
+        let mut state = if state_path.exists() {
+            tune::RlState::load(state_path)?
+        }
+        else {
+            let start = match flag_string("--weights") {
+                Some(file) => eval::EvalWeights::load(Path::new(file))?,
+                None => eval::EvalWeights::default()
+            };
+            tune::RlState::new(&start, seed)
+        };
+        let defaults = tune::RlOptions::default();
+        let options = tune::RlOptions {
+            self_play: selfplay::SelfPlayOptions {
+                games: flag_value("--games").map_or(defaults.self_play.games, |games| games as usize),
+                depth: flag_value("--depth").map_or(defaults.self_play.depth, |depth| depth as usize),
+                temperature: flag_string("--temperature").and_then(|temperature| temperature.parse().ok()).unwrap_or(defaults.self_play.temperature),
+                ..defaults.self_play
+            },
+            ..defaults
+        };
+        if let Some(dir) = flag_string("--data") {
+            std::fs::create_dir_all(dir)?;
+        }
+        for _ in 0..flag_value("--generations").unwrap_or(u64::MAX) {
+            let generation = state.generation(rules, &options);
+            state.save(state_path)?;
+            if let Some(out) = flag_string("--out") {
+                state.best.save(Path::new(out))?;
+            }
+            if let Some(dir) = flag_string("--data") {
+                selfplay::save(&Path::new(dir).join(format!("generation-{}.mksp", generation.number)), &selfplay::records(&generation.games))?;
+            }
+            println!("generation {}: candidate scored {:.0}%, {}", generation.number, 100.0 * generation.score, if generation.accepted { "accepted" } else { "rejected" });
+        }
+        return Ok(());
+    }
+    // mancala-rs solve [--pits N] [--stones N] [--state <file>] [--nodes N] [--endgame-db <file>] [rule flags]
+    // proves the value of the starting position (small boards only, realistically). with --state
+    // the progress is saved there every million positions, and a later run carries on from it.
+    if args.get(1).is_some_and(|arg| arg == "solve") {
+        let rules = match flag_value("--pits") {
+            Some(pits) => mancala::RuleSet { layout: mancala::Layout::kalah_sized(pits as usize), ..rules },
+            None => rules
+        };
+        let root = mancala::Node::with_stones(rules, flag_value("--stones").map_or(mancala::STONES, |stones| stones as mancala::Score));
+        let state_path = flag_string("--state").map(Path::new);
+        let mut solver = match state_path.filter(|path| path.exists()) {
+            Some(path) => solve::Solver::load(path, root)?,
+            None => match solve::Solver::new(root) {
+                Some(solver) => solver,
+                None => {
+                    println!("Solving needs stores, no avalanche sowing and no move limit.");
+                    return Ok(());
+                }
+            }
+        };
+        if let Some(path) = flag_string("--endgame-db") {
+            solver.set_endgame_db(Some(Arc::new(endgame::EndgameDb::load(Path::new(path), &rules)?)));
+        }
+        let start = Instant::now();
+        let mut left = flag_value("--nodes").unwrap_or(u64::MAX);
+        while solver.value().is_none() && left > 0 {
+            let before = solver.nodes;
+            solver.run(left.min(1_000_000));
+            left = left.saturating_sub(solver.nodes - before);
+            if let Some(path) = state_path {
+                solver.save(path)?;
+            }
+            let (lower, upper) = solver.bounds();
+            println!("{} positions, {:.1?}: the value is between {:+} and {:+}", solver.nodes, start.elapsed(), lower, upper);
+        }
+        if let Some(value) = solver.value() {
+            println!("Solved: with perfect play White finishes {:+}.", value);
+        }
+        return Ok(());
+    }
+    // mancala-rs perft <depth> [rule flags]
+    // the number of lines of play from the starting position at every depth up to <depth>
+    if command == Some("perft") {
+        let Some(depth) = args.get(2).and_then(|depth| depth.parse().ok()) else {
+            println!("Usage: mancala-rs perft <depth> [rule flags]");
+            return Ok(());
+        };
+        let node = mancala::Node::new(rules);
+        for depth in 1..=depth {
+            let start = Instant::now();
+            println!("perft {:2}: {:12} ({:.1?})", depth, node.perft(depth), start.elapsed());
+        }
+        return Ok(());
+    }
+    // mancala-rs bench [--depth N]
+    // searches the built-in benchmark positions (see bench::POSITIONS) and prints the nodes, the
+    // time and a signature of the results. a build that searches differently has a different
+    // signature; best compared with release builds.
+    if args.get(1).is_some_and(|arg| arg == "bench") {
+        print!("{}", bench::run(minimax::EngineOptions::default(), flag_value("--depth").map(|depth| depth as usize)));
+        return Ok(());
+    }
+    // for engine-vs-engine games: the two engine configurations (see arena::Contestant) after
+    // the command, if both are there, and how the games go
+    let contestants = || -> Option<Result<[arena::Contestant; 2], String>> {
+        let specs = [args.get(2)?, args.get(3)?];
+        if specs.iter().any(|spec| spec.starts_with("--")) {
+            return None;
+        }
+        Some(match specs.map(|spec| arena::Contestant::parse(spec)) {
+            [Ok(first), Ok(second)] => Ok([first.with_seed(seed), second.with_seed(seed)]),
+            [Err(error), _] | [_, Err(error)] => Err(format!("Bad engine settings: {}", error))
+        })
+    };
+    const SPEC_HELP: &str = "Each engine is comma-separated settings, e.g. depth=6,personality=hoarder or mcts,movetime=500.";
+    // --resign N [--resign-moves M]: an engine gives up once it's been N stones behind with no way
+    // back for M of its moves in a row (see ResignPolicy), against a person or another engine
+    let resign_policy = flag_value("--resign").map(|threshold| engine::ResignPolicy {
+        threshold: threshold as mancala::Score,
+        moves: flag_value("--resign-moves").map_or(engine::ResignPolicy::default().moves, |moves| moves as usize),
+        ..engine::ResignPolicy::default()
+    });
+    let arena_settings = arena::GameSettings {
+        start: start.clone(),
+        adjudication: flag_value("--adjudicate").map_or(tune::Adjudication::OFF, |threshold| tune::Adjudication { threshold: Some(threshold as mancala::Score), ..tune::Adjudication::default() }),
+        resign: resign_policy,
     };
+    // mancala-rs watch <white> <black> [--delay N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]
+    // two engines play each other, move by move, with N milliseconds (default 1000) to look at
+    // each position
+    if args.get(1).is_some_and(|arg| arg == "watch") {
+        let contestants = match contestants() {
+            Some(Ok(contestants)) => contestants,
+            Some(Err(error)) => {
+                println!("{}", error);
+                return Ok(());
+            }
+            None => {
+                println!("Usage: mancala-rs watch <white> <black> [--delay N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]\n{}", SPEC_HELP);
+                return Ok(());
+            }
+        };
+        let settings = arena_settings;
+        let delay = Duration::from_millis(flag_value("--delay").unwrap_or(1000));
+        let names = contestants.clone().map(|contestant| contestant.name());
+        cls();
+        println!("{} (White) vs {} (Black)\n\n{}", names[0], names[1], settings.start);
+        let game = arena::play(&mut *contestants[0].engine()?, &mut *contestants[1].engine()?, contestants.clone().map(|contestant| contestant.limits()), &settings, |game, result| {
+            std::thread::sleep(delay);
+            let mover = game.history().last().expect("a move was just played").get_turn().clone();
+            let pv: Vec<String> = result.pv.iter().map(game::format_move).collect();
+            cls();
+            println!("{} (White) vs {} (Black), move {}\n\n{}", names[0], names[1], game.moves().len(), game.node());
+            println!("{} ({}) played {}: score {:+} at depth {}, pv {}", mover, names[mover.index()], game::format_move(game.moves().last().unwrap()), result.score, result.depth, pv.join(" "));
+        });
+        if let Some(outcome) = game.outcome() {
+            println!("Game over! ({})", outcome.reason);
+            print_result(&outcome);
+        }
+        return Ok(());
+    }
+    // mancala-rs headless [<white> <black>] [--script <file>] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]
+    // a whole game for a pipeline: the moves of a record in <file>, then (if they're given) the
+    // two engines playing it out. the last line is the result, and so is the exit status (see
+    // HEADLESS_EXIT): 2 if the game didn't get finished, 1 if it couldn't be played at all.
+    if args.get(1).is_some_and(|arg| arg == "headless") {
+        let contestants = match contestants() {
+            Some(Ok(contestants)) => Some(contestants),
+            Some(Err(error)) => {
+                println!("{}", error);
+                std::process::exit(1);
+            }
+            None => None
+        };
+        if contestants.is_none() && flag_string("--script").is_none() {
+            println!("Usage: mancala-rs headless [<white> <black>] [--script <file>] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]\n{}", SPEC_HELP);
+            std::process::exit(1);
+        }
+        let script = match flag_string("--script") {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => String::new()
+        };
+        let mut game = game::Game::from_node(start.clone());
+        if let Err(error) = game.play_record(&script) {
+            println!("The script stops at {}", error);
+            std::process::exit(1);
+        }
+        if let Some(contestants) = contestants.filter(|_| game.outcome().is_none()) {
+            game = arena::play_on(game, &mut *contestants[0].engine()?, &mut *contestants[1].engine()?, contestants.clone().map(|contestant| contestant.limits()), &arena_settings, |_, _| {});
+        }
+        println!("{}", game.annotated_record());
+        let Some(outcome) = game.outcome() else {
+            println!("unfinished");
+            std::process::exit(2);
+        };
+        let (result, status) = match outcome.winner {
+            Some(mancala::Player::White) => ("1-0", HEADLESS_EXIT[0]),
+            Some(mancala::Player::Black) => ("0-1", HEADLESS_EXIT[1]),
+            None => ("1/2-1/2", HEADLESS_EXIT[2])
+        };
+        println!("{} {:+} ({})", result, outcome.score, outcome.reason);
+        std::process::exit(status);
+    }
+    // mancala-rs match <first> <second> [--games N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]
+    //                  [--sprt <elo0>,<elo1> [--alpha P] [--beta P]] [--results <file>]
+    // N games (default 10) between two engines, taking turns with White. with --sprt, games go
+    // on (up to N, default 10000) until a sequential test decides whether the first is elo1
+    // stronger or only elo0 (see arena::Sprt)
+    if args.get(1).is_some_and(|arg| arg == "match") {
+        let contestants = match contestants() {
+            Some(Ok(contestants)) => contestants,
+            Some(Err(error)) => {
+                println!("{}", error);
+                return Ok(());
+            }
+            None => {
+                println!("Usage: mancala-rs match <first> <second> [--games N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>]");
+                println!("                        [--sprt <elo0>,<elo1> [--alpha P] [--beta P]] [--results <file>]\n{}", SPEC_HELP);
+                return Ok(());
+            }
+        };
+        if let Some(hypotheses) = flag_string("--sprt") {
+            let Some((elo0, elo1)) = hypotheses.split_once(',').and_then(|(elo0, elo1)| Some((elo0.parse().ok()?, elo1.parse().ok()?))) else {
+                println!("--sprt needs two Elo differences, e.g. --sprt 0,10");
+                return Ok(());
+            };
+            let defaults = arena::Sprt::default();
+            let sprt = arena::Sprt {
+                elo0,
+                elo1,
+                alpha: flag_string("--alpha").and_then(|alpha| alpha.parse().ok()).unwrap_or(defaults.alpha),
+                beta: flag_string("--beta").and_then(|beta| beta.parse().ok()).unwrap_or(defaults.beta),
+            };
+            let (lower, upper) = sprt.bounds();
+            let games = flag_value("--games").map_or(10_000, |games| games as usize);
+            let (result, decision) = arena::play_sprt(&contestants, &sprt, games, &arena_settings, |result| {
+                let [wins, draws, losses] = result.wdl();
+                println!("Game {}: +{} ={} -{}, LLR {:.2} ({:.2}, {:.2})", result.games.len(), wins, draws, losses, sprt.llr(result.wdl()), lower, upper);
+            })?;
+            println!("\n{}", result);
+            print_elo(&result);
+            if let Some(path) = flag_string("--results") {
+                arena::save_results(Path::new(path), &result.results())?;
+            }
+            match decision {
+                Some(true) => { println!("H1 accepted: {} is {} Elo stronger than {}.", result.names[0], elo1, result.names[1]); }
+                Some(false) => { println!("H0 accepted: {} is no more than {} Elo stronger than {}.", result.names[0], elo0, result.names[1]); }
+                None => { println!("No decision after {} games.", result.games.len()); }
+            }
+            return Ok(());
+        }
+        let games = flag_value("--games").map_or(10, |games| games as usize);
+        let result = arena::play_match(&contestants, games, &arena_settings, |result| {
+            let game = result.games.last().expect("a game was just played");
+            let [first, second] = result.score();
+            println!("Game {}: {} ({}), {} {} - {} {}", result.games.len(), game.outcome.winner.as_ref().map_or("draw".to_string(), |winner| format!("{} wins", result.names[(winner.index() + game.white) % 2])), game.outcome.reason, result.names[0], first, second, result.names[1]);
+        })?;
+        println!("\n{}", result);
+        print_elo(&result);
+        if let Some(path) = flag_string("--results") {
+            arena::save_results(Path::new(path), &result.results())?;
+        }
+        return Ok(());
+    }
+    // mancala-rs tournament <file> [--threads N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>] [--results <file>]
+    // a round robin between the engines in <file> (see arena::TournamentConfig), N games at a time
+    if args.get(1).is_some_and(|arg| arg == "tournament") {
+        let Some(path) = args.get(2) else {
+            println!("Usage: mancala-rs tournament <file> [--threads N] [--resign N [--resign-moves N]] [--adjudicate N] [--fen <position>] [--results <file>]");
+            return Ok(());
+        };
+        let config = arena::TournamentConfig::load(Path::new(path))?;
+        let config = arena::TournamentConfig { engines: config.engines.iter().map(|engine| engine.with_seed(seed)).collect(), ..config };
+        let names: Vec<String> = config.engines.iter().map(arena::Contestant::name).collect();
+        let total = config.games * names.len() * (names.len() - 1) / 2;
+        let mut played = 0;
+        let threads = flag_value("--threads").map_or(1, |threads| threads as usize);
+        let tournament = arena::play_tournament(&config, &arena_settings, threads, |[first, second], game| {
+            played += 1;
+            let white = [first, second][game.white];
+            let black = [first, second][1 - game.white];
+            let result = match game.outcome.winner {
+                Some(mancala::Player::White) => "1-0",
+                Some(mancala::Player::Black) => "0-1",
+                None => "1/2-1/2"
+            };
+            println!("{}/{}: {} - {} {} ({})", played, total, names[white], names[black], result, game.outcome.reason);
+        })?;
+        println!("\n{}", tournament);
+        print_ratings(&tournament.results());
+        if let Some(path) = flag_string("--results") {
+            arena::save_results(Path::new(path), &tournament.results())?;
+        }
+        return Ok(());
+    }
+    // mancala-rs elo <results>
+    // ratings from the games in a file written with --results by match and tournament
+    if args.get(1).is_some_and(|arg| arg == "elo") {
+        let Some(path) = args.get(2) else {
+            println!("Usage: mancala-rs elo <results>");
+            return Ok(());
+        };
+        let results = arena::load_results(Path::new(path))?;
+        print_ratings(&results);
+        return Ok(());
+    }
+    // mancala-rs stats [--profile <file>]
+    // the player's results against the AI from their profile (see profile::Profile)
+    if args.get(1).is_some_and(|arg| arg == "stats") {
+        let Some(path) = flag_string("--profile").map(PathBuf::from).or_else(profile::Profile::default_path) else {
+            println!("There's no home directory for the profile: give one with --profile <file>.");
+            return Ok(());
+        };
+        println!("{}", profile::Profile::load(&path)?);
+        return Ok(());
+    }
+    // mancala-rs replay-search <bundle> [--runs N]
+    // runs the search in a bundle written with --repro again (N times, default 1) and reports
+    // anything that came out differently. exits with status 1 if something did.
+    if args.get(1).is_some_and(|arg| arg == "replay-search") {
+        let Some(path) = args.get(2) else {
+            println!("Usage: mancala-rs replay-search <bundle> [--runs N]");
+            return Ok(());
+        };
+        let bundle = repro::Bundle::load(Path::new(path))?;
+        let mut identical = true;
+        for run in 1..=flag_value("--runs").unwrap_or(1) {
+            let differences = repro::replay(&bundle);
+            match differences.is_empty() {
+                true => println!("Run {}: identical", run),
+                false => {
+                    println!("Run {}: {} differences", run, differences.len());
+                    for difference in &differences {
+                        println!("  {}", difference);
+                    }
+                    identical = false;
+                }
+            }
+        }
+        if !identical {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // mancala-rs analyze <games> [--depth N] [--movetime N] [--fen <position>] [rule flags]
+    // a report on every game in <games> (one record per line): how good each move was, the
+    // evaluation after it, the turning points and any missed captures.
+    // mancala-rs analyze --fen <position> [--depth N] [--movetime N]: every legal move there, ranked
+    if args.get(1).is_some_and(|arg| arg == "analyze") {
+        let Some(path) = args.get(2).filter(|arg| !arg.starts_with("--")) else {
+            if flag_string("--fen").is_some() {
+                println!("{}\n", start);
+                print!("{}", analysis::rank_moves(&start, limits));
+                return Ok(());
+            }
+            println!("Usage: mancala-rs analyze <games> [--depth N] [--movetime N] [--fen <position>]\n       mancala-rs analyze --fen <position> [--depth N] [--movetime N]");
+            return Ok(());
+        };
+        // --fen: the games start from there instead
+        let mut engine = minimax::Searcher::default();
+        for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match game::Game::from_record_at(start.clone(), line) {
+                Ok(record) => {
+                    println!("Game on line {}:", number + 1);
+                    print!("{}", analysis::report(&mut engine, &record, limits, &analysis::Thresholds::default()));
+                }
+                Err(_) => { println!("Skipping line {}: not a legal game", number + 1); }
+            }
+        }
+        return Ok(());
+    }
+
+    // mancala-rs [play] [flags]
+    if let Some(command) = command.filter(|&command| command != "play") {
+        println!("Unknown command {}.\n\n{}", command, USAGE);
+        return Ok(());
+    }
+    // --load <file>: carry on with a game written by the save command, under its own rules
+    // (and with its clocks, unless there's a --time).
+    // --fen <position>: start from a position written by the fen command
+    let (mut game, saved_clock) = match flag_string("--load") {
+        Some(path) => game::Game::load(Path::new(path))?,
+        None => (game::Game::from_node(start), None)
+    };
+    let saved_clock = saved_clock.filter(|_| flag_string("--time").is_none());
+    // --moves <file>: a record of moves to play before anyone's asked for one, or with - the first
+    // line of the input. it all has to be playable, so that a bug report or study starts where it
+    // should.
+    if let Some(source) = flag_string("--moves") {
+        let record = match source.as_str() {
+            "-" => read_input()?.unwrap_or_default(),
+            path => std::fs::read_to_string(path)?
+        };
+        if let Err(error) = game.play_record(&record) {
+            println!("--moves stops at {}", error);
+            return Ok(());
+        }
+    }
+    let rules = *game.node().get_rules();
+    let endgame_db = match flag_string("--endgame-db") {
+        Some(path) => Some(Arc::new(endgame::EndgameDb::load(Path::new(path), &rules)?)),
+        None => None
+    };
+    let chosen = flag_string("--difficulty").map(|name| {
+        minimax::Difficulty::from_name(name).or_else(|| name.parse::<usize>().ok().and_then(|number| minimax::Difficulty::ALL.get(number.wrapping_sub(1)).copied()))
+    });
+    if let Some(None) = chosen {
+        println!("Unknown difficulty {}: try one of {}.", flag_string("--difficulty").unwrap(), minimax::Difficulty::ALL.map(minimax::Difficulty::name).join(", "));
+        return Ok(());
+    }
+    // --mode pvp: two people take turns at the same keyboard, each seeing the board from their side
+    let pvp = match flag_string("--mode").map(String::as_str) {
+        None | Some("ai") => false,
+        Some("pvp") => true,
+        Some(mode) => {
+            println!("Unknown mode {}: try ai or pvp.", mode);
+            return Ok(());
+        }
+    };
+    // --output json: events for another program to read (see event::Event), and nothing else
+    let json = match flag_string("--output").map(String::as_str) {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => {
+            println!("Unknown --output {}: text or json.", other);
+            return Ok(());
+        }
+    };
+    JSON.store(json, Ordering::Relaxed);
+    // --quiet: only what a script driving the game needs (see `report` below)
+    let quiet = has_flag("--quiet") || json;
+    if !quiet {
+        cls();
+    }
+    let color = match flag_string("--color").map(String::as_str) {
+        _ if pvp => Some(mancala::Player::White),
+        Some("w" | "white") => Some(mancala::Player::White),
+        Some("b" | "black") => Some(mancala::Player::Black),
+        // nobody's there to be asked
+        _ if quiet => Some(mancala::Player::White),
+        _ => None
+    };
+    match pvp {
+        _ if quiet => {}
+        true => { println!("Two players, one board: White goes first."); }
+        false => { print!("Hello! I am the Mancala Rust AI. "); }
+    }
+    if color.is_none() {
+        print!("Would you like to play as White or Black? (w/b) ");
+    }
+    io::stdout().flush()?;
+    // the end of the input at any prompt is as good as quitting
+    let user_player = match color {
+        Some(color) => color,
+        None => loop {
+            let Some(answer) = read_input()? else {
+                return Ok(());
+            };
+            break match answer.as_str() {
+                "w" | "W" => mancala::Player::White,
+                "b" | "Black" => mancala::Player::Black,
+                _ => {
+                    print!("Invalid option. Enter 'w' (White) or 'b' (Black): ");
+                    continue;
+                }
+            };
+        }
+    };
+    // the presets only exist for alpha-beta; just pressing enter keeps the command line settings.
+    // with --quiet there's no asking, but a --difficulty still counts.
+    let difficulty = if has_flag("--mcts") || pvp {
+        None
+    }
+    else if let Some(chosen) = chosen {
+        chosen
+    }
+    else if quiet {
+        None
+    }
+    else {
+        let names: Vec<String> = minimax::Difficulty::ALL.iter().enumerate().map(|(index, difficulty)| format!("{} {:?}", index + 1, difficulty)).collect();
+        print!("Choose a difficulty ({}), or press enter for the default: ", names.join(", "));
+        loop {
+            let Some(choice) = read_input()? else {
+                return Ok(());
+            };
+            if choice.is_empty() {
+                break None;
+            }
+            match choice.parse::<usize>().ok().and_then(|number| minimax::Difficulty::ALL.get(number.wrapping_sub(1))) {
+                Some(difficulty) => { break Some(*difficulty); }
+                None => { print!("Invalid option. Enter a number from 1 to {}: ", minimax::Difficulty::ALL.len()); }
+            }
+        }
+    };
+    let limits = difficulty.map_or(limits, minimax::Difficulty::limits);
+    // --time <minutes or m:ss>[+S|dS|bS]: each side gets that long for the whole game, and S
+    // seconds each move by increment or delay. the AI then thinks for a share of what it has
+    // left (and gets back) rather than to the usual depth.
+    let control = match flag_string("--time") {
+        Some(text) => match clock::TimeControl::parse(text) {
+            Some(control) => Some(control),
+            None => {
+                println!("--time takes minutes (e.g. 5) or minutes and seconds (e.g. 2:30), then optionally +, d or b and seconds (e.g. 5+3), not {}.", text);
+                return Ok(());
+            }
+        },
+        None => saved_clock.map(|saved| saved.control)
+    };
+    // --on-timeout lose|move: losing on time, or a quick move played for you instead
+    let on_timeout = match flag_string("--on-timeout") {
+        Some(name) => match clock::OnTimeout::from_name(name) {
+            Some(on_timeout) => on_timeout,
+            None => {
+                println!("Unknown --on-timeout {}: lose or move.", name);
+                return Ok(());
+            }
+        },
+        None => saved_clock.map_or(clock::OnTimeout::default(), |saved| saved.on_timeout)
+    };
+    // the clocks the game starts with: fresh ones, or the ones it was saved with
+    let start_clock = || match saved_clock {
+        Some(saved) => Some(clock::Clock::from_saved(&clock::SavedClock { on_timeout, ..saved })),
+        None => control.map(|control| clock::Clock::new(control, on_timeout))
+    };
+    let limits = match control {
+        Some(_) if difficulty.is_none() && !has_flag("--depth") => engine::SearchLimits { depth: None, ..limits },
+        _ => limits
+    };
+    // a hint is a quick look: half the AI's depth and a quarter of its time and nodes, or
+    // --hint-depth N
+    let hint_limits = match flag_value("--hint-depth") {
+        Some(depth) => engine::SearchLimits::depth(depth as usize),
+        None => engine::SearchLimits {
+            depth: limits.depth.map(|depth| (depth / 2).max(1)),
+            movetime: limits.movetime.map(|movetime| movetime / 4),
+            nodes: limits.nodes.map(|nodes| (nodes / 4).max(1)),
+            ..engine::SearchLimits::default()
+        }
+    };
+
+    // what the minimax engine runs with, for --repro
+    let mut engine_options = None;
+    let mut searcher: Box<dyn engine::Engine> = if has_flag("--mcts") {
+        // MCTS has no use for a depth, so it only looks at --movetime and --nodes
+        #[cfg_attr(not(feature = "nn"), allow(unused_mut))]
+        let mut engine = mcts::MctsEngine::new(mcts::MctsConfig {
+            playout: if has_flag("--greedy-playouts") { mcts::Playout::GreedyCapture } else { mcts::Playout::Random },
+            rollout_depth: flag_value("--rollout-depth").map(|depth| depth as usize),
+            threads: flag_value("--threads").map_or(1, |threads| threads as usize),
+            reuse_tree: true,
+            seed,
+            batch: flag_value("--batch").map_or(1, |batch| batch as usize),
+            ..mcts::MctsConfig::default()
+        });
+        // with --nn the network judges the leaves, --batch at a time
+        #[cfg(feature = "nn")]
+        if let Some(path) = flag_string("--nn") {
+            engine.set_evaluator(Some(Arc::new(load_network(path)?)));
+        }
+        Box::new(engine)
+    }
+    else {
+        let threads = flag_value("--threads").map_or(1, |threads| threads as usize);
+        let options = match difficulty {
+            Some(difficulty) => minimax::EngineOptions { threads, seed, ..difficulty.options() },
+            None => minimax::EngineOptions {
+                algorithm: if has_flag("--mtdf") { minimax::Algorithm::Mtdf } else { minimax::Algorithm::AlphaBeta },
+                threads,
+                endgame_stones: flag_value("--endgame-stones").map_or(minimax::ENDGAME_STONES, |stones| stones as mancala::Score),
+                adaptive_depth: !has_flag("--fixed-depth"),
+                contempt: flag_string("--contempt").and_then(|contempt| contempt.parse().ok()).unwrap_or(0),
+                multipv: flag_value("--multipv").map_or(1, |lines| lines as usize),
+                random_margin: flag_value("--random-margin").map(|margin| margin as mancala::Score),
+                seed,
+                strength: flag_value("--strength").map(|strength| strength as u32),
+                eval_noise: flag_value("--eval-noise").map_or(0, |noise| noise as mancala::Score),
+                quiescence: flag_value("--quiescence").map(|moves| moves as usize),
+                ..minimax::EngineOptions::default()
+            }
+        };
+        // the transposition table, for any difficulty
+        let options = minimax::EngineOptions {
+            tt_megabytes: flag_value("--hash").map(|megabytes| megabytes as usize),
+            tt_replacement: if has_flag("--always-replace") { tt::Replacement::Always } else { tt::Replacement::DepthPreferred },
+            tt_aging: !has_flag("--no-aging"),
+            ..options
+        };
+        let options = match flag_string("--personality") {
+            Some(name) => match minimax::Personality::from_name(name) {
+                Some(personality) => personality.apply(options),
+                None => {
+                    println!("Unknown --personality {}: {}.", name, minimax::Personality::ALL.map(minimax::Personality::name).join(", "));
+                    return Ok(());
+                }
+            },
+            None => options
+        };
+        let options = match flag_string("--weights") {
+            Some(path) => minimax::EngineOptions { weights: eval::EvalWeights::load(Path::new(path))?, ..options },
+            None => options
+        };
+        let options = match flag_string("--win-model") {
+            Some(path) => minimax::EngineOptions { win_model: eval::WinModel::load(Path::new(path))?, ..options },
+            None => options
+        };
+        // for experiments: --no-term capture_threat turns one evaluation term off
+        let options = match flag_string("--no-term") {
+            Some(name) => match eval::Term::from_name(name) {
+                Some(term) => minimax::EngineOptions { weights: options.weights.without(term), ..options },
+                None => {
+                    println!("Unknown --no-term {}: {}.", name, eval::Term::ALL.map(eval::Term::name).join(", "));
+                    return Ok(());
+                }
+            },
+            None => options
+        };
+        engine_options = Some(options);
+        let mut searcher = minimax::Searcher::new(options);
+        // --repro: every search starts over, with nothing a bundle couldn't hold, so the one
+        // the AI played can be written down as it was and run again
+        let repro = flag_string("--repro").is_some();
+        searcher.search_from_scratch(repro);
+        searcher.set_endgame_db(endgame_db.filter(|_| !repro));
+        #[cfg(feature = "nn")]
+        if let Some(path) = flag_string("--nn").filter(|_| !repro) {
+            searcher.set_evaluator(Some(Arc::new(load_network(path)?)));
+        }
+        // the starter book only knows the standard rules
+        let book = match flag_string("--book") {
+            Some(path) => Some(book::Book::load(Path::new(path))?),
+            None if !has_flag("--no-book") && rules == mancala::RuleSet::default() => Some(book::Book::starter()),
+            None => None
+        };
+        searcher.set_book(book.filter(|_| !repro).map(Arc::new));
+        if flag_string("--tree").is_some() {
+            searcher.record_tree(Some(flag_value("--tree-nodes").map_or(10_000, |nodes| nodes as usize)));
+        }
+        Box::new(searcher)
+    };
+    let report: engine::InfoCallback = Box::new(|info: &engine::SearchInfo| {
+        if JSON.load(Ordering::Relaxed) {
+            println!("{}", event::Event::info(info).to_json());
+            return;
+        }
+        if !VERBOSE.load(Ordering::Relaxed) {
+            return;
+        }
+        let pv: Vec<String> = info.pv.iter().map(game::format_move).collect();
+        println!("depth {} score {:+} nodes {} time {:.2}s pv {}", info.depth, info.score, info.nodes, info.time.as_secs_f64(), pv.join(" "));
+    });
+    // with --repro the iterations are kept as well, for the bundle
+    let mut repro_iterations = None;
+    let report = match flag_string("--repro").filter(|_| engine_options.is_some()) {
+        Some(_) => {
+            let (report, iterations) = repro::recorder(Some(report));
+            repro_iterations = Some(iterations);
+            report
+        }
+        None => report
+    };
+    searcher.set_info_callback(Some(report));
+
+    // the AI gives up by --resign
+    let mut resigner = resign_policy.map(engine::Resigner::new);
 
-    cls();
-    println!("{}", node);
+    // is it one of the people's move
+    let human = |player: &mancala::Player| pvp || *player == user_player;
+    // --colors auto|always|never: auto colors the board when it's going to a terminal that can
+    // show them (see ansi_terminal) and NO_COLOR isn't set
+    let colors = match flag_string("--colors").map(String::as_str) {
+        None | Some("auto") => ansi_terminal(),
+        Some("always") => true,
+        Some("never") => false,
+        Some(other) => {
+            println!("Unknown --colors {}: auto, always or never.", other);
+            return Ok(());
+        }
+    };
+    // a finished game against the AI goes into the player's profile, with their accuracy if it
+    // was analyzed, unless --no-profile. (games played from a script with --quiet don't count.)
+    let profile_path = flag_string("--profile").map(PathBuf::from).or_else(profile::Profile::default_path).filter(|_| !pvp && !quiet && !has_flag("--no-profile"));
+    let add_to_profile = |game: &game::Game, accuracy: Option<f64>| {
+        let (Some(path), Some(outcome)) = (&profile_path, game.outcome()) else {
+            return;
+        };
+        let added = profile::Profile::load(path).and_then(|mut profile| {
+            profile.add(difficulty.map_or(profile::CUSTOM, minimax::Difficulty::name), &outcome, &user_player, accuracy);
+            profile.save(path)
+        });
+        if let Err(error) = added {
+            println!("Couldn't update the profile {}: {}", path.display(), error);
+        }
+    };
+    // --tui: the whole game on one full screen, played with the arrow keys or letters
+    if has_flag("--tui") {
+        // (progress lines would scribble over the screen)
+        searcher.set_info_callback(None);
+        let humans = [human(&mancala::Player::White), human(&mancala::Player::Black)];
+        let played = tui::run(&mut game, &mut *searcher, limits, humans, colors, start_clock(), on_timeout);
+        add_to_profile(&game, None);
+        return played;
+    }
+    // --display ascii|unicode|compact: the plain board, one drawn with box lines or one small
+    // enough for a narrow window. without it the board is plain unless the terminal is too
+    // narrow for that.
+    let columns = display::terminal_width().filter(|_| flag_string("--display").is_none());
+    let Some(style) = display::Style::from_name(flag_string("--display").map_or("ascii", String::as_str)) else {
+        println!("Unknown --display {}: ascii, unicode or compact.", flag_string("--display").unwrap());
+        return Ok(());
+    };
+    // --eval-bar: how the game stands under the board, from the AI's latest search of the position
+    // shown or, when there isn't one, a search as long as a hint's
+    let latest_eval: std::cell::RefCell<Option<(mancala::Node, mancala::Score, f64)>> = std::cell::RefCell::new(None);
+    let clock = std::cell::RefCell::new(start_clock());
+    let view = |game: &game::Game| {
+        let node = game.node();
+        let mut view = display::View {
+            // your own side nearest you, with the pits labelled by what to type for them
+            bottom: if pvp { node.get_turn().clone() } else { user_player.clone() },
+            style,
+            colors,
+            labels: true,
+            ..display::View::default()
+        };
+        // what the last full move did, so it can still be seen once the screen's cleared
+        if let (Some(before), Some(mv)) = (game.history().last(), game.moves().last()) {
+            view = view.with_last_move(before, mv);
+        }
+        view
+    };
+    let draw = |node: &mancala::Node, view: &display::View| match columns {
+        Some(columns) => display::render_within(node, view, columns),
+        None => display::render(node, view)
+    };
+    let board = |game: &game::Game| {
+        let node = game.node();
+        let mut text = draw(node, &view(game));
+        // with nothing cleared, a header to find each move by when scrolling back
+        if let (true, Some(before), Some(mv)) = (has_flag("--no-clear"), game.history().last(), game.moves().last()) {
+            text = format!("== move {}: {} played {} ==\n{}", game.moves().len(), before.get_turn(), game::format_move(mv), text);
+        }
+        if let Some(clock) = clock.borrow().as_ref() {
+            text = format!("{}\n{}", text, clock);
+        }
+        if !has_flag("--eval-bar") || node.is_over() {
+            return text;
+        }
+        let mut latest = latest_eval.borrow_mut();
+        if latest.as_ref().is_none_or(|(seen, _, _)| seen != node) {
+            let result = minimax::Searcher::default().search(node, hint_limits);
+            *latest = Some((node.clone(), result.score, result.win_prob));
+        }
+        let (_, score, win_prob) = latest.as_ref().unwrap();
+        format!("{}\n{}", text, display::eval_bar(*score, *win_prob))
+    };
+    // --animate N: show a move being sown a stone at a time, N milliseconds apart
+    let delay = flag_value("--animate").map(Duration::from_millis);
+    let animate = |game: &game::Game, mv: &[mancala::Pocket]| {
+        // (frames that aren't cleared away would only be clutter)
+        let Some(delay) = delay.filter(|_| !has_flag("--no-clear") && !quiet) else { return; };
+        let view = display::View { last_move: mv.to_vec(), captured: Vec::new(), ..view(game) };
+        let mut node = game.node().clone();
+        for &pocket in mv {
+            let Ok(frames) = node.sowing(pocket) else { return; };
+            for frame in frames {
+                cls();
+                println!("{}", draw(&frame, &view));
+                std::thread::sleep(delay);
+                node = frame;
+            }
+        }
+    };
+    // with --quiet, each full move once it's been played, as "White 2-5" (or a move event)
+    let reported = std::cell::Cell::new(0);
+    let report = |game: &game::Game| {
+        for (before, mv) in game.history().iter().zip(game.moves()).skip(reported.get()) {
+            match json {
+                true => { println!("{}", event::Event::played(before, mv).to_json()); }
+                false if quiet => { println!("{} {}", before.get_turn(), game::format_move(mv)); }
+                false => {}
+            }
+        }
+        reported.set(game.moves().len());
+    };
+    // the board, unless --quiet. as events, each position once, after the move that led to it.
+    // `shown` is the last one shown, so the AI doesn't show it again before its move.
+    let shown: std::cell::RefCell<Option<mancala::Node>> = std::cell::RefCell::new(None);
+    let show = |game: &game::Game| {
+        if json {
+            report(game);
+            if shown.borrow().as_ref() != Some(game.node()) {
+                println!("{}", event::Event::board(game.node()).to_json());
+                *shown.borrow_mut() = Some(game.node().clone());
+            }
+        }
+        else if !quiet {
+            cls();
+            println!("{}", board(game));
+            *shown.borrow_mut() = Some(game.node().clone());
+        }
+    };
+    // whether the side to move ran out of time before they moved; if so it's dealt with (see
+    // clock::time_up) and said what happened
+    let time_up = |game: &mut game::Game| {
+        if clock.borrow().as_ref().and_then(clock::Clock::flagged).is_none() {
+            return false;
+        }
+        let turn = game.node().get_turn().clone();
+        let played = clock::time_up(game, on_timeout, human(&turn));
+        show(game);
+        match played {
+            Some(mv) => { say(&format!("{}'s time is up: played {} for them.", turn, game::format_move(&mv))); }
+            None => { say(&format!("{}'s time is up.", turn)); }
+        }
+        true
+    };
+    // the AI's searches, with the number of the move each was for, for the summary at the end
+    let mut searches = Vec::new();
+    // (a game carried on from --load or --moves already has its moves)
+    reported.set(game.moves().len());
+    show(&game);
 
     loop {
-        if node.children().is_empty() {
-            println!("Game over!");
-            let final_score = node.final_score();
-            if final_score > 0 {
-                println!("White wins by {}", final_score);
+        report(&game);
+        // the clock of whoever's to move runs until they've finished their move
+        if let Some(clock) = clock.borrow_mut().as_mut() {
+            match game.outcome() {
+                Some(_) => clock.stop(),
+                None => clock.start(game.node().get_turn())
             }
-            else if final_score < 0 {
-                println!("Black wins by {}", -1 * final_score);
+        }
+        if let Some(outcome) = game.outcome() {
+            if let Some(path) = flag_string("--record") {
+                let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", game.annotated_record())?;
             }
-            else {
-                println!("Draw.");
+            match json {
+                true => { println!("{}", event::Event::end(&outcome, &game).to_json()); }
+                false => {
+                    println!("Game over! ({})", outcome.reason);
+                    print_result(&outcome);
+                    if !quiet {
+                        println!("\n{}", summary::Summary::new(&game, &searches));
+                    }
+                }
             }
+            // --analyze: the post-game report (see the analyze subcommand)
+            let report = has_flag("--analyze").then(|| analysis::report(&mut minimax::Searcher::default(), &game, limits, &analysis::Thresholds::default()));
+            if let Some(report) = &report {
+                say(&report.to_string());
+            }
+            add_to_profile(&game, report.and_then(|report| report.accuracy(&user_player)));
             break;
         }
-        if *node.get_turn() == user_player {
+        let node = game.node().clone();
+        if human(node.get_turn()) {
             loop {
-                print!("Enter move: ");
-                io::stdout().flush()?;
-                let mut buffer = String::new();
-                io::stdin().read_line(&mut buffer)?;
-                match buffer.trim().parse() {
-                    Ok(v) => {
-                        match node.sub_move(v) {
-                            Ok(_) => {
-                                cls();
-                                println!("{}", node);
+                // the pits that can be sown from now, by their letters
+                let mut starts: Vec<mancala::Pocket> = game.node().legal_moves().iter().filter_map(|mv| mv.first().copied()).collect();
+                starts.sort();
+                starts.dedup();
+                let starts: Vec<String> = starts.into_iter().map(|pocket| display::pit_label(game.node(), pocket)).collect();
+                if !quiet {
+                    print!("Legal: {}\nEnter move (or help): ", starts.join(" "));
+                }
+                let Some(line) = read_input()? else {
+                    return Ok(());
+                };
+                // whatever was typed came too late
+                if time_up(&mut game) {
+                    break;
+                }
+                // a letter is one of the pits of whoever's moving
+                let turn = game.node().get_turn().clone();
+                let parsed = command::Command::parse(&line).and_then(|command| match command {
+                    command::Command::Pit(index) => game.node().pits(&turn).nth(index).map(command::Command::Move).ok_or_else(|| format!("There's no pit {} on your side.", line.trim().to_uppercase())),
+                    command => Ok(command)
+                });
+                match parsed {
+                    Ok(command::Command::Move(pocket)) => match game.clone().sub_move(pocket) {
+                        Ok(_) => {
+                            animate(&game, &[pocket]);
+                            game.sub_move(pocket).expect("it was just played on a copy");
+                            show(&game);
+                            break;
+                        }
+                        Err(_) => { say("Invalid move, please try again."); }
+                    },
+                    Ok(command::Command::Hint { eval }) => {
+                        // its own searcher, so the AI's search lines don't get printed
+                        let result = minimax::Searcher::default().search(game.node(), hint_limits);
+                        match result.best_move {
+                            Some(mv) if eval => {
+                                let score = if *game.node().get_turn() == mancala::Player::White { result.score } else { -result.score };
+                                say(&format!("Hint: {} (score {:+} for you at depth {})", game::format_move(&mv), score, result.depth));
+                            }
+                            Some(mv) => { say(&format!("Hint: {}", game::format_move(&mv))); }
+                            None => { say("No moves to suggest."); }
+                        }
+                    }
+                    Ok(command::Command::Analyze(depth)) => {
+                        let limits = depth.map_or(hint_limits, engine::SearchLimits::depth);
+                        say(&analysis::rank_moves(game.node(), limits).to_string());
+                    }
+                    Ok(command::Command::Undo) => {
+                        // back to before your last move, taking the AI's reply with it
+                        let mut rewound = game.clone();
+                        let mut undone = 0;
+                        while rewound.undo().is_some() {
+                            undone += 1;
+                            if human(rewound.node().get_turn()) {
                                 break;
                             }
-                            Err(_) => {
-                                println!("Invalid input, please try again.");
-                                continue;
+                        }
+                        if undone > 0 && human(rewound.node().get_turn()) {
+                            game = rewound;
+                            // nothing the AI worked out along the line that's gone carries over
+                            searcher.takeback();
+                            if let Some(resigner) = resigner.as_mut() {
+                                resigner.reset();
                             }
+                            show(&game);
+                        }
+                        else {
+                            say("Nothing to undo.");
                         }
                     }
-                    Err(_) => {
-                        println!("Invalid input, please try again.");
-                        continue;
+                    Ok(command::Command::Redo) => {
+                        if game.redo().is_none() {
+                            say("Nothing to redo.");
+                            continue;
+                        }
+                        // the AI's reply too, if it was taken back with your move
+                        while !human(game.node().get_turn()) && game.outcome().is_none() && game.redo().is_some() {}
+                        show(&game);
+                        // otherwise it's the AI's move, or the game's over
+                        if !human(game.node().get_turn()) || game.outcome().is_some() {
+                            break;
+                        }
+                    }
+                    Ok(command::Command::Show) => {
+                        show(&game);
+                    }
+                    Ok(command::Command::Moves) => {
+                        let moves: Vec<String> = game.node().legal_moves().iter().map(game::format_move).collect();
+                        say(&format!("Legal moves: {}", moves.join(" ")));
                     }
+                    Ok(command::Command::Fen) => { say(&game.node().fen()); }
+                    Ok(command::Command::SetBoard(fen)) => match mancala::Node::from_fen(&fen, rules) {
+                        Some(node) => {
+                            game = game::Game::from_node(node);
+                            show(&game);
+                            // it may be the AI's move now
+                            if !human(game.node().get_turn()) || game.outcome().is_some() {
+                                break;
+                            }
+                        }
+                        None => { say(&format!("{} isn't a position under these rules.", fen)); }
+                    },
+                    Ok(command::Command::Save(path)) => match game.save(&path, clock.borrow().as_ref().map(clock::Clock::saved)) {
+                        Ok(_) => { say(&format!("Saved the game to {}.", path.display())); }
+                        Err(error) => { say(&format!("Couldn't save to {}: {}", path.display(), error)); }
+                    },
+                    Ok(command::Command::OfferDraw) => {
+                        if game.offer_draw().is_err() {
+                            say("Finish your move first.");
+                            continue;
+                        }
+                        let taken = if pvp {
+                            if !quiet {
+                                print!("{} offers a draw. {}, do you accept? (y/n) ", turn, turn.toggled());
+                            }
+                            read_input()?.is_some_and(|answer| answer.to_lowercase().starts_with('y'))
+                        }
+                        else {
+                            // the AI takes it if it doesn't expect better from playing on. (a
+                            // searcher of its own, so the AI's search lines don't get printed)
+                            let result = engine_options.map_or_else(minimax::Searcher::default, minimax::Searcher::new).search(game.node(), hint_limits);
+                            engine::accepts_draw(&result, &turn.toggled(), engine_options.map_or(0, |options| options.contempt))
+                        };
+                        if taken {
+                            game.agree_draw().expect("the game isn't over");
+                            break;
+                        }
+                        say("Draw declined.");
+                    }
+                    Ok(command::Command::Resign) => match game.resign(game.node().get_turn().clone()) {
+                        Ok(_) => { break; }
+                        Err(_) => { say("Finish your move first."); }
+                    },
+                    Ok(command::Command::Pit(_)) => unreachable!("letters were turned into pits above"),
+                    Ok(command::Command::Quit) => { return Ok(()); }
+                    Ok(command::Command::Verbose(on)) => {
+                        VERBOSE.store(on, Ordering::Relaxed);
+                        say(&format!("The AI's search {} be shown.", if on { "will" } else { "won't" }));
+                    }
+                    Ok(command::Command::Help) => { say(command::HELP); }
+                    Err(message) => { say(&message); }
                 }
             };
         }
         else {
-            cls();
-            println!("{}", node);
-            println!("AI is thinking...");
-            let mut alpha = mancala::Score::MIN;
-            let mut beta = mancala::Score::MAX;
-            let (wrapped_best_move, score) = minimax::minimax(&node, DEPTH, &mut alpha, &mut beta);
-            let best_move = wrapped_best_move.unwrap();
-            match node.full_move(&best_move) {
+            // (the position the player's move left is on screen already)
+            if shown.borrow().as_ref() != Some(&node) {
+                show(&game);
+            }
+            // with --verbose the depths it finishes say as much
+            if !VERBOSE.load(Ordering::Relaxed) && !quiet {
+                println!("AI is thinking...");
+            }
+            let search_limits = clock.borrow().as_ref().map_or(limits, |clock| clock.limits(node.get_turn(), limits));
+            if let Some(iterations) = &repro_iterations {
+                iterations.try_iter().for_each(drop);
+            }
+            let result = searcher.search(&node, search_limits);
+            if time_up(&mut game) {
+                continue;
+            }
+            // --repro <file>: the AI's latest search, written down for replay-search to check
+            if let (Some(path), Some(options), Some(iterations)) = (flag_string("--repro"), engine_options, &repro_iterations) {
+                repro::record(&node, options, search_limits, iterations.try_iter().collect(), &result).save(Path::new(path))?;
+            }
+            // --tree <file>: the tree the AI explored for its latest move, as DOT for a .dot file and JSON otherwise
+            if let (Some(path), Some(tree)) = (flag_string("--tree"), searcher.tree()) {
+                std::fs::write(path, if path.ends_with(".dot") { tree.to_dot() } else { tree.to_json() })?;
+            }
+            if resigner.as_mut().is_some_and(|resigner| resigner.should_resign(&result, node.get_turn())) {
+                say(&format!("AI resigns: it sees no way back from {:+}.", result.score));
+                game.resign(node.get_turn().clone()).expect("the game isn't over");
+                continue;
+            }
+            let best_move = result.best_move.clone().unwrap();
+            animate(&game, &best_move);
+            searches.push((game.moves().len(), result.clone()));
+            match game.play(&best_move) {
                 Ok(_) => {
-                    cls();
-                    println!("{}", node);
-                    println!("AI moved: {:?}", best_move);
+                    *latest_eval.borrow_mut() = Some((game.node().clone(), result.score, result.win_prob));
+                    show(&game);
+                    if !quiet {
+                        println!("AI moved: {}", display::move_label(&node, &best_move));
+                    }
+                    if let Some(moves) = result.ends_in.filter(|_| !quiet) {
+                        let winner = if result.score > 0 { mancala::Player::White } else { mancala::Player::Black };
+                        println!("{} wins by {} in {} moves with best play", winner, result.score.abs(), moves);
+                    }
+                    // --show-expected: what the AI thought you'd answer, and how it saw the game going from there
+                    if let Some(reply) = result.expected_reply().filter(|_| has_flag("--show-expected")) {
+                        let rest: Vec<String> = result.pv[2..].iter().map(game::format_move).collect();
+                        say(&format!("AI expects you to play {} (score {:+}), then {}", game::format_move(reply), result.score, if rest.is_empty() { "-".to_string() } else { rest.join(" ") }));
+                    }
+                    if result.lines.len() > 1 {
+                        for (rank, line) in result.lines.iter().enumerate() {
+                            let pv: Vec<String> = line.pv.iter().map(game::format_move).collect();
+                            say(&format!("{}. {:+} {}", rank + 1, line.score, pv.join(" ")));
+                        }
+                    }
                 },
-                Err(_) => { println!("Error occurred when playing move"); }
+                Err(_) => { say("Error occurred when playing move"); }
             }
         }
     }
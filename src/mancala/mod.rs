@@ -1,3 +1,7 @@
+use super::rng;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashSet;
 use std::fmt;
 
 // a pocket on the board (aliased as a u8)
@@ -5,20 +9,30 @@ pub type Pocket = usize;
 pub type Move = Vec<Pocket>;
 pub type Score = i32;
 
+// the standard Kalah board (see Layout::kalah)
 pub const BOARD_SIZE: Pocket = 14;
 pub const STONES: Score = 4;
 pub const WHITE_POCKET: Pocket = 6;
 pub const BLACK_POCKET: Pocket = 13;
+pub const PITS: Pocket = 6;
 
 // either White's or Black's turn
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Player {
     White,
     Black,
 }
 
 impl Player {
-    fn toggled(&self) -> Self {
+    // index into per-player arrays (White first)
+    pub fn index(&self) -> usize {
+        match *self {
+            Player::White => 0,
+            Player::Black => 1
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
         match *self {
             Player::White => Player::Black,
             Player::Black => Player::White
@@ -35,104 +49,579 @@ impl fmt::Display for Player {
     }
 }
 
+// what happens when the last stone of a sub-move lands in a pit
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum CaptureRule {
+    // Kalah: landing in one of our own empty pits captures it along with the opposite pit
+    #[default]
+    Opposite,
+    // Oware: landing in an opponent pit and bringing it to 2 or 3 stones captures that pit
+    Oware,
+}
+
+// who collects the stones still on the board when the game ends
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum SweepRule {
+    // stones stay with the side they're on, i.e. the opponent of whoever ran out (standard Kalah)
+    #[default]
+    Opponent,
+    // the player whose side ran empty sweeps everything that's left
+    Emptier,
+}
+
+// how a game that ends level is decided
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TieBreak {
+    #[default]
+    Draw,
+    // the player whose side ran empty wins the tie
+    Emptier,
+    // the player who still had stones wins the tie
+    Opponent,
+}
+
+// the shape of the board. pockets are numbered in sowing order; each player owns the `pits`
+// consecutive pockets starting at their first pit, plus optionally a store somewhere on the ring.
+// arrays are indexed by Player::index.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub size: Pocket,
+    // pits per side, not counting the stores
+    pub pits: Pocket,
+    pub first_pit: [Pocket; 2],
+    // store-less boards (Oware) keep captured stones in a separate count on the node instead
+    pub stores: [Option<Pocket>; 2],
+}
+
+impl Layout {
+    pub fn kalah() -> Self {
+        Layout {
+            size: BOARD_SIZE,
+            pits: PITS,
+            first_pit: [0, WHITE_POCKET + 1],
+            stores: [Some(WHITE_POCKET), Some(BLACK_POCKET)],
+        }
+    }
+
+    // Kalah with fewer (or more) pits a side, for small versions of the game
+    pub fn kalah_sized(pits: Pocket) -> Self {
+        Layout {
+            size: 2 * pits + 2,
+            pits,
+            first_pit: [0, pits + 1],
+            stores: [Some(pits), Some(2 * pits + 1)],
+        }
+    }
+
+    // twelve pits and no stores
+    pub fn storeless() -> Self {
+        Layout {
+            size: 2 * PITS,
+            pits: PITS,
+            first_pit: [0, PITS],
+            stores: [None, None],
+        }
+    }
+
+    // both stores next to each other, White's then Black's, between White's last pit and Black's
+    // first: White sows into its store and skips Black's, and Black sows around the end of the
+    // board and along White's pits, skips White's store and only then reaches its own
+    pub fn adjacent_stores() -> Self {
+        Layout {
+            size: BOARD_SIZE,
+            pits: PITS,
+            first_pit: [0, PITS + 2],
+            stores: [Some(PITS), Some(PITS + 1)],
+        }
+    }
+
+    // some games (e.g. Bao, Omweso) have two ranks per player. flattened into sowing order,
+    // each player just owns `ranks * pits_per_rank` pits in a row.
+    pub fn flattened(ranks: Pocket, pits_per_rank: Pocket) -> Self {
+        let pits = ranks * pits_per_rank;
+        Layout {
+            size: 2 * pits,
+            pits,
+            first_pit: [0, pits],
+            stores: [None, None],
+        }
+    }
+
+    // how far round the board Black's side is from White's
+    fn half_turn(&self) -> Pocket {
+        self.first_pit[1].wrapping_sub(self.first_pit[0])
+    }
+
+    // whether turning the board half-way round swaps the two sides exactly, pits and stores,
+    // so that a position with the players swapped (see Node::mirrored) is a position of the same
+    // game. neither side may wrap round the end of the board, so both list their moves in the
+    // same order. adjacent stores aren't symmetric: only Black sows past both.
+    pub fn is_symmetric(&self) -> bool {
+        let half = self.half_turn();
+        let turned = |store: Option<Pocket>| store.map(|pocket| (pocket + half) % self.size);
+        self.first_pit[0] < self.first_pit[1] && 2 * half == self.size && self.first_pit[1] + self.pits <= self.size
+            && self.stores[1] == turned(self.stores[0])
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::kalah()
+    }
+}
+
+// house rules that change how a move is played out.
+// the default is plain Kalah.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub layout: Layout,
+    // "avalanche" sowing: if the last stone lands in a non-empty pit (other than our store),
+    // pick that pit up and keep sowing. the sub-move only ends on a store or an empty pit.
+    pub avalanche: bool,
+    pub capture: CaptureRule,
+    // when a capture triggers, also capture the contiguous run of pits before the landing pit
+    // that satisfy the same condition (the usual Oware rule)
+    pub chain_captures: bool,
+    pub sweep: SweepRule,
+    pub tie_break: TieBreak,
+    // if the opponent's side is empty, we must play a move that gives them stones (if we have one)
+    pub must_feed: bool,
+    // end the game after this many full moves, scoring the stones as they stand
+    pub move_limit: Option<usize>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            layout: Layout::kalah(),
+            avalanche: false,
+            capture: CaptureRule::Opposite,
+            chain_captures: false,
+            sweep: SweepRule::Opponent,
+            tie_break: TieBreak::Draw,
+            must_feed: false,
+            move_limit: None,
+        }
+    }
+}
+
+impl RuleSet {
+    // names for RuleSet::variant
+    pub const VARIANTS: [&'static str; 4] = ["kalah", "oware", "avalanche", "adjacent"];
+
+    // a named set of house rules: plain Kalah, Oware (no stores, chained captures of 2s and 3s
+    // on the opponent's side, and the opponent has to be fed), Kalah with avalanche sowing, or
+    // Kalah with the stores side by side
+    pub fn variant(name: &str) -> Option<Self> {
+        let kalah = RuleSet::default();
+        match name {
+            "kalah" => Some(kalah),
+            "oware" => Some(RuleSet { layout: Layout::storeless(), capture: CaptureRule::Oware, chain_captures: true, must_feed: true, ..kalah }),
+            "avalanche" => Some(RuleSet { avalanche: true, ..kalah }),
+            "adjacent" => Some(RuleSet { layout: Layout::adjacent_stores(), ..kalah }),
+            _ => None
+        }
+    }
+}
+
+// why a game ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerminationReason {
+    // the side to move has no stones to sow, the opponent's are swept
+    NoStones,
+    // both sides ran out at the same time, nothing is left to sweep
+    BothSidesEmpty,
+    // the game hit the ruleset's move limit
+    MoveLimit,
+    // a position repeated (only possible on some variants, e.g. store-less boards)
+    Repetition,
+    // a player gave up (see Game::resign)
+    Resignation,
+    // a player's clock ran out (see Game::time_out)
+    Timeout,
+    // a draw was offered and taken (see Game::agree_draw)
+    Agreement,
+    // called early in an engine match (see tune::Adjudication)
+    Adjudication,
+}
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            TerminationReason::NoStones => "no stones left to move",
+            TerminationReason::BothSidesEmpty => "both sides empty",
+            TerminationReason::MoveLimit => "move limit reached",
+            TerminationReason::Repetition => "position repeated",
+            TerminationReason::Resignation => "resignation",
+            TerminationReason::Timeout => "time forfeit",
+            TerminationReason::Agreement => "draw agreed",
+            TerminationReason::Adjudication => "adjudicated"
+        })
+    }
+}
+
+// the result of a finished game
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    // None for a draw
+    pub winner: Option<Player>,
+    // final stone difference (White minus Black) after the sweep
+    pub score: Score,
+    pub reason: TerminationReason,
+}
+
+// what happened during a (sub-)move, collected while generating children so that
+// the search can order moves without replaying them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MoveInfo {
+    // stones moved into the mover's score by captures, including the capturing stones
+    pub captured: Score,
+    // sub-moves that ended in the mover's store and earned another sowing
+    pub extra_turns: usize,
+}
+
+impl MoveInfo {
+    fn merge(&mut self, other: &MoveInfo) {
+        self.captured += other.captured;
+        self.extra_turns += other.extra_turns;
+    }
+}
+
+// what a player could do with one sowing if it were their turn
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Threats {
+    // pits they could sow at all
+    pub moves: usize,
+    // pits whose sowing ends in the player's store
+    pub extra_turns: usize,
+    // stones in the pits whose sowing puts at least one stone in the store
+    pub reach_store: Score,
+    // the most stones a single sowing would capture
+    pub capture: Score,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Error {
     IndexError, // pocket number not within valid range
-    EmptyError // pocket chosen is empty
+    EmptyError, // pocket chosen is empty
+    IllegalError, // not a complete legal move in this position
+    GameOverError // the game has already ended
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Error::IndexError => "not a pit of the player to move",
+            Error::EmptyError => "the pit is empty",
+            Error::IllegalError => "not a legal move here",
+            Error::GameOverError => "the game is already over"
+        })
+    }
 }
 
 // used to represent board positions, including ones in the "middle" of a move
 // we may get multiple "sub-moves" if we "land" on our own pocket
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Node {
     board: Vec<Score>,
     turn: Player,
+    rules: RuleSet,
+    // captured stones per player, for boards without scoring pockets (always 0 otherwise)
+    scores: [Score; 2],
 }
 
 impl Node {
+    // the starting position under the given house rules
+    pub fn new(rules: RuleSet) -> Self {
+        Self::with_stones(rules, STONES)
+    }
+
+    // the starting position with `stones` in every pit instead of the usual four
+    pub fn with_stones(rules: RuleSet, stones: Score) -> Self {
+        let mut node = Node {
+            board: vec![0; rules.layout.size],
+            turn: Player::White,
+            rules,
+            scores: [0; 2],
+        };
+        for player in [Player::White, Player::Black] {
+            for pocket in node.pits(&player) {
+                node.board[pocket] = stones;
+            }
+        }
+        node
+    }
+
+    // an arbitrary position; the board has to match the ruleset's layout
+    pub fn from_board(board: &[Score], turn: Player, rules: RuleSet) -> Result<Self, Error> {
+        if board.len() != rules.layout.size {
+            return Err(Error::IndexError);
+        }
+        Ok(Node {
+            board: board.to_vec(),
+            turn,
+            rules,
+            scores: [0; 2],
+        })
+    }
+
+    fn size(&self) -> Pocket {
+        self.board.len()
+    }
+
+    // the pit facing `pocket` across the board: the k-th pit of one side faces the k-th from last
+    // on the other.
     // note: no error checking since this is an internal helper method.
     fn opposite(&self, pocket: Pocket) -> Pocket {
-        (BOARD_SIZE - 2) - pocket
+        let layout = &self.rules.layout;
+        let (own, other) = match self.pits(&Player::White).contains(&pocket) {
+            true => (layout.first_pit[0], layout.first_pit[1]),
+            false => (layout.first_pit[1], layout.first_pit[0])
+        };
+        other + (layout.pits - 1) - (pocket - own)
+    }
+
+    // the pits (not including the scoring pocket) that belong to a player
+    pub fn pits(&self, player: &Player) -> std::ops::Range<Pocket> {
+        let first = self.rules.layout.first_pit[player.index()];
+        first..first + self.rules.layout.pits
+    }
+
+    // the scoring pocket of a player, if the board has them
+    pub fn store(&self, player: &Player) -> Option<Pocket> {
+        self.rules.layout.stores[player.index()]
+    }
+
+    // stones a player has banked, either in their store or in the separate score count
+    pub fn score(&self, player: &Player) -> Score {
+        match self.store(player) {
+            Some(pocket) => self.board[pocket],
+            None => self.scores[player.index()]
+        }
+    }
+
+    fn add_score(&mut self, player: &Player, stones: Score) {
+        match self.store(player) {
+            Some(pocket) => { self.board[pocket] += stones; }
+            None => { self.scores[player.index()] += stones; }
+        }
+    }
+
+    // whether the side to move would capture `pocket` if a sub-move ended there,
+    // given the board after sowing
+    fn can_capture(&self, pocket: Pocket) -> bool {
+        match self.rules.capture {
+            CaptureRule::Opposite => {
+                self.pits(&self.turn).contains(&pocket) && self.board[pocket] == 1
+            }
+            CaptureRule::Oware => {
+                self.pits(&self.turn.toggled()).contains(&pocket) && (2..=3).contains(&self.board[pocket])
+            }
+        }
+    }
+
+    // the pits captured by the side to move when its last stone lands on `cursor`.
+    // shared by sub_move and anything that wants to look for capture threats.
+    fn captures(&self, cursor: Pocket) -> Vec<Pocket> {
+        let mut result = Vec::new();
+        let mut pocket = cursor;
+        while self.can_capture(pocket) {
+            result.push(pocket);
+            if !self.rules.chain_captures { break; }
+            pocket = (pocket + self.size() - 1) % self.size();
+        }
+        result
+    }
+
+    pub fn threats(&self, player: &Player) -> Threats {
+        let mut threats = Threats::default();
+        let mut node = self.clone();
+        node.turn = player.clone();
+        for pocket in node.pits(player) {
+            let mut child = node.clone();
+            if let Ok(info) = child.sow(pocket) {
+                threats.moves += 1;
+                threats.extra_turns += info.extra_turns;
+                if child.score(player) - info.captured > node.score(player) {
+                    threats.reach_store += node.board[pocket];
+                }
+                threats.capture = cmp::max(threats.capture, info.captured);
+            }
+        }
+        threats
+    }
+
+    // stones in a player's pits that the other side could capture by landing in the right place:
+    // ones facing an empty pit with the opposite rule, and pits of 1 or 2 with the Oware rule
+    pub fn exposed(&self, player: &Player) -> Score {
+        self.pits(player).map(|pocket| (pocket, self.board[pocket])).filter(|&(pocket, stones)| match self.rules.capture {
+            CaptureRule::Opposite => stones > 0 && self.board[self.opposite(pocket)] == 0,
+            CaptureRule::Oware => (1..=2).contains(&stones)
+        }).map(|(_, stones)| stones).sum()
     }
 
     pub fn sub_move(&mut self, pocket: Pocket) -> Result<(), Error> {
-        let (own_pocket, enemy_pocket, start_index, end_index) =
-        match self.turn {
-            Player::White => (WHITE_POCKET, BLACK_POCKET, (BLACK_POCKET + 1) % BOARD_SIZE, WHITE_POCKET),
-            Player::Black => (BLACK_POCKET, WHITE_POCKET, (WHITE_POCKET + 1) % BOARD_SIZE, BLACK_POCKET)
-        };
-        if (pocket < start_index) || (pocket >= end_index) {
+        self.sow(pocket).map(|_| ())
+    }
+
+    // the board after each stone of a sub-move is dropped and each capture taken, for showing
+    // the sowing as it happens. the last one is the position the sub-move leads to.
+    pub fn sowing(&self, pocket: Pocket) -> Result<Vec<Node>, Error> {
+        let mut frames: Vec<Node> = Vec::new();
+        let mut node = self.clone();
+        node.sow_with(pocket, &mut |board: &[Score]| frames.push(Node { board: board.to_vec(), ..self.clone() }))?;
+        if frames.last().is_some_and(|frame| frame.board == node.board) {
+            frames.pop();
+        }
+        frames.push(node);
+        Ok(frames)
+    }
+
+    // plays a sub-move, reporting what it did
+    fn sow(&mut self, pocket: Pocket) -> Result<MoveInfo, Error> {
+        self.sow_with(pocket, &mut |_: &[Score]| {})
+    }
+
+    // the same, calling `step` with the board every time it changes
+    fn sow_with(&mut self, pocket: Pocket, step: &mut impl FnMut(&[Score])) -> Result<MoveInfo, Error> {
+        let mut info = MoveInfo::default();
+        let own_pocket = self.store(&self.turn);
+        let enemy_pocket = self.store(&self.turn.toggled());
+        if !self.pits(&self.turn).contains(&pocket) {
             return Err(Error::IndexError);
         }
         if self.board[pocket] == 0 {
             return Err(Error::EmptyError);
         }
         let mut cursor = pocket;
-        let mut count = self.board[pocket];
-        self.board[pocket] = 0;
-        while count > 0 {
-            cursor = (cursor + 1) % BOARD_SIZE;
-            if cursor != enemy_pocket {
-                self.board[cursor] += 1;
-                count -= 1;
+        // positions (board + pit about to be picked up) already seen during an avalanche.
+        // some pit counts recirculate for a very long time (or forever), so we stop
+        // sowing as soon as one repeats.
+        let mut seen = HashSet::new();
+        loop {
+            let mut count = self.board[cursor];
+            self.board[cursor] = 0;
+            while count > 0 {
+                cursor = (cursor + 1) % self.size();
+                if Some(cursor) != enemy_pocket {
+                    self.board[cursor] += 1;
+                    count -= 1;
+                    step(&self.board);
+                }
+            }
+            // if we land in our own pocket, we get another turn, so "next-to-move" doesn't change
+            // otherwise it does change
+            if Some(cursor) == own_pocket {
+                info.extra_turns += 1;
+                return Ok(info);
             }
+
+            if !self.rules.avalanche || self.board[cursor] == 1 { break; }
+            if !seen.insert((self.board.clone(), cursor)) { break; }
         }
-        // if we land in our own pocket, we get another turn, so "next-to-move" doesn't change
-        // otherwise it does change
-        if cursor == own_pocket { return Ok(()); }
 
-        // capture rule - if we "land" on an empty zone that belongs to us,
+        // capture rule - with the Kalah rule, if we "land" on an empty zone that belongs to us,
         // and it isn't our scoring pocket, then we capture everything on the opposite pocket
-        // (and also the stone we captured with)
-        if self.board[cursor] == 1 && start_index <= cursor && cursor < end_index {
-            let opp = self.opposite(cursor);
-            self.board[own_pocket] += self.board[opp] + 1;
-            self.board[opp] = 0;
-            self.board[cursor] = 0;
+        // (and also the stone we captured with).
+        // with the Oware rule we capture the stones in the opponent pit we landed in.
+        let turn = self.turn.clone();
+        for captured in self.captures(cursor) {
+            if self.rules.capture == CaptureRule::Opposite {
+                let opp = self.opposite(captured);
+                info.captured += self.board[opp];
+                self.add_score(&turn, self.board[opp]);
+                self.board[opp] = 0;
+            }
+            info.captured += self.board[captured];
+            self.add_score(&turn, self.board[captured]);
+            self.board[captured] = 0;
+            step(&self.board);
         }
 
         self.turn = self.turn.toggled();
-        Ok(())
+        Ok(info)
     }
 
-    fn sub_children(&self) -> Vec<(Pocket, Node)> {
+    fn sub_children(&self) -> Vec<(Pocket, Node, MoveInfo)> {
         let mut result = Vec::new();
-        for pocket in 0..BOARD_SIZE {
+        for pocket in 0..self.size() {
             let mut new_sub_node = self.clone();
-            match new_sub_node.sub_move(pocket) {
-                Ok(_) => {
-                    result.push((pocket, new_sub_node));
-                },
-                _ => {}
-            };
+            if let Ok(info) = new_sub_node.sow(pocket) {
+                result.push((pocket, new_sub_node, info));
+            }
         }
         result
     }
 
-    fn children_from_sub_node(sub_node: &Node) -> Vec<(Move, Node)> {
+    fn children_from_sub_node(sub_node: &Node) -> Vec<(Move, Node, MoveInfo)> {
         let mut result = Vec::new();
-        for (pocket, sub_child) in sub_node.sub_children() {
-            // if the sub node toggled the turn, that means the turn ended with that sub-node
-            if sub_child.turn != sub_node.turn {
+        for (pocket, sub_child, info) in sub_node.sub_children() {
+            // if the sub node toggled the turn, that means the turn ended with that sub-node.
+            // the same goes if we landed in our store with the last stones on our side.
+            if sub_child.turn != sub_node.turn || sub_child.is_over() {
                 let full_move = vec![pocket];
-                result.push((full_move, sub_child));
+                result.push((full_move, sub_child, info));
             }
             // if turn is not ended sub-node yet, then keep on going via recursion 
             else {
-                for (mut move_fragment, node) in Self::children_from_sub_node(&sub_child) {
+                for (mut move_fragment, node, mut fragment_info) in Self::children_from_sub_node(&sub_child) {
                     // note: this makes it so that the move is in reverse-order
                     move_fragment.push(pocket);
-                    result.push((move_fragment, node))
+                    fragment_info.merge(&info);
+                    result.push((move_fragment, node, fragment_info))
                 }
             }
         }
         result
     }
 
+    fn side_stones(&self, player: &Player) -> Score {
+        self.board[self.pits(player)].iter().sum()
+    }
+
+    // stones still in the pits, i.e. not yet in a store or captured
+    pub fn stones_in_play(&self) -> Score {
+        self.side_stones(&Player::White) + self.side_stones(&Player::Black)
+    }
+
     pub fn children(&self) -> Vec<(Move, Node)> {
-        Self::children_from_sub_node(&self).into_iter().map(
-            |(full_move, node)| {
-                (full_move.into_iter().rev().collect(), node)
-            }).collect()
+        self.children_with_info().into_iter().map(|(mv, node, _)| (mv, node)).collect()
+    }
+
+    // like children(), along with what each move did
+    pub fn children_with_info(&self) -> Vec<(Move, Node, MoveInfo)> {
+        let children: Vec<(Move, Node, MoveInfo)> = Self::children_from_sub_node(self).into_iter().map(
+            |(full_move, node, info)| {
+                (full_move.into_iter().rev().collect(), node, info)
+            }).collect();
+        let opponent = self.turn.toggled();
+        if self.rules.must_feed && self.side_stones(&opponent) == 0 {
+            let feeding: Vec<(Move, Node, MoveInfo)> = children.iter().filter(
+                |(_, child, _)| child.side_stones(&opponent) > 0
+            ).cloned().collect();
+            // if no move feeds the opponent, any move is allowed
+            if !feeding.is_empty() {
+                return feeding;
+            }
+        }
+        children
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.children().into_iter().map(|(mv, _)| mv).collect()
+    }
+
+    // the number of lines of play `depth` full moves long from here (games that end sooner
+    // count once), for checking move generation and timing it
+    pub fn perft(&self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let children = self.children();
+        if children.is_empty() {
+            return 1;
+        }
+        children.iter().map(|(_, child)| child.perft(depth - 1)).sum()
     }
 
     pub fn full_move(&mut self, mv: &Move) -> Result<(), Error> {
@@ -142,54 +631,195 @@ impl Node {
         Ok(())
     }
 
+    // Zobrist key of the position: the xor of a pseudo-random value per (pocket, stone count),
+    // plus the side to move and the separate score counts. the random values are derived on
+    // the fly rather than kept in a table, so any board size and stone count works.
+    pub fn hash(&self) -> u64 {
+        let mut key = 0;
+        for (pocket, &stones) in self.board.iter().enumerate() {
+            key ^= rng::splitmix64(((pocket as u64) << 32) | stones as u32 as u64);
+        }
+        for (player, &score) in self.scores.iter().enumerate() {
+            key ^= rng::splitmix64(((0x100 + player as u64) << 32) | score as u32 as u64);
+        }
+        if self.turn == Player::Black {
+            key ^= rng::splitmix64(u64::MAX);
+        }
+        key
+    }
+
+    // the position with the players swapped: each side's pits and store hold what the other's
+    // did, and the other side is to move. it plays out the same way with the colors reversed,
+    // so its final score is minus this one's. None if the layout isn't symmetric.
+    pub fn mirrored(&self) -> Option<Node> {
+        let layout = &self.rules.layout;
+        if !layout.is_symmetric() {
+            return None;
+        }
+        let half = layout.half_turn();
+        let mut board = self.board.clone();
+        board.rotate_right(half);
+        Some(Node {
+            board,
+            turn: self.turn.toggled(),
+            rules: self.rules,
+            scores: [self.scores[1], self.scores[0]],
+        })
+    }
+
+    // the smaller of the keys of this position and of its mirror image (the same as hash() if
+    // the layout isn't symmetric), and whether it's the mirror image's. a position and its mirror
+    // image get the same key, and the side to move stands just as well in both.
+    pub fn symmetric_hash(&self) -> (u64, bool) {
+        let key = self.hash();
+        match self.mirrored().map(|mirrored| mirrored.hash()) {
+            Some(mirrored) if mirrored < key => (mirrored, true),
+            _ => (key, false)
+        }
+    }
+
+    // the position as one line of text: the board in sowing order, separated by commas, and the
+    // side to move (e.g. "4,4,4,4,4,4,0,4,4,4,4,4,4,0 w"). boards without stores add the stones
+    // each side has captured. the rules aren't included.
+    pub fn fen(&self) -> String {
+        let board = self.board.iter().map(|stones| stones.to_string()).collect::<Vec<_>>().join(",");
+        let turn = match self.turn {
+            Player::White => "w",
+            Player::Black => "b"
+        };
+        match self.rules.layout.stores {
+            [None, None] => format!("{} {} {} {}", board, turn, self.scores[0], self.scores[1]),
+            _ => format!("{} {}", board, turn)
+        }
+    }
+
+    // reads what `fen` writes, under the given rules. None unless it fits them.
+    pub fn from_fen(text: &str, rules: RuleSet) -> Option<Self> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let board = fields.first()?.split(',').map(|stones| stones.parse().ok().filter(|&stones: &Score| stones >= 0)).collect::<Option<Vec<Score>>>()?;
+        let turn = match *fields.get(1)? {
+            "w" | "W" => Player::White,
+            "b" | "B" => Player::Black,
+            _ => { return None; }
+        };
+        let mut node = Node::from_board(&board, turn, rules).ok()?;
+        match (&fields[2..], rules.layout.stores) {
+            ([], _) => {}
+            ([white, black], [None, None]) => {
+                node.scores = [white.parse().ok().filter(|&score: &Score| score >= 0)?, black.parse().ok().filter(|&score: &Score| score >= 0)?];
+            }
+            _ => { return None; }
+        }
+        Some(node)
+    }
+
+    pub fn get_rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
     pub fn get_turn(&self) -> &Player {
         &self.turn
     }
 
+    pub fn get_board(&self) -> &[Score] {
+        &self.board
+    }
+
     pub fn eval(&self) -> Score {
-        self.board[WHITE_POCKET] - self.board[BLACK_POCKET]
+        self.score(&Player::White) - self.score(&Player::Black)
     }
 
+    // the game is over once the side to move has no stones left to sow
+    pub fn is_over(&self) -> bool {
+        self.board[self.pits(&self.turn)].iter().all(|&stones| stones == 0)
+    }
+
+    // stone difference (White minus Black) once the remaining stones are swept
     pub fn final_score(&self) -> Score {
-        let white_score: Score = self.board[(BLACK_POCKET + 1) % BOARD_SIZE..WHITE_POCKET + 1].iter().sum();
-        let black_score: Score = self.board[(WHITE_POCKET + 1) % BOARD_SIZE..BLACK_POCKET + 1].iter().sum();
-        white_score - black_score
+        let white_side: Score = self.board[self.pits(&Player::White)].iter().sum();
+        let black_side: Score = self.board[self.pits(&Player::Black)].iter().sum();
+        let (white_sweep, black_sweep) = match self.rules.sweep {
+            SweepRule::Opponent => (white_side, black_side),
+            // the side to move is the one that ran out
+            SweepRule::Emptier => match self.turn {
+                Player::White => (white_side + black_side, 0),
+                Player::Black => (0, white_side + black_side)
+            }
+        };
+        (self.score(&Player::White) + white_sweep) - (self.score(&Player::Black) + black_sweep)
+    }
+
+    // the result of the game, or None if it isn't over yet
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !self.is_over() {
+            return None;
+        }
+        let reason = match self.side_stones(&self.turn.toggled()) {
+            0 => TerminationReason::BothSidesEmpty,
+            _ => TerminationReason::NoStones
+        };
+        Some(self.outcome_with(self.final_score(), reason))
+    }
+
+    // the result of a game stopped early (move limit, repetition): everybody keeps what's on their side
+    pub fn adjudicate(&self, reason: TerminationReason) -> Outcome {
+        let score = (self.score(&Player::White) + self.side_stones(&Player::White))
+            - (self.score(&Player::Black) + self.side_stones(&Player::Black));
+        self.outcome_with(score, reason)
+    }
+
+    // the result of a game ending here with this final score (White minus Black). the tie-break
+    // is about whose side ran empty, so only a game that ended that way uses it; a level game
+    // stopped any other way is a draw
+    pub fn outcome_with(&self, score: Score, reason: TerminationReason) -> Outcome {
+        let winner = if score > 0 {
+            Some(Player::White)
+        }
+        else if score < 0 {
+            Some(Player::Black)
+        }
+        else {
+            match reason {
+                TerminationReason::NoStones | TerminationReason::BothSidesEmpty => self.tie_winner(),
+                _ => None
+            }
+        };
+        Outcome { winner, score, reason }
+    }
+
+    // who wins a level game that ended because the side to move ran out of stones
+    pub fn tie_winner(&self) -> Option<Player> {
+        match self.rules.tie_break {
+            TieBreak::Draw => None,
+            TieBreak::Emptier => Some(self.turn.clone()),
+            TieBreak::Opponent => Some(self.turn.toggled())
+        }
     }
 }
 
 impl Default for Node {
     fn default() -> Self {
-        let mut new_board = vec![0; BOARD_SIZE.into()];
-        for i in 0..BOARD_SIZE {
-            match i {
-                WHITE_POCKET | BLACK_POCKET => {}
-                _ => { new_board[i] = STONES; }
-            };
-        }
-        Node {
-            board: new_board,
-            turn: Player::White
-        }
+        Self::new(RuleSet::default())
     }
 }
 
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // the white/black sides of the board respectively, not including the scoring pockets
-        let board_white = &(self.board)[0..(WHITE_POCKET)];
-        let board_black = &(self.board)[WHITE_POCKET+1..BLACK_POCKET];
+        let board_white = &(self.board)[self.pits(&Player::White)];
+        let board_black = &(self.board)[self.pits(&Player::Black)];
         // we display White side on bottom, Black side on top
         let board_top = board_black.iter().rev().map(
             |pocket| {
-                format!("( {} )", pocket.to_string())
+                format!("( {} )", pocket)
             }
         ).collect::<Vec<String>>().join("  ");
         let board_bottom = board_white.iter().map(
             |pocket| {
-                format!("( {} )", pocket.to_string())
+                format!("( {} )", pocket)
             }
         ).collect::<Vec<String>>().join("  ");
-        write!(f, "[ {} ]  {}\n\n       {}  [ {} ]\n{} to move", self.board[BLACK_POCKET], board_top, board_bottom, self.board[WHITE_POCKET], self.turn)
+        write!(f, "[ {} ]  {}\n\n       {}  [ {} ]\n{} to move", self.score(&Player::Black), board_top, board_bottom, self.score(&Player::White), self.turn)
     }
 }
 
@@ -209,6 +839,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_perft() {
+        let node = Node::default();
+        assert_eq!((node.perft(0), node.perft(1)), (1, 10));
+        assert_eq!(node.perft(2), node.children().iter().map(|(_, child)| child.legal_moves().len() as u64).sum::<u64>());
+        // a game that's over is a single line however deep
+        let over = Node::from_board(&[0, 0, 0, 0, 0, 0, 30, 1, 0, 0, 0, 0, 0, 17], Player::White, RuleSet::default()).unwrap();
+        assert_eq!(over.perft(5), 1);
+        for name in RuleSet::VARIANTS {
+            assert!(Node::new(RuleSet::variant(name).unwrap()).perft(2) > 0);
+        }
+        assert_eq!(RuleSet::variant("chess"), None);
+    }
+
+    #[test]
+    fn test_move_info() {
+        let node = Node::default();
+        for (mv, _, info) in node.children_with_info() {
+            assert_eq!(info.extra_turns, mv.len() - 1);
+        }
+        // 5 lands in the store, then 0 lands in the empty pit 1 opposite Black's pit 11
+        let node = Node::from_board(&[1, 0, 4, 4, 4, 1, 0, 4, 4, 4, 4, 4, 4, 0], Player::White, RuleSet::default()).unwrap();
+        let (_, _, info) = node.children_with_info().into_iter().find(|(mv, _, _)| *mv == vec![5, 0]).unwrap();
+        assert_eq!(info, MoveInfo { captured: 5, extra_turns: 1 });
+    }
+
+    #[test]
+    fn test_sowing() {
+        let node = Node::default();
+        let frames = node.sowing(2).unwrap();
+        assert_eq!(frames.iter().map(|frame| frame.board[3..7].to_vec()).collect::<Vec<_>>(), vec![vec![5, 4, 4, 0], vec![5, 5, 4, 0], vec![5, 5, 5, 0], vec![5, 5, 5, 1]]);
+        let mut played = node.clone();
+        played.sub_move(2).unwrap();
+        assert_eq!(frames.last(), Some(&played));
+        // a stone into the empty pit 1, then the capture
+        let board = [1, 0, 0, 0, 3, 5, 10, 1, 1, 1, 1, 10, 0, 12];
+        let node = Node::from_board(&board, Player::White, RuleSet::default()).unwrap();
+        let frames = node.sowing(0).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].board[1], frames[0].board[11], frames[1].board[6]), (1, 10, 21));
+        assert_eq!(node.sowing(1), Err(Error::EmptyError));
+    }
+
+    #[test]
+    fn test_hash() {
+        let node = Node::default();
+        assert_eq!(node.hash(), Node::default().hash());
+        let mut keys: Vec<u64> = node.children().iter().map(|(_, child)| child.hash()).collect();
+        keys.push(node.hash());
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 11);
+
+        // same board, other side to move
+        let black = Node::from_board(&node.board, Player::Black, RuleSet::default()).unwrap();
+        assert_ne!(black.hash(), node.hash());
+    }
+
+    #[test]
+    fn test_mirrored() {
+        assert!(Layout::kalah().is_symmetric() && Layout::storeless().is_symmetric() && Layout::kalah_sized(3).is_symmetric());
+        assert!(!Layout::adjacent_stores().is_symmetric());
+        assert!(Node::new(RuleSet { layout: Layout::adjacent_stores(), ..RuleSet::default() }).mirrored().is_none());
+        for rules in [RuleSet::default(), RuleSet { layout: Layout::storeless(), capture: CaptureRule::Oware, ..RuleSet::default() }] {
+            let mut node = Node::new(rules);
+            // along one line of play, every position and its mirror image move alike
+            while let Some((_, child)) = node.children().into_iter().nth(1).or_else(|| node.children().into_iter().next()) {
+                let mirrored = node.mirrored().unwrap();
+                assert_eq!(mirrored.mirrored().unwrap(), node);
+                assert_eq!((mirrored.eval(), mirrored.symmetric_hash().0), (-node.eval(), node.symmetric_hash().0));
+                let moves: Vec<Move> = node.legal_moves().iter().map(|mv| mv.iter().map(|&pocket| (pocket + rules.layout.size / 2) % rules.layout.size).collect()).collect();
+                assert_eq!(mirrored.legal_moves(), moves);
+                node = child;
+            }
+            assert_eq!(node.mirrored().unwrap().final_score(), -node.final_score());
+        }
+    }
+
     #[test]
     fn test_display() {
         let node = Node::default();
@@ -217,5 +925,192 @@ mod tests {
 
        ( 4 )  ( 4 )  ( 4 )  ( 4 )  ( 4 )  ( 4 )  [ 0 ]\nWhite to move";
         assert_eq!(node.to_string(), default_string);
+        assert_eq!(node.fen(), "4,4,4,4,4,4,0,4,4,4,4,4,4,0 w");
+        assert_eq!(Node::from_fen(&node.fen(), RuleSet::default()), Some(node));
+        let oware = RuleSet::variant("oware").unwrap();
+        let node = Node::from_fen("0,1,2,3,4,5,6,7,8,9,10,11 b 3 2", oware).unwrap();
+        assert_eq!((node.score(&Player::White), node.score(&Player::Black), node.get_turn()), (3, 2, &Player::Black));
+        assert_eq!(Node::from_fen(&node.fen(), oware), Some(node));
+        assert_eq!(Node::from_fen("4,4,4 w", RuleSet::default()), None);
+        assert_eq!(Node::from_fen("4,4,4,4,4,4,0,4,4,4,4,4,4,0 w 1 2", RuleSet::default()), None);
+        assert_eq!(Node::from_fen("4,4,4,4,4,4,0,4,4,4,4,4,-4,0 w", RuleSet::default()), None);
+    }
+
+    #[test]
+    fn test_avalanche() {
+        let mut board = vec![0; BOARD_SIZE];
+        board[0] = 1;
+        board[1] = 2;
+        board[8] = 5;
+
+        let mut plain = Node::new(RuleSet::default());
+        plain.board = board.clone();
+        assert!(plain.sub_move(0).is_ok());
+        assert_eq!(plain.board[1], 3);
+        assert_eq!(plain.board[WHITE_POCKET], 0);
+
+        // landing on pit 1 picks up its 3 stones, which finish in the empty pit 4 and capture
+        let mut node = Node::new(RuleSet { avalanche: true, ..RuleSet::default() });
+        node.board = board;
+        assert!(node.sub_move(0).is_ok());
+        assert_eq!(node.board, vec![0, 0, 1, 1, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(node.turn, Player::Black);
+    }
+
+    #[test]
+    fn test_chained_captures() {
+        let mut board = vec![0; BOARD_SIZE];
+        board[5] = 4;
+        board[7] = 1;
+        board[8] = 2;
+        board[9] = 5;
+
+        // White sows 5 -> 6, 7, 8, 9 and brings pits 7, 8, 9 to 2, 3, 2 stones
+        let rules = RuleSet { capture: CaptureRule::Oware, chain_captures: true, ..RuleSet::default() };
+        let mut node = Node::new(rules);
+        node.board = board.clone();
+        node.board[9] = 1;
+        assert!(node.sub_move(5).is_ok());
+        assert_eq!(node.board[WHITE_POCKET], 1 + 2 + 3 + 2);
+
+        // without chaining only the landing pit is taken
+        let mut single = Node::new(RuleSet { chain_captures: false, ..rules });
+        single.board = board.clone();
+        single.board[9] = 1;
+        assert!(single.sub_move(5).is_ok());
+        assert_eq!(single.board[WHITE_POCKET], 1 + 2);
+        assert_eq!(single.board[8], 3);
+
+        // the chain stops at the first pit that doesn't satisfy the condition
+        let mut blocked = Node::new(rules);
+        blocked.board = board;
+        blocked.board[9] = 2;
+        blocked.board[8] = 4;
+        assert!(blocked.sub_move(5).is_ok());
+        assert_eq!(blocked.board[WHITE_POCKET], 1 + 3);
+        assert_eq!(blocked.board[8], 5);
+    }
+
+    #[test]
+    fn test_storeless() {
+        let rules = RuleSet { layout: Layout::storeless(), capture: CaptureRule::Oware, chain_captures: true, ..RuleSet::default() };
+        let mut node = Node::new(rules);
+        assert_eq!(node.board.len(), 12);
+        assert_eq!(node.children().len(), 6);
+
+        // White sows 5 -> 6, 7 (no store in between) and captures both pits
+        node.board = vec![0, 0, 0, 0, 0, 2, 1, 2, 0, 0, 0, 0];
+        assert!(node.sub_move(5).is_ok());
+        assert_eq!(node.board, vec![0; 12]);
+        assert_eq!(node.score(&Player::White), 5);
+        assert_eq!(node.eval(), 5);
+        assert_eq!(node.turn, Player::Black);
+    }
+
+    #[test]
+    fn test_outcome() {
+        let mut node = Node::default();
+        assert_eq!(node.outcome(), None);
+
+        // White to move with an empty side, Black still has 2 stones
+        node.board = vec![0, 0, 0, 0, 0, 0, 20, 0, 0, 2, 0, 0, 0, 26];
+        assert_eq!(node.outcome(), Some(Outcome { winner: Some(Player::Black), score: -8, reason: TerminationReason::NoStones }));
+
+        node.rules.sweep = SweepRule::Emptier;
+        assert_eq!(node.outcome(), Some(Outcome { winner: Some(Player::Black), score: -4, reason: TerminationReason::NoStones }));
+
+        node.board = vec![0, 0, 0, 0, 0, 0, 22, 0, 0, 2, 0, 0, 0, 24];
+        assert_eq!(node.outcome(), Some(Outcome { winner: None, score: 0, reason: TerminationReason::NoStones }));
+        node.rules.tie_break = TieBreak::Opponent;
+        assert_eq!(node.outcome(), Some(Outcome { winner: Some(Player::Black), score: 0, reason: TerminationReason::NoStones }));
+        node.rules.tie_break = TieBreak::Emptier;
+        assert_eq!(node.outcome(), Some(Outcome { winner: Some(Player::White), score: 0, reason: TerminationReason::NoStones }));
+
+        node.board = vec![0, 0, 0, 0, 0, 0, 24, 0, 0, 0, 0, 0, 0, 24];
+        assert_eq!(node.outcome().map(|outcome| outcome.reason), Some(TerminationReason::BothSidesEmpty));
+
+        // a level game stopped early is a draw whatever the tie-break
+        node.board = vec![1, 0, 0, 0, 0, 0, 23, 0, 0, 1, 0, 0, 0, 23];
+        assert_eq!(node.adjudicate(TerminationReason::MoveLimit).winner, None);
+        assert_eq!(node.outcome_with(0, TerminationReason::Adjudication).winner, None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_last_stone_into_store() {
+        // the only move lands in our store and empties our side, which still counts as a move
+        let mut node = Node::new(RuleSet::default());
+        node.board = vec![0, 0, 0, 0, 0, 1, 20, 3, 0, 0, 0, 0, 0, 24];
+        assert_eq!(node.legal_moves(), vec![vec![5]]);
+    }
+
+    #[test]
+    fn test_must_feed() {
+        let mut node = Node::new(RuleSet::default());
+        node.board = vec![1, 0, 0, 0, 0, 2, 10, 0, 0, 0, 0, 0, 0, 10];
+        assert_eq!(node.legal_moves(), vec![vec![0], vec![5]]);
+
+        // only pit 5 reaches Black's side
+        node.rules.must_feed = true;
+        assert_eq!(node.legal_moves(), vec![vec![5]]);
+
+        // nothing reaches Black's side, so any move goes
+        node.board = vec![1, 1, 0, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 10];
+        assert_eq!(node.legal_moves(), vec![vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn test_adjacent_stores() {
+        let mut node = Node::new(RuleSet { layout: Layout::adjacent_stores(), ..RuleSet::default() });
+        assert_eq!(node.board, vec![4, 4, 4, 4, 4, 4, 0, 0, 4, 4, 4, 4, 4, 4]);
+        assert_eq!(node.children().len(), 10);
+
+        // White sows past its own store and skips Black's
+        assert!(node.sub_move(5).is_ok());
+        assert_eq!(node.board, vec![4, 4, 4, 4, 4, 0, 1, 0, 5, 5, 5, 4, 4, 4]);
+        // Black sows around the end of the board, skips White's store and ends in its own
+        node.board[13] = 7;
+        assert!(node.sub_move(13).is_ok());
+        assert_eq!(node.board, vec![5, 5, 5, 5, 5, 1, 1, 1, 5, 5, 5, 4, 4, 0]);
+        assert_eq!(node.turn, Player::Black);
+
+        // pit 0 faces Black's last pit
+        assert_eq!(node.opposite(0), 13);
+        assert_eq!(node.opposite(13), 0);
+    }
+
+    #[test]
+    fn test_flattened() {
+        // four ranks of eight pits, two per player, sown as one ring of 32
+        let layout = Layout::flattened(2, 8);
+        assert_eq!(layout, Layout { size: 32, pits: 16, first_pit: [0, 16], stores: [None, None] });
+        assert!(layout.is_symmetric());
+        let rules = RuleSet { layout, capture: CaptureRule::Oware, must_feed: true, ..RuleSet::default() };
+        assert!(Node::from_board(&[2; 32], Player::White, rules).is_ok());
+        assert!(Node::from_board(&[2; 14], Player::White, rules).is_err());
+
+        let mut node = Node::with_stones(rules, 2);
+        assert_eq!(node.opposite(0), 31);
+        assert_eq!(node.opposite(16), 15);
+        let stones = node.stones_in_play();
+        let mut plies = 0;
+        while !node.is_over() && plies < 500 {
+            let moves = node.legal_moves();
+            assert!(!moves.is_empty());
+            let mv = moves[plies % moves.len()].clone();
+            node.full_move(&mv).unwrap();
+            assert_eq!(node.stones_in_play() + node.score(&Player::White) + node.score(&Player::Black), stones);
+            plies += 1;
+        }
+        assert!(plies > 0);
+    }
+
+    #[test]
+    fn test_avalanche_children() {
+        let node = Node::new(RuleSet { avalanche: true, ..RuleSet::default() });
+        let stones: Score = node.board.iter().sum();
+        for (_, child) in node.children() {
+            let child_stones: Score = child.board.iter().sum();
+            assert_eq!(child_stones, stones);
+        }
+    }
+}
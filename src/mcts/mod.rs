@@ -0,0 +1,570 @@
+use super::engine::{Engine, InfoCallback, PvLine, SearchInfo, SearchLimits, SearchResult, StopToken};
+use super::eval::{self, Evaluator, WinModel};
+use super::game::format_move;
+use super::log::{self, Level};
+use super::mancala;
+use super::minimax;
+use super::rng::Rng;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// playouts per search when no limit is given
+const DEFAULT_ITERATIONS: u64 = 10_000;
+// full moves after which a playout is scored as it stands. only variants that can cycle
+// (e.g. store-less boards) ever get near it.
+const MAX_PLAYOUT_MOVES: usize = 1_000;
+// how often (in playouts) the clock is checked
+const CLOCK_INTERVAL: u64 = 64;
+// how often (in playouts) progress is reported
+const INFO_INTERVAL: u64 = 4096;
+// how many stones ahead a rollout search (or an evaluator) has to put White for its leaf to
+// count as (about) 3/4 won
+const ROLLOUT_SCALE: f64 = 4.0;
+
+// how moves are picked during playouts
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Playout {
+    // uniformly at random
+    #[default]
+    Random,
+    // the move capturing the most stones, else one that earns an extra turn, else at random
+    GreedyCapture,
+}
+
+// everything about MCTS that can be tuned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MctsConfig {
+    // the C in UCT: higher explores more, lower trusts the averages more. rewards are in [0, 1],
+    // so sqrt(2) is the textbook value.
+    pub exploration: f64,
+    pub playout: Playout,
+    // evaluate leaves with an alpha-beta search this deep instead of playing them out
+    // (the playout policy is unused then). stronger per iteration, especially late in the game,
+    // at the cost of far fewer iterations.
+    pub rollout_depth: Option<usize>,
+    // threads growing the same tree
+    pub threads: usize,
+    // losses temporarily added to every node on a path while a thread's playout through it is
+    // running, steering the other threads elsewhere. has no effect with a single thread.
+    pub virtual_loss: f64,
+    // keep the subtree of the new position from the previous search instead of starting over
+    pub reuse_tree: bool,
+    pub seed: u64,
+    // with an evaluator (see `MctsEngine::set_evaluator`), how many leaves each thread collects
+    // before evaluating them all in one call
+    pub batch: usize,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            exploration: std::f64::consts::SQRT_2,
+            playout: Playout::Random,
+            rollout_depth: None,
+            threads: 1,
+            virtual_loss: 1.0,
+            reuse_tree: false,
+            seed: 0,
+            batch: 1,
+        }
+    }
+}
+
+struct TreeNode {
+    position: mancala::Node,
+    // the move leading here from the parent (None at the root)
+    mv: Option<mancala::Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // legal moves not expanded into children yet
+    untried: Vec<mancala::Move>,
+    // playouts through here, plus the virtual losses of playouts still running
+    visits: f64,
+    // total reward of playouts through here, for the player who moved into this node
+    reward: f64,
+    // total final score (White minus Black) of playouts through here
+    margin: f64,
+}
+
+impl TreeNode {
+    fn new(position: mancala::Node, mv: Option<mancala::Move>, parent: Option<usize>) -> Self {
+        TreeNode {
+            untried: position.legal_moves(),
+            position,
+            mv,
+            parent,
+            children: Vec::new(),
+            visits: 0.0,
+            reward: 0.0,
+            margin: 0.0,
+        }
+    }
+}
+
+// the search tree, as an arena with the root at index 0
+struct Tree {
+    nodes: Vec<TreeNode>,
+}
+
+impl Tree {
+    fn new(position: mancala::Node) -> Self {
+        Tree { nodes: vec![TreeNode::new(position, None, None)] }
+    }
+
+    // the child of `index` with the best upper confidence bound
+    fn select_child(&self, index: usize, exploration: f64) -> usize {
+        let parent = &self.nodes[index];
+        let log_visits = parent.visits.ln();
+        let uct = |child: usize| {
+            let child = &self.nodes[child];
+            child.reward / child.visits + exploration * (log_visits / child.visits).sqrt()
+        };
+        *parent.children.iter().max_by(|&&a, &&b| uct(a).total_cmp(&uct(b))).unwrap()
+    }
+
+    // walks down to a leaf and expands it, charging a virtual loss to everything on the way.
+    // returns the new leaf and its depth.
+    fn descend(&mut self, config: &MctsConfig, rng: &mut Rng) -> (usize, usize) {
+        let mut index = 0;
+        let mut depth = 0;
+        self.nodes[index].visits += config.virtual_loss;
+        while self.nodes[index].untried.is_empty() && !self.nodes[index].children.is_empty() {
+            index = self.select_child(index, config.exploration);
+            self.nodes[index].visits += config.virtual_loss;
+            depth += 1;
+        }
+        if !self.nodes[index].untried.is_empty() {
+            let pick = rng.below(self.nodes[index].untried.len());
+            let mv = self.nodes[index].untried.swap_remove(pick);
+            let mut position = self.nodes[index].position.clone();
+            position.full_move(&mv).expect("generated moves are legal");
+            let mut leaf = TreeNode::new(position, Some(mv), Some(index));
+            leaf.visits = config.virtual_loss;
+            self.nodes.push(leaf);
+            let child = self.nodes.len() - 1;
+            self.nodes[index].children.push(child);
+            index = child;
+            depth += 1;
+        }
+        (index, depth)
+    }
+
+    // adds a leaf's value to everything from the leaf up, taking back the virtual losses
+    fn backpropagate(&mut self, leaf: usize, value: &Value, virtual_loss: f64) {
+        let mut current = Some(leaf);
+        while let Some(index) = current {
+            let parent = self.nodes[index].parent;
+            // the reward belongs to whoever moved into this node, i.e. the parent's side to move
+            let reward = match parent.map(|parent| self.nodes[parent].position.get_turn().clone()) {
+                Some(mancala::Player::White) => value.white_reward,
+                Some(mancala::Player::Black) => 1.0 - value.white_reward,
+                None => 0.0
+            };
+            let node = &mut self.nodes[index];
+            node.visits += 1.0 - virtual_loss;
+            node.reward += reward;
+            node.margin += value.margin;
+            current = parent;
+        }
+    }
+
+    fn most_visited(&self, index: usize) -> Option<usize> {
+        self.nodes[index].children.iter().copied().max_by(|&a, &b| self.nodes[a].visits.total_cmp(&self.nodes[b].visits))
+    }
+
+    // the most visited line from the root
+    fn pv(&self) -> Vec<mancala::Move> {
+        let mut pv = Vec::new();
+        let mut current = 0;
+        while let Some(child) = self.most_visited(current) {
+            pv.push(self.nodes[child].mv.clone().unwrap());
+            current = child;
+        }
+        pv
+    }
+
+    // the average final score through the most visited move
+    fn score(&self) -> Option<mancala::Score> {
+        let child = &self.nodes[self.most_visited(0)?];
+        Some((child.margin / child.visits).round() as mancala::Score)
+    }
+
+    // the subtree under the first node (in creation order, so the shallowest along its line)
+    // whose position is `position`, as a tree of its own. None if the position isn't in the tree.
+    fn subtree(mut self, position: &mancala::Node) -> Option<Tree> {
+        let root = self.nodes.iter().position(|node| node.position == *position)?;
+        let mut nodes: Vec<TreeNode> = Vec::new();
+        // (old index, new parent index)
+        let mut stack: Vec<(usize, Option<usize>)> = vec![(root, None)];
+        while let Some((old, parent)) = stack.pop() {
+            let placeholder = TreeNode::new(mancala::Node::default(), None, None);
+            let mut node = std::mem::replace(&mut self.nodes[old], placeholder);
+            let index = nodes.len();
+            if let Some(parent) = parent {
+                nodes[parent].children.push(index);
+            }
+            else {
+                node.mv = None;
+            }
+            node.parent = parent;
+            stack.extend(node.children.drain(..).map(|child| (child, Some(index))));
+            nodes.push(node);
+        }
+        Some(Tree { nodes })
+    }
+}
+
+fn playout_move(playout: Playout, rng: &mut Rng, position: &mancala::Node) -> Option<mancala::Node> {
+    let mut children = position.children_with_info();
+    if children.is_empty() {
+        return None;
+    }
+    let index = match playout {
+        Playout::Random => rng.below(children.len()),
+        Playout::GreedyCapture => {
+            let best = children.iter().map(|(_, _, info)| (info.captured, info.extra_turns)).max().unwrap();
+            if best == (0, 0) {
+                rng.below(children.len())
+            }
+            else {
+                children.iter().position(|(_, _, info)| (info.captured, info.extra_turns) == best).unwrap()
+            }
+        }
+    };
+    Some(children.swap_remove(index).1)
+}
+
+// what a leaf turned out to be worth
+struct Value {
+    // White's reward, in [0, 1]
+    white_reward: f64,
+    // White minus Black
+    margin: f64,
+}
+
+impl From<mancala::Outcome> for Value {
+    fn from(outcome: mancala::Outcome) -> Self {
+        Value {
+            white_reward: match outcome.winner {
+                Some(mancala::Player::White) => 1.0,
+                Some(mancala::Player::Black) => 0.0,
+                None => 0.5
+            },
+            margin: outcome.score as f64,
+        }
+    }
+}
+
+// plays the position out to the end and returns the final outcome
+fn simulate(playout: Playout, rng: &mut Rng, mut position: mancala::Node) -> mancala::Outcome {
+    for _ in 0..MAX_PLAYOUT_MOVES {
+        match playout_move(playout, rng, &position) {
+            Some(child) => { position = child; }
+            None => { break; }
+        }
+    }
+    position.outcome().unwrap_or_else(|| position.adjudicate(mancala::TerminationReason::MoveLimit))
+}
+
+// what the threads of one search share
+struct Shared<'a> {
+    tree: Mutex<Tree>,
+    config: &'a MctsConfig,
+    evaluator: Option<&'a Arc<dyn Evaluator>>,
+    start: Instant,
+    movetime: Option<Duration>,
+    iterations: u64,
+    stop: StopToken,
+    // playouts started and finished so far
+    started: AtomicU64,
+    finished: AtomicU64,
+    depth: AtomicUsize,
+}
+
+impl Shared<'_> {
+    // playouts until the budget runs out, on this thread
+    fn work(&self, rng: &mut Rng, mut info_callback: Option<&mut InfoCallback>) {
+        // one per thread, so rollouts share neither killers nor the transposition table across threads
+        // (without the endgame solver, which would make rollouts near the end far slower than the rest)
+        let options = minimax::EngineOptions { endgame_stones: 0, ..minimax::EngineOptions::default() };
+        let mut searcher = self.config.rollout_depth.map(|_| minimax::Searcher::new(options));
+        let batch = match self.evaluator {
+            Some(_) => self.config.batch.max(1),
+            None => 1
+        };
+        let mut out_of_budget = false;
+        while !out_of_budget {
+            let mut leaves = Vec::with_capacity(batch);
+            while leaves.len() < batch {
+                let done = self.started.fetch_add(1, Ordering::Relaxed);
+                let out_of_time = self.movetime.is_some_and(|movetime| done.is_multiple_of(CLOCK_INTERVAL) && self.start.elapsed() >= movetime);
+                if done >= self.iterations || self.stop.is_stopped() || out_of_time {
+                    out_of_budget = true;
+                    break;
+                }
+                let mut tree = self.tree.lock().unwrap();
+                let (leaf, depth) = tree.descend(self.config, rng);
+                self.depth.fetch_max(depth, Ordering::Relaxed);
+                leaves.push((leaf, tree.nodes[leaf].position.clone()));
+            }
+            if leaves.is_empty() {
+                break;
+            }
+            let values = self.evaluate(rng, searcher.as_mut(), leaves.iter().map(|(_, position)| position.clone()).collect());
+            let mut tree = self.tree.lock().unwrap();
+            for ((leaf, _), value) in leaves.iter().zip(values) {
+                tree.backpropagate(*leaf, &value, self.config.virtual_loss);
+                let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(callback) = info_callback.as_mut().filter(|_| finished.is_multiple_of(INFO_INTERVAL)) {
+                    callback(&SearchInfo {
+                        depth: self.depth.load(Ordering::Relaxed),
+                        score: tree.score().unwrap_or_default(),
+                        pv: tree.pv(),
+                        nodes: finished,
+                        time: self.start.elapsed(),
+                    });
+                }
+            }
+        }
+    }
+
+    // what the leaves are worth: from the evaluator if there is one (all in one call), else from
+    // a playout each, or a shallow search if the config says so
+    fn evaluate(&self, rng: &mut Rng, mut searcher: Option<&mut minimax::Searcher>, positions: Vec<mancala::Node>) -> Vec<Value> {
+        let from_score = |score: f64| Value {
+            white_reward: 1.0 / (1.0 + (-score / ROLLOUT_SCALE).exp()),
+            margin: score,
+        };
+        if let Some(evaluator) = self.evaluator {
+            let unfinished: Vec<mancala::Node> = positions.iter().filter(|position| position.outcome().is_none()).cloned().collect();
+            let mut scores = match unfinished.is_empty() {
+                true => Vec::new(),
+                false => evaluator.evaluate_batch(&unfinished)
+            }.into_iter();
+            return positions.into_iter().map(|position| match position.outcome() {
+                Some(outcome) => outcome.into(),
+                None => from_score(scores.next().expect("a score per position") as f64 / eval::STONE as f64)
+            }).collect();
+        }
+        positions.into_iter().map(|position| {
+            if let Some(outcome) = position.outcome() {
+                return outcome.into();
+            }
+            match (searcher.as_deref_mut(), self.config.rollout_depth) {
+                (Some(searcher), Some(depth)) => from_score(searcher.search(&position, SearchLimits::depth(depth)).score as f64),
+                _ => simulate(self.config.playout, rng, position).into()
+            }
+        }).collect()
+    }
+}
+
+// Monte Carlo Tree Search with UCT selection. rewards are 1 for a win, 1/2 for a draw and 0 for
+// a loss, so unlike alpha-beta it needs no evaluation function at all.
+pub struct MctsEngine {
+    config: MctsConfig,
+    rng: Rng,
+    // the tree of the last search, kept if config.reuse_tree is set
+    tree: Option<Tree>,
+    evaluator: Option<Arc<dyn Evaluator>>,
+    info_callback: Option<InfoCallback>,
+    stop: StopToken,
+}
+
+impl Default for MctsEngine {
+    fn default() -> Self {
+        Self::new(MctsConfig::default())
+    }
+}
+
+impl MctsEngine {
+    pub fn new(config: MctsConfig) -> Self {
+        MctsEngine {
+            rng: Rng::new(config.seed),
+            config,
+            tree: None,
+            evaluator: None,
+            info_callback: None,
+            stop: StopToken::new(),
+        }
+    }
+
+    pub fn config(&self) -> &MctsConfig {
+        &self.config
+    }
+
+    // judge leaves with this instead of playing them out (None to go back to playouts)
+    pub fn set_evaluator(&mut self, evaluator: Option<Arc<dyn Evaluator>>) {
+        self.evaluator = evaluator;
+    }
+}
+
+impl Engine for MctsEngine {
+    fn search(&mut self, node: &mancala::Node, limits: SearchLimits) -> SearchResult {
+        let start = Instant::now();
+        let tree = match self.tree.take() {
+            Some(tree) if self.config.reuse_tree => tree.subtree(node),
+            _ => None
+        };
+        let shared = Shared {
+            tree: Mutex::new(tree.unwrap_or_else(|| Tree::new(node.clone()))),
+            config: &self.config,
+            evaluator: self.evaluator.as_ref(),
+            start,
+            movetime: limits.time_for_move(),
+            iterations: match (limits.nodes, limits.time_for_move()) {
+                (Some(nodes), _) => nodes,
+                (None, Some(_)) => u64::MAX,
+                (None, None) => DEFAULT_ITERATIONS
+            },
+            stop: self.stop.clone(),
+            started: AtomicU64::new(0),
+            finished: AtomicU64::new(0),
+            depth: AtomicUsize::new(0),
+        };
+        if !node.legal_moves().is_empty() {
+            let mut helper_rngs: Vec<Rng> = (1..self.config.threads).map(|_| Rng::new(self.rng.next_u64())).collect();
+            thread::scope(|scope| {
+                for rng in helper_rngs.iter_mut() {
+                    let shared = &shared;
+                    scope.spawn(move || shared.work(rng, None));
+                }
+                shared.work(&mut self.rng, self.info_callback.as_mut());
+            });
+        }
+        let tree = shared.tree.into_inner().unwrap();
+        let pv = tree.pv();
+        let score = tree.score().unwrap_or_else(|| node.final_score());
+        let result = SearchResult {
+            best_move: pv.first().cloned(),
+            score,
+            lines: vec![PvLine { score, pv: pv.clone() }],
+            pv,
+            depth: shared.depth.load(Ordering::Relaxed),
+            nodes: shared.finished.load(Ordering::Relaxed),
+            time: start.elapsed(),
+            tt_hits: 0,
+            exact: false,
+            ends_in: None,
+            win_prob: WinModel::default().win_prob(score, node.stones_in_play(), false),
+        };
+        log::event("search", Level::Info, || format!("mcts: {} score {:+} after {} playouts to depth {}, {:.3}s", result.best_move.as_ref().map_or("-".to_string(), format_move), result.score, result.nodes, result.depth, result.time.as_secs_f64()));
+        if self.config.reuse_tree {
+            self.tree = Some(tree);
+        }
+        result
+    }
+
+    fn set_info_callback(&mut self, callback: Option<InfoCallback>) {
+        self.info_callback = callback;
+    }
+
+    fn set_stop_token(&mut self, token: StopToken) {
+        self.stop = token;
+    }
+
+    // the kept tree is of positions after the ones taken back
+    fn takeback(&mut self) {
+        self.tree = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let node = mancala::Node::default();
+        for playout in [Playout::Random, Playout::GreedyCapture] {
+            let config = MctsConfig { playout, seed: 1, ..MctsConfig::default() };
+            let result = MctsEngine::new(config).search(&node, SearchLimits::nodes(500));
+            assert_eq!(result.nodes, 500);
+            assert!(node.legal_moves().contains(result.best_move.as_ref().unwrap()));
+            assert_eq!(result.pv.first(), result.best_move.as_ref());
+
+            // same seed, same search
+            let again = MctsEngine::new(config).search(&node, SearchLimits::nodes(500));
+            assert_eq!(again.pv, result.pv);
+        }
+    }
+
+    #[test]
+    fn test_finds_winning_capture() {
+        // White's pit 1 lands in the empty pit 2 and captures the 10 stones opposite
+        let board = [0, 1, 0, 0, 3, 0, 10, 1, 1, 1, 10, 0, 0, 12];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        for threads in [1, 3] {
+            let config = MctsConfig { threads, seed: 3, ..MctsConfig::default() };
+            let result = MctsEngine::new(config).search(&node, SearchLimits::nodes(2000));
+            assert_eq!(result.nodes, 2000);
+            assert_eq!(result.best_move, Some(vec![1]));
+        }
+    }
+
+    #[test]
+    fn test_minimax_rollouts() {
+        let node = mancala::Node::default();
+        let config = MctsConfig { rollout_depth: Some(2), ..MctsConfig::default() };
+        let result = MctsEngine::new(config).search(&node, SearchLimits::nodes(200));
+        assert_eq!(result.nodes, 200);
+        assert!(node.legal_moves().contains(result.best_move.as_ref().unwrap()));
+
+        let board = [0, 1, 0, 0, 3, 0, 10, 1, 1, 1, 10, 0, 0, 12];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let result = MctsEngine::new(config).search(&node, SearchLimits::nodes(200));
+        assert_eq!(result.best_move, Some(vec![1]));
+    }
+
+    #[test]
+    fn test_evaluator_batches() {
+        // counts the calls, so the test can see the leaves arrive in batches
+        struct Counting(AtomicU64);
+        impl Evaluator for Counting {
+            fn evaluate(&self, node: &mancala::Node) -> mancala::Score {
+                eval::WeightedEval::default().evaluate(node)
+            }
+            fn evaluate_batch(&self, nodes: &[mancala::Node]) -> Vec<mancala::Score> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                nodes.iter().map(|node| self.evaluate(node)).collect()
+            }
+        }
+        let board = [0, 1, 0, 0, 3, 0, 10, 1, 1, 1, 10, 0, 0, 12];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let counting = Arc::new(Counting(AtomicU64::new(0)));
+        let mut engine = MctsEngine::new(MctsConfig { batch: 8, ..MctsConfig::default() });
+        engine.set_evaluator(Some(counting.clone()));
+        let result = engine.search(&node, SearchLimits::nodes(400));
+        assert_eq!(result.nodes, 400);
+        // fewer if a batch is all finished games
+        assert!((1..=400 / 8).contains(&counting.0.load(Ordering::Relaxed)));
+        assert_eq!(result.best_move, Some(vec![1]));
+    }
+
+    #[test]
+    fn test_reuse_tree() {
+        let mut engine = MctsEngine::new(MctsConfig { reuse_tree: true, ..MctsConfig::default() });
+        let node = mancala::Node::default();
+        let result = engine.search(&node, SearchLimits::nodes(1000));
+        let mut after = node.clone();
+        for mv in result.pv.iter().take(2) {
+            after.full_move(mv).unwrap();
+        }
+        let kept = engine.tree.as_ref().unwrap().nodes.iter().find(|tree_node| tree_node.position == after).unwrap().visits;
+        assert!(kept > 1.0);
+
+        // the playouts from the first search are still there
+        let result = engine.search(&after, SearchLimits::nodes(1000));
+        assert_eq!(result.nodes, 1000);
+        let tree = engine.tree.as_ref().unwrap();
+        assert_eq!(tree.nodes[0].position, after);
+        assert_eq!(tree.nodes[0].parent, None);
+        assert_eq!(tree.nodes[0].visits, kept + 1000.0);
+
+        // nothing of it is left to go back to after a takeback
+        engine.takeback();
+        assert_eq!(engine.search(&node, SearchLimits::nodes(1000)).nodes, 1000);
+        assert_eq!(engine.tree.as_ref().unwrap().nodes[0].visits, 1000.0);
+    }
+}
@@ -1,43 +1,1606 @@
+use super::book::Book;
+use super::endgame::{self, EndgameDb};
+use super::engine::{Engine, InfoCallback, PvLine, SearchInfo, StopToken};
+// these lived here before the Engine trait, and code using minimax::SearchLimits still works
+pub use super::engine::{SearchLimits, SearchResult};
+use super::eval::{self, EvalWeights, Evaluator, WeightedEval, WinModel};
+use super::game::format_move;
+use super::log::{self, Level};
 use super::mancala;
+use super::rng::{self, Rng};
+use super::tree::SearchTree;
+use super::tt;
+use serde::{Deserialize, Serialize};
 use std::cmp;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-// taken from pseudocode found on Wikipedia
-pub fn minimax(node: &mancala::Node, depth: usize, alpha: &mut mancala::Score, beta: &mut mancala::Score) -> (Option<mancala::Move>, mancala::Score) {
-    let children = node.children();
-    if children.is_empty() {
-        return (None, node.final_score());
+// how often (in nodes) the clock is checked, since Instant::now() isn't free
+const CLOCK_INTERVAL: u64 = 1024;
+
+// keeps track of how much of the budget a search has used
+struct Budget {
+    limits: SearchLimits,
+    // the search stops early once any of these is raised (the caller's token, plus for helper
+    // threads the one the main thread raises when it's done)
+    stops: Vec<StopToken>,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+    // whether the last iteration stopped at the depth limit somewhere (rather than only at
+    // finished games), i.e. whether searching deeper could change anything
+    horizon: bool,
+}
+
+impl Budget {
+    fn new(limits: SearchLimits, stops: Vec<StopToken>) -> Self {
+        Budget {
+            limits,
+            stops,
+            start: Instant::now(),
+            nodes: 0,
+            aborted: false,
+            horizon: false,
+        }
+    }
+
+    // counts a node and reports whether the search has to stop
+    fn exhausted(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        // the node that would go over the limit isn't searched (or counted)
+        if self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes) {
+            self.aborted = true;
+            return true;
+        }
+        self.nodes += 1;
+        if self.stops.iter().any(StopToken::is_stopped) {
+            self.aborted = true;
+        }
+        if self.nodes.is_multiple_of(CLOCK_INTERVAL) && self.limits.time_for_move().is_some_and(|movetime| self.start.elapsed() >= movetime) {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+}
+
+// larger than any real score, and safe to negate (unlike Score::MIN)
+const INFINITY: mancala::Score = mancala::Score::MAX;
+
+// +1 for White, -1 for Black: turns White-relative scores into side-to-move-relative ones
+fn sign(player: &mancala::Player) -> mancala::Score {
+    match player {
+        mancala::Player::White => 1,
+        mancala::Player::Black => -1
+    }
+}
+
+// inside the search, scores are in 1/SCALE of a stone, so that a finished game can also say how
+// soon it finished: the side that's ahead gets up to TEMPO extra, more the sooner the game ends (and
+// the side that's behind as much less), which makes the engine take the quickest win instead of
+// shuffling and put off a loss as long as it can. evaluations at the horizon get nothing extra.
+// TEMPO is under half of SCALE, so rounding always gives back the stones.
+const SCALE: mancala::Score = 256;
+const TEMPO: mancala::Score = 127;
+
+// the margin in stones of a score used inside the search
+fn stones(score: mancala::Score) -> mancala::Score {
+    (score + SCALE / 2).div_euclid(SCALE)
+}
+
+// the part of a score that says how far away the end of the game is
+fn tempo(score: mancala::Score) -> mancala::Score {
+    score - stones(score) * SCALE
+}
+
+// the table stores distances from the position itself rather than from the root, so a finished
+// line found at one ply is still right when the position comes up at another. only solved entries
+// are adjusted: a score that came from somewhere in the middle of the game can be any number.
+fn to_table(score: mancala::Score, ply: usize) -> mancala::Score {
+    let tempo = tempo(score);
+    score + tempo.signum() * cmp::min(ply as mancala::Score, TEMPO - tempo.abs())
+}
+
+fn from_table(score: mancala::Score, ply: usize) -> mancala::Score {
+    let tempo = tempo(score);
+    score - tempo.signum() * cmp::min(ply as mancala::Score, tempo.abs() - 1)
+}
+
+// which search runs at each iteration of iterative deepening
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Algorithm {
+    // full-window alpha-beta
+    #[default]
+    AlphaBeta,
+    // MTD(f): a series of zero-window searches converging on the score, starting from the
+    // previous iteration's score and relying on the transposition table to avoid re-searching
+    Mtdf,
+}
+
+// engine settings that stay the same from one search to the next
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineOptions {
+    pub algorithm: Algorithm,
+    // threads searching the same tree ("Lazy SMP"); they only cooperate through the shared
+    // transposition table. the first thread's result is the one returned, but what the helpers
+    // leave in the table depends on timing, so searches with more than one thread aren't reproducible.
+    pub threads: usize,
+    // with at most this many stones left in the pits the game tree is small enough to solve,
+    // so the depth limit is lifted and the search runs to the end of the game (time and node
+    // limits still apply). only under rules where every game does end (see `endgame::supports`)
+    // and that have no move limit, like solve::Solver.
+    pub endgame_stones: mancala::Score,
+    // search deeper as the board empties out, since there are fewer moves to look at: up to half
+    // as deep again as the depth limit when almost nothing is left (see `adaptive_depth`). off by
+    // default so that a depth limit means the same thing everywhere.
+    pub adaptive_depth: bool,
+    // how much the engine dislikes draws, in stones: a drawn game counts as losing by this much
+    // for the side the engine is playing (negative to steer towards draws instead). scores
+    // reported for drawn lines include it.
+    pub contempt: mancala::Score,
+    // how many of the best root moves to find, each with its own exact score and PV. above 1
+    // every root move has to be searched with a wider window, so it costs some strength.
+    pub multipv: usize,
+    // play a random one of the root moves scoring within this many stones of the best (0 to only
+    // vary between equally good moves), instead of always the first best one. like multi-PV, it
+    // needs exact scores for more root moves.
+    pub random_margin: Option<mancala::Score>,
+    // seeds the random choices, so a game can be replayed exactly
+    pub seed: u64,
+    // play weaker, at about this rating (see `Handicap`), so that beginners can win sometimes
+    pub strength: Option<u32>,
+    // up to this many stones either way added to every evaluation at the horizon, so the engine
+    // misjudges positions the way a person might. the noise depends only on the position and the
+    // seed, so a position always looks the same.
+    pub eval_noise: mancala::Score,
+    // for the default evaluator (see `Searcher::set_evaluator`)
+    pub weights: EvalWeights,
+    // which of the nearly-best root moves it likes to play
+    pub style: Style,
+    // for SearchResult::win_prob
+    pub win_model: WinModel,
+    // at the depth limit, keep following captures and extra turns (up to this many more moves)
+    // until the position is quiet, instead of judging it in the middle of a fight. off by default
+    // so that a depth limit means exactly that depth.
+    pub quiescence: Option<usize>,
+    // the size of the transposition table, in megabytes (None for tt::DEFAULT_ENTRIES)
+    pub tt_megabytes: Option<usize>,
+    pub tt_replacement: tt::Replacement,
+    // mark the table's entries as old at the start of every search, so that results from
+    // earlier moves of a long game give way to ones about the current position (see
+    // tt::Replacement::DepthPreferred). they still get used until they're overwritten.
+    pub tt_aging: bool,
+}
+
+// a taste for certain kinds of moves: among the root moves scoring within `slack` stones of the
+// best, the one with the most bonus gets played. the bonuses are in hundredths of a stone, and
+// only decide between moves; they never change a score.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Style {
+    // per stone captured
+    pub capture: mancala::Score,
+    // per extra turn earned
+    pub extra_turn: mancala::Score,
+    pub slack: mancala::Score,
+}
+
+impl Style {
+    fn bonus(&self, info: &mancala::MoveInfo) -> mancala::Score {
+        self.capture * info.captured + self.extra_turn * info.extra_turns as mancala::Score
+    }
+}
+
+// ways of playing that an opponent can tell apart, to make repeated games less alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Personality {
+    // keeps stones on its own side, even at the cost of banking them later
+    Hoarder,
+    // grabs every capture it can get away with
+    CaptureHungry,
+    // plays for extra turns and a solid position rather than material right now
+    Positional,
+}
+
+impl Personality {
+    pub const ALL: [Personality; 3] = [Personality::Hoarder, Personality::CaptureHungry, Personality::Positional];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Personality::Hoarder => "hoarder",
+            Personality::CaptureHungry => "capture-hungry",
+            Personality::Positional => "positional"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|personality| personality.name() == name)
+    }
+
+    pub fn weights(self) -> EvalWeights {
+        match self {
+            Personality::Hoarder => EvalWeights { own_side: 40, ..EvalWeights::default() },
+            Personality::CaptureHungry => EvalWeights::default(),
+            Personality::Positional => EvalWeights { own_side: 15, ..EvalWeights::default() }
+        }
+    }
+
+    pub fn style(self) -> Style {
+        match self {
+            Personality::Hoarder => Style::default(),
+            Personality::CaptureHungry => Style { capture: 100, extra_turn: 0, slack: 2 },
+            Personality::Positional => Style { capture: 0, extra_turn: 100, slack: 1 }
+        }
+    }
+
+    // the options with this personality's evaluation and style
+    pub fn apply(self, options: EngineOptions) -> EngineOptions {
+        EngineOptions { weights: self.weights(), style: self.style(), ..options }
+    }
+}
+
+// forcing moves past the horizon for the presets that look for them (see EngineOptions::quiescence)
+pub const QUIESCENCE: usize = 6;
+
+// solving from here takes well under a second
+pub const ENDGAME_STONES: mancala::Score = 12;
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            algorithm: Algorithm::AlphaBeta,
+            threads: 1,
+            endgame_stones: ENDGAME_STONES,
+            adaptive_depth: false,
+            contempt: 0,
+            multipv: 1,
+            random_margin: None,
+            seed: 0,
+            strength: None,
+            eval_noise: 0,
+            weights: EvalWeights::default(),
+            style: Style::default(),
+            win_model: WinModel::default(),
+            quiescence: None,
+            tt_megabytes: None,
+            tt_replacement: tt::Replacement::default(),
+            tt_aging: true,
+        }
+    }
+}
+
+// ready-made bundles of settings, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Beginner,
+    Easy,
+    Medium,
+    Hard,
+    Max,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] = [Difficulty::Beginner, Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Max];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "beginner",
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Max => "max"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|difficulty| difficulty.name() == name)
+    }
+
+    pub fn options(self) -> EngineOptions {
+        let defaults = EngineOptions::default();
+        match self {
+            Difficulty::Beginner => EngineOptions { strength: Some(250), eval_noise: 3, ..defaults },
+            Difficulty::Easy => EngineOptions { strength: Some(1000), eval_noise: 2, ..defaults },
+            Difficulty::Medium => EngineOptions { random_margin: Some(1), eval_noise: 1, ..defaults },
+            Difficulty::Hard => EngineOptions { random_margin: Some(0), adaptive_depth: true, quiescence: Some(QUIESCENCE), ..defaults },
+            Difficulty::Max => EngineOptions { adaptive_depth: true, quiescence: Some(QUIESCENCE), ..defaults }
+        }
+    }
+
+    pub fn limits(self) -> SearchLimits {
+        match self {
+            Difficulty::Beginner => SearchLimits::depth(2),
+            Difficulty::Easy => SearchLimits::depth(4),
+            Difficulty::Medium => SearchLimits::depth(6),
+            Difficulty::Hard => SearchLimits { depth: Some(10), movetime: Some(Duration::from_secs(2)), ..SearchLimits::default() },
+            Difficulty::Max => SearchLimits::movetime(Duration::from_secs(5))
+        }
+    }
+}
+
+// the top of the strength scale, where the engine stops making mistakes on purpose
+pub const MAX_STRENGTH: u32 = 2000;
+
+// how a weakened engine plays. the ratings are a rough scale rather than anything measured
+// against people: 0 looks one move ahead and often plays a move up to 9 stones worse than the
+// best, each 250 points adds a move of depth, and MAX_STRENGTH only differs from full strength
+// in the depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handicap {
+    pub depth: usize,
+    // the chance of picking a worse move on purpose
+    pub mistakes: f64,
+    // how much worse (in stones) that move can be
+    pub mistake_size: mancala::Score,
+}
+
+impl Handicap {
+    pub fn for_rating(rating: u32) -> Self {
+        let rating = cmp::min(rating, MAX_STRENGTH);
+        let missing = MAX_STRENGTH - rating;
+        Handicap {
+            depth: 1 + rating as usize / 250,
+            mistakes: 0.5 * missing as f64 / MAX_STRENGTH as f64,
+            mistake_size: 1 + missing as mancala::Score / 250,
+        }
+    }
+}
+
+// the depth to search a position to, given the depth wanted at the start of the game
+pub fn adaptive_depth(depth: usize, node: &mancala::Node) -> usize {
+    let layout = &node.get_rules().layout;
+    let start = (2 * layout.pits) as mancala::Score * mancala::STONES;
+    let gone = (start - node.stones_in_play()).clamp(0, start);
+    depth + depth * gone as usize / (2 * start as usize)
+}
+
+// a child from Node::children_with_info, with its position in that list
+type Child = (usize, (mancala::Move, mancala::Node, mancala::MoveInfo));
+
+// the state a search keeps between nodes (and between iterations of iterative deepening)
+pub struct Searcher {
+    options: EngineOptions,
+    budget: Budget,
+    tt: Arc<tt::TranspositionTable>,
+    tt_hits: u64,
+    // per ply, the two most recent moves that caused a beta cutoff there
+    killers: Vec<[Option<mancala::Move>; 2]>,
+    // per player and starting pocket of a full move, how much that move has caused cutoffs
+    // (weighted by remaining depth squared). grows as needed for bigger boards.
+    history: [Vec<u64>; 2],
+    // triangular PV table: pv_table[ply] is the best line found from the node currently searched at that ply
+    pv_table: Vec<Vec<mancala::Move>>,
+    // exact values for positions with few stones left, used instead of searching them
+    endgame_db: Option<Arc<EndgameDb>>,
+    // opening moves to play without searching
+    book: Option<Arc<Book>>,
+    evaluator: Arc<dyn Evaluator>,
+    info_callback: Option<InfoCallback>,
+    stop: StopToken,
+    // the side to move at the root of the current search
+    root: mancala::Player,
+    rng: Rng,
+    // how far below the best root move this search may pick one (see `random_margin`)
+    margin: Option<mancala::Score>,
+    // whether a position and its mirror image share table entries in this search (see `key`)
+    symmetric: bool,
+    // the tree of the iteration in progress, if one's being recorded (see `record_tree`), and of
+    // the last one that finished
+    tree: Option<SearchTree>,
+    finished_tree: Option<SearchTree>,
+    // set when the node just searched was settled by the table
+    tt_cutoff: bool,
+    // see `search_from_scratch`
+    from_scratch: bool,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new(EngineOptions::default())
+    }
+}
+
+impl Searcher {
+    pub fn new(options: EngineOptions) -> Self {
+        let entries = options.tt_megabytes.map_or(tt::DEFAULT_ENTRIES, tt::entries_for_megabytes);
+        Self::with_table(options, Arc::new(tt::TranspositionTable::with_replacement(entries, options.tt_replacement)))
+    }
+
+    // a searcher sharing its transposition table with others, possibly on other threads. the
+    // table keeps its own size and replacement policy, whatever the options say.
+    pub fn with_table(options: EngineOptions, tt: Arc<tt::TranspositionTable>) -> Self {
+        Searcher {
+            options,
+            budget: Budget::new(SearchLimits::default(), Vec::new()),
+            stop: StopToken::new(),
+            root: mancala::Player::White,
+            rng: Rng::new(options.seed),
+            margin: None,
+            symmetric: false,
+            tree: None,
+            finished_tree: None,
+            tt_cutoff: false,
+            from_scratch: false,
+            tt,
+            tt_hits: 0,
+            killers: Vec::new(),
+            history: [Vec::new(), Vec::new()],
+            pv_table: Vec::new(),
+            endgame_db: None,
+            book: None,
+            evaluator: Arc::new(WeightedEval::new(options.weights)),
+            info_callback: None,
+        }
+    }
+
+    pub fn set_book(&mut self, book: Option<Arc<Book>>) {
+        self.book = book;
+    }
+
+    pub fn set_endgame_db(&mut self, endgame_db: Option<Arc<EndgameDb>>) {
+        self.endgame_db = endgame_db;
+    }
+
+    // keep the tree each later search explores, up to `limit` positions (None to stop). only the
+    // last iteration's tree is kept, and only this thread's part of it. it costs some speed.
+    pub fn record_tree(&mut self, limit: Option<usize>) {
+        self.tree = limit.map(SearchTree::new);
+        self.finished_tree = None;
+    }
+
+    // the tree of the last search's deepest finished iteration, if it was recorded
+    pub fn tree(&self) -> Option<&SearchTree> {
+        self.finished_tree.as_ref()
+    }
+
+    // what judges the positions at the horizon. None goes back to a WeightedEval with the
+    // options' weights. the table is cleared, since its scores came from the old one.
+    pub fn set_evaluator(&mut self, evaluator: Option<Arc<dyn Evaluator>>) {
+        self.evaluator = evaluator.unwrap_or_else(|| Arc::new(WeightedEval::new(self.options.weights)));
+        self.tt.clear();
+    }
+
+    // have every later search start like a new searcher's: an empty table, no move ordering
+    // history and the random choices back at the seed. slower, but a search can then be run
+    // again on its own and come out the same (see repro).
+    pub fn search_from_scratch(&mut self, on: bool) {
+        self.from_scratch = on;
+    }
+
+    // searches with iterative deepening until one of the limits is hit.
+    // if the search is cut short, the result of the deepest completed iteration is returned.
+    pub fn search(&mut self, node: &mancala::Node, mut limits: SearchLimits) -> SearchResult {
+        if self.from_scratch {
+            self.tt.clear();
+            self.history = Default::default();
+            self.killers.clear();
+            self.rng = Rng::new(self.options.seed);
+        }
+        if let Some(mv) = self.book.as_ref().and_then(|book| book.probe(node)) {
+            // nothing was searched, so the score is just the current material
+            return SearchResult {
+                best_move: Some(mv.clone()),
+                score: node.eval(),
+                pv: vec![mv.clone()],
+                depth: 0,
+                nodes: 0,
+                time: Duration::ZERO,
+                tt_hits: 0,
+                exact: false,
+                ends_in: None,
+                win_prob: self.options.win_model.win_prob(node.eval(), node.stones_in_play(), false),
+                lines: vec![PvLine { score: node.eval(), pv: vec![mv] }],
+            };
+        }
+        let rules = node.get_rules();
+        if node.stones_in_play() <= self.options.endgame_stones && endgame::supports(rules) && rules.move_limit.is_none() {
+            limits.depth = None;
+        }
+        else if self.options.adaptive_depth {
+            limits.depth = limits.depth.map(|depth| adaptive_depth(depth, node));
+        }
+        self.margin = self.options.random_margin;
+        if self.options.style != Style::default() {
+            self.margin = Some(cmp::max(self.options.style.slack, self.margin.unwrap_or(0)));
+        }
+        if let Some(handicap) = self.options.strength.map(Handicap::for_rating) {
+            // (endgames included: a weak engine shouldn't suddenly play them perfectly)
+            limits.depth = Some(limits.depth.map_or(handicap.depth, |depth| cmp::min(depth, handicap.depth)));
+            if self.rng.next_f64() < handicap.mistakes {
+                self.margin = Some(cmp::max(handicap.mistake_size, self.margin.unwrap_or(0)));
+            }
+        }
+        if self.options.tt_aging {
+            self.tt.new_search();
+        }
+        if self.options.threads <= 1 {
+            return self.iterate(node, limits, 1, vec![self.stop.clone()]);
+        }
+        let done = StopToken::new();
+        thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.options.threads).map(|id| {
+                let options = EngineOptions { threads: 1, ..self.options };
+                let mut helper = Searcher::with_table(options, self.tt.clone());
+                helper.set_endgame_db(self.endgame_db.clone());
+                // (not through set_evaluator, which would clear the shared table)
+                helper.evaluator = self.evaluator.clone();
+                let stops = vec![self.stop.clone(), done.clone()];
+                // half the helpers run one iteration ahead, so the threads don't all search the same depth
+                scope.spawn(move || helper.iterate(node, SearchLimits { nodes: None, ..limits }, 1 + id % 2, stops))
+            }).collect();
+            let mut result = self.iterate(node, limits, 1, vec![self.stop.clone()]);
+            done.stop();
+            for helper in helpers {
+                let helper_result = helper.join().expect("search thread panicked");
+                result.nodes += helper_result.nodes;
+                result.tt_hits += helper_result.tt_hits;
+            }
+            result
+        })
+    }
+
+    // iterative deepening on this thread, starting at `first_depth`
+    fn iterate(&mut self, node: &mancala::Node, limits: SearchLimits, first_depth: usize, stops: Vec<StopToken>) -> SearchResult {
+        self.budget = Budget::new(limits, stops);
+        self.root = node.get_turn().clone();
+        // contempt makes a draw worth something different to each player, so it doesn't look the
+        // same with the colors swapped
+        self.symmetric = self.options.contempt == 0 && node.get_rules().layout.is_symmetric() && self.evaluator.symmetric();
+        self.tt_hits = 0;
+        self.killers.clear();
+        // keep some of what we learned about the previous position, but let it fade
+        for table in self.history.iter_mut() {
+            table.iter_mut().for_each(|count| *count /= 2);
+        }
+        let mut best = (None, sign(node.get_turn()) * node.eval() * SCALE);
+        let mut pv = Vec::new();
+        let mut lines = Vec::new();
+        let mut completed = 0;
+        let mut exact = false;
+        let mut depth = first_depth;
+        while limits.depth.is_none_or(|max_depth| depth <= max_depth) {
+            self.budget.horizon = false;
+            if let Some(tree) = self.tree.as_mut() {
+                tree.clear();
+            }
+            let result = if self.options.multipv > 1 || self.margin.is_some() {
+                let found = self.root_lines(node, depth);
+                let result = found.first().map_or((None, -INFINITY), |(score, line)| (line.first().cloned(), *score));
+                if !self.budget.aborted {
+                    lines = found;
+                }
+                result
+            }
+            else {
+                match self.options.algorithm {
+                    Algorithm::AlphaBeta => self.negamax(node, depth, 0, -INFINITY, INFINITY),
+                    Algorithm::Mtdf => self.mtdf(node, depth, best.1)
+                }
+            };
+            if self.budget.aborted {
+                // a partial first iteration still beats having no move at all
+                if best.0.is_none() {
+                    best = result;
+                }
+                break;
+            }
+            best = result;
+            if let Some(tree) = self.tree.as_ref() {
+                self.finished_tree = Some(tree.clone());
+            }
+            pv = match (lines.first(), self.options.algorithm) {
+                (Some((_, line)), _) => line.clone(),
+                (None, Algorithm::AlphaBeta) => self.pv_table[0].clone(),
+                // zero-window searches mostly fail high or low, so the PV table only has fragments
+                (None, Algorithm::Mtdf) => self.tt_pv(node, depth)
+            };
+            completed = depth;
+            if let Some(callback) = self.info_callback.as_mut() {
+                callback(&SearchInfo {
+                    depth,
+                    score: sign(node.get_turn()) * stones(best.1),
+                    pv: pv.clone(),
+                    nodes: self.budget.nodes,
+                    time: self.budget.start.elapsed(),
+                });
+            }
+            log::event("search", Level::Debug, || format!("depth {} score {:+} nodes {} table hits {}", depth, sign(node.get_turn()) * stones(best.1), self.budget.nodes, self.tt_hits));
+            if !self.budget.horizon {
+                exact = true;
+                break;
+            }
+            depth += 1;
+        }
+        if let Some(margin) = self.margin {
+            let candidates = lines.iter().take_while(|(score, _)| *score >= lines[0].0 - margin * SCALE).count();
+            if candidates > 1 {
+                let index = match self.options.style == Style::default() {
+                    true => self.rng.below(candidates),
+                    false => self.favourite(node, &lines[..candidates])
+                };
+                let (score, line) = &lines[index];
+                best = (line.first().cloned(), *score);
+                pv = line.clone();
+            }
+            lines.truncate(cmp::max(self.options.multipv, 1));
+        }
+        if best.0.is_none() {
+            best.0 = node.legal_moves().into_iter().next();
+        }
+        if pv.is_empty() {
+            pv = best.0.iter().cloned().collect();
+        }
+        let color = sign(node.get_turn());
+        let lines = match lines.is_empty() {
+            true => vec![PvLine { score: color * stones(best.1), pv: pv.clone() }],
+            false => lines.into_iter().map(|(score, pv)| PvLine { score: color * stones(score), pv }).collect()
+        };
+        let tempo = tempo(best.1);
+        SearchResult {
+            best_move: best.0,
+            score: color * stones(best.1),
+            ends_in: (exact && tempo != 0).then(|| (TEMPO - tempo.abs()) as usize),
+            // a proven draw can still have contempt in its score
+            win_prob: self.options.win_model.win_prob(if exact && tempo == 0 { 0 } else { color * stones(best.1) }, node.stones_in_play(), exact),
+            lines,
+            pv,
+            depth: completed,
+            nodes: self.budget.nodes,
+            time: self.budget.start.elapsed(),
+            tt_hits: self.tt_hits,
+            exact,
+        }
+    }
+
+    // the `multipv` best root moves with their scores (for the side to move) and lines, best first,
+    // and after them any others within `margin` of the best. each move is searched with alpha
+    // set to the worst score that would still make the list, so moves that can't fail low cheaply,
+    // and the rest (with beta at infinity) get exact scores.
+    fn root_lines(&mut self, node: &mancala::Node, depth: usize) -> Vec<(mancala::Score, Vec<mancala::Move>)> {
+        let count = cmp::max(self.options.multipv, 1);
+        let margin = self.margin.map(|margin| margin * SCALE);
+        let mut children: Vec<Child> = node.children_with_info().into_iter().enumerate().collect();
+        let tt_move = self.tt.probe(self.key(node)).and_then(|entry| entry.best_move);
+        self.order_moves(&mut children, node.get_turn(), 0, tt_move);
+        let mut lines: Vec<(mancala::Score, Vec<mancala::Move>)> = Vec::new();
+        for (_, (mv, child, _)) in children {
+            let mut alpha = if lines.len() >= count { lines[count - 1].0 } else { -INFINITY };
+            if let (Some(margin), Some((best, _))) = (margin, lines.first()) {
+                alpha = cmp::min(alpha, best - margin - 1);
+            }
+            if let Some(tree) = self.tree.as_mut() {
+                tree.next_move(&mv);
+            }
+            let score = if child.get_turn() == node.get_turn() {
+                self.negamax(&child, depth - 1, 1, alpha, INFINITY).1
+            }
+            else {
+                -self.negamax(&child, depth - 1, 1, -INFINITY, -alpha).1
+            };
+            if self.budget.aborted { break; }
+            if score > alpha || lines.len() < count {
+                let mut line = vec![mv];
+                line.extend(self.pv_table[1].iter().cloned());
+                let position = lines.iter().position(|(known, _)| *known < score).unwrap_or(lines.len());
+                lines.insert(position, (score, line));
+                let best = lines[0].0;
+                let mut rank = 0;
+                lines.retain(|(score, _)| {
+                    rank += 1;
+                    rank <= count || margin.is_some_and(|margin| *score >= best - margin)
+                });
+            }
+        }
+        lines
+    }
+
+    // the line whose first move the style likes best (the better scoring one on a tie)
+    fn favourite(&self, node: &mancala::Node, lines: &[(mancala::Score, Vec<mancala::Move>)]) -> usize {
+        let children = node.children_with_info();
+        let bonus = |mv: &mancala::Move| children.iter().find(|(child_move, _, _)| child_move == mv).map_or(0, |(_, _, info)| self.options.style.bonus(info));
+        let value = |(score, line): &(mancala::Score, Vec<mancala::Move>)| score + line.first().map_or(0, bonus) * SCALE / eval::STONE;
+        (0..lines.len()).max_by_key(|&index| (value(&lines[index]), cmp::Reverse(index))).unwrap_or(0)
+    }
+
+    // MTD(f): narrows [lower, upper] with zero-window searches around the current guess until
+    // the bounds meet. the score is from the point of view of the side to move.
+    fn mtdf(&mut self, node: &mancala::Node, depth: usize, guess: mancala::Score) -> (Option<mancala::Move>, mancala::Score) {
+        let mut score = guess;
+        let (mut lower, mut upper) = (-INFINITY, INFINITY);
+        let mut best_move = None;
+        while lower < upper {
+            let beta = if score == lower { score + 1 } else { score };
+            let (mv, result) = self.negamax(node, depth, 0, beta - 1, beta);
+            if self.budget.aborted { break; }
+            score = result;
+            if score < beta {
+                upper = score;
+            }
+            else {
+                // only a search that failed high has actually proven its move
+                lower = score;
+                best_move = mv;
+            }
+        }
+        (best_move, score)
+    }
+
+    // the transposition table key. with contempt, a draw is scored differently depending on who
+    // the engine plays, so entries stored while searching for Black don't count for White.
+    fn key(&self, node: &mancala::Node) -> u64 {
+        if self.symmetric {
+            // the table's scores are for the side to move, the same in both
+            return node.symmetric_hash().0;
+        }
+        match (self.options.contempt, &self.root) {
+            (0, _) | (_, mancala::Player::White) => node.hash(),
+            (_, mancala::Player::Black) => !node.hash()
+        }
+    }
+
+    // the search's score (from White's point of view) of a game ending `ply` moves from the root
+    // with this final score (White minus Black), adjusted for contempt. a level game the tie-break
+    // hands to one side is the smallest win there is for it: just the tempo, which is less than
+    // any win by a stone. (a database score isn't a finished game, so there's no telling yet who
+    // the tie-break would favour.)
+    fn final_score(&self, node: &mancala::Node, score: mancala::Score, ply: usize) -> mancala::Score {
+        let tempo = TEMPO - cmp::min(ply, TEMPO as usize - 1) as mancala::Score;
+        if score == 0 {
+            return match node.get_rules().tie_break {
+                mancala::TieBreak::Draw => -sign(&self.root) * self.options.contempt * SCALE,
+                _ => match node.tie_winner().filter(|_| node.is_over()) {
+                    Some(winner) => sign(&winner) * tempo,
+                    None => 0
+                }
+            };
+        }
+        score * SCALE + score.signum() * tempo
+    }
+
+    // a position's evaluation as the search sees it, from White's point of view
+    fn evaluate(&self, node: &mancala::Node) -> mancala::Score {
+        let score = self.evaluator.evaluate(node) * SCALE / eval::STONE;
+        let noise = self.options.eval_noise;
+        if noise <= 0 {
+            return score;
+        }
+        // a mirror image gets the opposite noise, so that sharing table entries with it changes nothing
+        let (key, mirrored) = match self.symmetric {
+            true => node.symmetric_hash(),
+            false => (node.hash(), false)
+        };
+        let offset = (rng::splitmix64(key ^ self.options.seed) % (2 * noise + 1) as u64) as mancala::Score - noise;
+        score + if mirrored { -offset * SCALE } else { offset * SCALE }
+    }
+
+    // follows the table's best moves from the root, for at most `depth` moves
+    fn tt_pv(&self, node: &mancala::Node, depth: usize) -> Vec<mancala::Move> {
+        let mut pv = Vec::new();
+        let mut current = node.clone();
+        while pv.len() < depth {
+            let Some(index) = self.tt.probe(self.key(&current)).and_then(|entry| entry.best_move) else { break; };
+            let Some(mv) = current.legal_moves().into_iter().nth(index) else { break; };
+            if current.full_move(&mv).is_err() {
+                break;
+            }
+            pv.push(mv);
+        }
+        pv
+    }
+
+    fn is_killer(&self, ply: usize, mv: &mancala::Move) -> bool {
+        self.killers.get(ply).is_some_and(|killers| killers.iter().any(|killer| killer.as_ref() == Some(mv)))
+    }
+
+    fn store_killer(&mut self, ply: usize, mv: &mancala::Move) {
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None, None]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0].as_ref() != Some(mv) {
+            killers[1] = killers[0].take();
+            killers[0] = Some(mv.clone());
+        }
+    }
+
+    fn history_score(&self, player: &mancala::Player, mv: &mancala::Move) -> u64 {
+        self.history[player.index()].get(mv[0]).copied().unwrap_or(0)
+    }
+
+    fn record_history(&mut self, player: &mancala::Player, mv: &mancala::Move, depth: usize) {
+        let table = &mut self.history[player.index()];
+        if table.len() <= mv[0] {
+            table.resize(mv[0] + 1, 0);
+        }
+        table[mv[0]] += (depth * depth) as u64;
+    }
+
+    // searches the transposition table's move first, then forcing-looking moves: captures (biggest first),
+    // this ply's killer moves, moves that earn extra turns, and finally whatever has caused the most
+    // cutoffs elsewhere. the sort is stable so everything else stays in generation order.
+    fn order_moves(&self, children: &mut [Child], player: &mancala::Player, ply: usize, tt_move: Option<usize>) {
+        children.sort_by_cached_key(|(index, (mv, _, info))| {
+            cmp::Reverse((tt_move == Some(*index), info.captured, self.is_killer(ply, mv), info.extra_turns, self.history_score(player, mv)))
+        });
+    }
+
+    // searches a node, recording it in the tree if one's being kept (see `record_tree`)
+    fn negamax(&mut self, node: &mancala::Node, depth: usize, ply: usize, alpha: mancala::Score, beta: mancala::Score) -> (Option<mancala::Move>, mancala::Score) {
+        let window = |value: mancala::Score| (value.abs() < INFINITY).then(|| stones(value));
+        let entered = self.tree.as_mut().and_then(|tree| tree.enter(node.get_turn().clone(), depth, window(alpha), window(beta), alpha.saturating_add(1) >= beta));
+        let Some(id) = entered else {
+            let result = self.alpha_beta(node, depth, ply, alpha, beta);
+            // (so that a node further up doesn't take it for its own)
+            self.tt_cutoff = false;
+            return result;
+        };
+        let result = self.alpha_beta(node, depth, ply, alpha, beta);
+        let bound = match result.1 {
+            score if score <= alpha => tt::Bound::Upper,
+            score if score >= beta => tt::Bound::Lower,
+            _ => tt::Bound::Exact
+        };
+        let tt_cutoff = std::mem::take(&mut self.tt_cutoff);
+        if let Some(tree) = self.tree.as_mut() {
+            tree.leave(id, stones(result.1), bound, tt_cutoff);
+        }
+        result
     }
-    if depth == 0 {
-        return (None, node.eval());
+
+    // fail-soft alpha-beta in negamax form: every node maximises its own score, and a child's
+    // score is negated whenever the turn passes (it doesn't when a move ends the game in the mover's store).
+    // scores and the (alpha, beta) window are from the point of view of the side to move. each node gets
+    // its own window, and the returned score may fall outside it: a score <= alpha is an upper bound,
+    // a score >= beta a lower bound.
+    fn alpha_beta(&mut self, node: &mancala::Node, depth: usize, ply: usize, mut alpha: mancala::Score, beta: mancala::Score) -> (Option<mancala::Move>, mancala::Score) {
+        let color = sign(node.get_turn());
+        if self.pv_table.len() <= ply + 1 {
+            self.pv_table.resize(ply + 2, Vec::new());
+        }
+        self.pv_table[ply].clear();
+        let mut children: Vec<Child> = node.children_with_info().into_iter().enumerate().collect();
+        if children.is_empty() {
+            return (None, color * self.final_score(node, node.final_score(), ply));
+        }
+        // (not at the root, which needs a move). the database doesn't know how long the game
+        // has left, so it counts as ending here.
+        if let Some(score) = self.endgame_db.as_ref().filter(|_| ply > 0).and_then(|db| db.probe(node)) {
+            return (None, color * self.final_score(node, score, ply));
+        }
+        if depth == 0 {
+            self.budget.horizon = true;
+            return match self.options.quiescence {
+                Some(left) => (None, self.quiesce(node, children.into_iter().map(|(_, child)| child).collect(), ply, alpha, beta, left)),
+                None => (None, color * self.evaluate(node))
+            };
+        }
+        if self.budget.exhausted() {
+            return (None, color * self.evaluate(node));
+        }
+
+        let key = self.key(node);
+        let original_alpha = alpha;
+        let mut tt_move = None;
+        let entry = self.tt.probe(key).map(|entry| match entry.solved {
+            true => tt::Entry { score: from_table(entry.score, ply), ..entry },
+            false => entry
+        });
+        if let Some(entry) = entry {
+            // full-window (PV) nodes always search, so the principal variation stays intact
+            let zero_window = alpha.saturating_add(1) >= beta;
+            if ply > 0 && zero_window && entry.cutoff(depth, alpha, beta) {
+                self.tt_hits += 1;
+                self.tt_cutoff = true;
+                self.budget.horizon |= !entry.solved;
+                let best_move = entry.best_move.and_then(|index| children.get(index)).map(|(_, (mv, _, _))| mv.clone());
+                return (best_move, entry.score);
+            }
+            tt_move = entry.best_move;
+        }
+
+        self.order_moves(&mut children, node.get_turn(), ply, tt_move);
+        // track whether this subtree reaches the horizon anywhere, so the table knows if it's solved
+        let outer_horizon = std::mem::replace(&mut self.budget.horizon, false);
+        let mut best_score = -INFINITY;
+        let mut best_move = vec![];
+        let mut best_index = None;
+        for (index, (mv, child, _)) in children {
+            // principal variation search: with good ordering the first move is usually best, so
+            // the others only have to be shown no better, with a null window. one that turns out
+            // better after all gets searched again with the full window.
+            let search = |searcher: &mut Self, alpha: mancala::Score, beta: mancala::Score| {
+                if let Some(tree) = searcher.tree.as_mut() {
+                    tree.next_move(&mv);
+                }
+                match child.get_turn() == node.get_turn() {
+                    true => searcher.negamax(&child, depth - 1, ply + 1, alpha, beta).1,
+                    false => -searcher.negamax(&child, depth - 1, ply + 1, -beta, -alpha).1
+                }
+            };
+            let mut score = match best_index {
+                Some(_) if alpha.saturating_add(1) < beta => search(self, alpha, alpha + 1),
+                _ => search(self, alpha, beta)
+            };
+            if best_index.is_some() && score > alpha && score < beta && alpha.saturating_add(1) < beta && !self.budget.aborted {
+                score = search(self, alpha, beta);
+            }
+            if self.budget.aborted { break; }
+            if score > best_score {
+                best_score = score;
+                let mut line = vec![mv.clone()];
+                line.extend(self.pv_table[ply + 1].iter().cloned());
+                self.pv_table[ply] = line;
+                best_move = mv;
+                best_index = Some(index);
+            }
+            alpha = cmp::max(alpha, best_score);
+            if alpha >= beta {
+                self.store_killer(ply, &best_move);
+                self.record_history(node.get_turn(), &best_move, depth);
+                break;
+            }
+        }
+        let solved = !self.budget.horizon;
+        self.budget.horizon |= outer_horizon;
+
+        let best_move = Some(best_move).filter(|mv| !mv.is_empty());
+        if !self.budget.aborted {
+            let bound = if best_score <= original_alpha {
+                tt::Bound::Upper
+            }
+            else if best_score >= beta {
+                tt::Bound::Lower
+            }
+            else {
+                tt::Bound::Exact
+            };
+            self.tt.store(tt::Entry { key, depth, score: if solved { to_table(best_score, ply) } else { best_score }, bound, best_move: best_index, solved });
+        }
+        (best_move, best_score)
     }
-    match node.get_turn() {
-        mancala::Player::White => {
-            let mut max_score = mancala::Score::MIN;
-            let mut max_move = vec![];
-            for (mv, child) in children {
-                let score = minimax(&child, depth - 1, alpha, beta).1;
-                if score > max_score {
-                    max_score = score;
-                    max_move = mv;
+}
+
+impl Searcher {
+    // the score (for the side to move) of a position past the horizon: the evaluation as it
+    // stands, unless a capture or extra turn does better, with the same chance for the other side
+    // in reply. `children` are the node's moves (there's at least one), `left` how many more
+    // forcing moves to follow.
+    fn quiesce(&mut self, node: &mancala::Node, children: Vec<(mancala::Move, mancala::Node, mancala::MoveInfo)>, ply: usize, mut alpha: mancala::Score, beta: mancala::Score, left: usize) -> mancala::Score {
+        let color = sign(node.get_turn());
+        let stand_pat = color * self.evaluate(node);
+        if left == 0 || stand_pat >= beta || self.budget.exhausted() {
+            return stand_pat;
+        }
+        let mut best = stand_pat;
+        alpha = cmp::max(alpha, best);
+        for (_, child, _) in children.into_iter().filter(|(_, _, info)| info.captured > 0 || info.extra_turns > 0) {
+            let same_side = child.get_turn() == node.get_turn();
+            let grandchildren = child.children_with_info();
+            let child_color = sign(child.get_turn());
+            let child_score = if grandchildren.is_empty() {
+                child_color * self.final_score(&child, child.final_score(), ply + 1)
+            }
+            else if let Some(score) = self.endgame_db.as_ref().and_then(|db| db.probe(&child)) {
+                child_color * self.final_score(&child, score, ply + 1)
+            }
+            else if same_side {
+                self.quiesce(&child, grandchildren, ply + 1, alpha, beta, left - 1)
+            }
+            else {
+                self.quiesce(&child, grandchildren, ply + 1, -beta, -alpha, left - 1)
+            };
+            if self.budget.aborted { break; }
+            let score = if same_side { child_score } else { -child_score };
+            best = cmp::max(best, score);
+            alpha = cmp::max(alpha, best);
+            if alpha >= beta { break; }
+        }
+        best
+    }
+}
+
+impl Engine for Searcher {
+    fn search(&mut self, node: &mancala::Node, limits: SearchLimits) -> SearchResult {
+        let result = Searcher::search(self, node, limits);
+        log::event("search", Level::Info, || {
+            let best = result.best_move.as_ref().map_or("-".to_string(), format_move);
+            format!("{} at depth {} score {:+}: {} nodes, {} table hits in a table of {}, {:.3}s", best, result.depth, result.score, result.nodes, result.tt_hits, self.tt.entries(), result.time.as_secs_f64())
+        });
+        result
+    }
+
+    fn tree(&self) -> Option<&SearchTree> {
+        Searcher::tree(self)
+    }
+
+    fn set_info_callback(&mut self, callback: Option<InfoCallback>) {
+        self.info_callback = callback;
+    }
+
+    fn set_stop_token(&mut self, token: StopToken) {
+        self.stop = token;
+    }
+
+    // the move ordering history was learned along the line that's gone. the table is keyed by
+    // position, not by move number, so what's in it is still true and stays.
+    fn takeback(&mut self) {
+        self.history = Default::default();
+        self.killers.clear();
+    }
+}
+
+// a one-off search with a fresh searcher
+pub fn search(node: &mancala::Node, limits: SearchLimits) -> SearchResult {
+    Searcher::default().search(node, limits)
+}
+
+// a plain fixed-depth search with no other limits. the score is from White's point of view.
+pub fn minimax(node: &mancala::Node, depth: usize) -> (Option<mancala::Move>, mancala::Score) {
+    let mut searcher = Searcher::default();
+    let (best_move, score) = searcher.negamax(node, depth, 0, -INFINITY, INFINITY);
+    (best_move, sign(node.get_turn()) * stones(score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movetime() {
+        let node = mancala::Node::default();
+        let start = Instant::now();
+        let result = search(&node, SearchLimits::movetime(Duration::from_millis(200)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(result.time < Duration::from_secs(2));
+        assert!(result.depth > 0);
+        assert!(node.legal_moves().contains(&result.best_move.unwrap()));
+    }
+
+    // full-width minimax with no pruning at all, from White's point of view
+    fn oracle(node: &mancala::Node, depth: usize) -> mancala::Score {
+        let children = node.children();
+        if children.is_empty() {
+            return node.final_score();
+        }
+        if depth == 0 {
+            return node.eval();
+        }
+        let scores = children.iter().map(|(_, child)| oracle(child, depth - 1));
+        match node.get_turn() {
+            mancala::Player::White => scores.max().unwrap(),
+            mancala::Player::Black => scores.min().unwrap()
+        }
+    }
+
+    // a handful of positions from the opening to the endgame, reached by a fixed sequence of moves
+    fn positions() -> Vec<mancala::Node> {
+        let mut result = Vec::new();
+        let mut node = mancala::Node::default();
+        let mut ply = 0;
+        loop {
+            if ply % 3 == 0 {
+                result.push(node.clone());
+            }
+            let mut children = node.children();
+            if children.is_empty() {
+                break;
+            }
+            let index = (ply * 7 + 3) % children.len();
+            node = children.swap_remove(index).1;
+            ply += 1;
+        }
+        result.push(node);
+        result
+    }
+
+    #[test]
+    fn test_endgame_solver() {
+        let boards = [
+            [0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 20],
+            [1, 1, 1, 1, 0, 0, 22, 0, 0, 0, 2, 1, 1, 18],
+            [0, 0, 3, 0, 0, 2, 19, 0, 4, 0, 0, 0, 1, 19],
+        ];
+        for (index, board) in boards.iter().enumerate() {
+            let turn = if index % 2 == 0 { mancala::Player::White } else { mancala::Player::Black };
+            let node = mancala::Node::from_board(board, turn, mancala::RuleSet::default()).unwrap();
+            let result = search(&node, SearchLimits::depth(1));
+            assert!(result.exact);
+            // deep enough to reach the end of every line
+            assert_eq!(result.score, oracle(&node, 64));
+
+            let options = EngineOptions { endgame_stones: 0, ..EngineOptions::default() };
+            let result = Searcher::new(options).search(&node, SearchLimits::depth(1));
+            assert_eq!(result.depth, 1);
+        }
+
+        // without stores positions can come round again forever, so the depth limit stays
+        let node = mancala::Node::from_fen("2,0,1,0,3,0,1,0,2,0,1,0 b 18 18", mancala::RuleSet::variant("oware").unwrap()).unwrap();
+        assert!(node.stones_in_play() <= 12);
+        assert_eq!(search(&node, SearchLimits::depth(4)).depth, 4);
+    }
+
+    // the final White margin with perfect play, and how many moves it takes, with the winner
+    // ending the game as soon as it can and the loser putting it off
+    fn ending(node: &mancala::Node) -> (mancala::Score, usize) {
+        let children = node.children();
+        if children.is_empty() {
+            return (node.final_score(), 0);
+        }
+        // better for White, with the length only mattering to whoever is ahead
+        let key = |&(score, moves): &(mancala::Score, usize)| (score, -score.signum() * moves as mancala::Score);
+        let endings = children.iter().map(|(_, child)| ending(child)).map(|(score, moves)| (score, moves + 1));
+        match node.get_turn() {
+            mancala::Player::White => endings.max_by_key(key).unwrap(),
+            mancala::Player::Black => endings.min_by_key(key).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_ends_in() {
+        let boards = [
+            [0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 20],
+            [1, 1, 1, 1, 0, 0, 22, 0, 0, 0, 2, 1, 1, 18],
+            [0, 0, 3, 0, 0, 2, 19, 0, 4, 0, 0, 0, 1, 19],
+            [3, 0, 0, 0, 0, 1, 20, 0, 1, 0, 0, 0, 0, 23],
+        ];
+        for board in boards {
+            for turn in [mancala::Player::White, mancala::Player::Black] {
+                let node = mancala::Node::from_board(&board, turn, mancala::RuleSet::default()).unwrap();
+                let result = search(&node, SearchLimits::depth(1));
+                let (score, moves) = ending(&node);
+                assert_eq!(result.score, score);
+                assert_eq!(result.ends_in, (score != 0).then_some(moves));
+                if score != 0 {
+                    assert_eq!(result.pv.len(), moves);
                 }
-                if max_score > *beta { break; }
-                *alpha = cmp::max(*alpha, max_score);
             }
-            (Some(max_move), max_score)
         }
-        mancala::Player::Black => {
-            let mut min_score = mancala::Score::MAX;
-            let mut min_move = vec![];
-            for (mv, child) in children {
-                let score = minimax(&child, depth - 1, alpha, beta).1;
-                if score < min_score {
-                    min_score = score;
-                    min_move = mv;
+        // not proven
+        assert_eq!(search(&mancala::Node::default(), SearchLimits::depth(3)).ends_in, None);
+        // once it's proven, the result is certain
+        let board = [0, 0, 0, 0, 0, 1, 20, 0, 0, 0, 0, 0, 1, 26];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let result = search(&node, SearchLimits::default());
+        assert!(result.exact);
+        assert_eq!(result.win_prob, 0.0);
+    }
+
+    #[test]
+    fn test_endgame_db() {
+        let board = [0, 2, 0, 1, 0, 0, 21, 1, 0, 1, 0, 0, 1, 21];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let options = EngineOptions { endgame_stones: 0, ..EngineOptions::default() };
+        let mut searcher = Searcher::new(options);
+        searcher.set_endgame_db(EndgameDb::build(mancala::RuleSet::default(), 6).map(Arc::new));
+        // every child is in the database, so one ply is enough to solve the position
+        let result = searcher.search(&node, SearchLimits::depth(1));
+        assert!(result.exact);
+        assert_eq!(result.score, oracle(&node, 64));
+    }
+
+    #[test]
+    fn test_book() {
+        let node = mancala::Node::default();
+        let mut searcher = Searcher::default();
+        searcher.set_book(Some(Arc::new(Book::starter())));
+        let result = searcher.search(&node, SearchLimits::depth(4));
+        assert_eq!(result.best_move, Book::starter().probe(&node));
+        assert_eq!(result.nodes, 0);
+    }
+
+    #[test]
+    fn test_info_callback() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut searcher = Searcher::default();
+        searcher.set_info_callback(Some(Box::new(move |info: &SearchInfo| sender.send(info.clone()).unwrap())));
+        let node = mancala::Node::default();
+        let result = searcher.search(&node, SearchLimits::depth(4));
+        let infos: Vec<SearchInfo> = receiver.try_iter().collect();
+        assert_eq!(infos.iter().map(|info| info.depth).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        let last = infos.last().unwrap();
+        assert_eq!((last.score, &last.pv), (result.score, &result.pv));
+    }
+
+    #[test]
+    fn test_stop_token() {
+        let node = mancala::Node::default();
+        for threads in [1, 2] {
+            let mut searcher = Searcher::new(EngineOptions { threads, ..EngineOptions::default() });
+            let token = StopToken::new();
+            searcher.set_stop_token(token.clone());
+            let stopper = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                token.stop();
+            });
+            // no limits at all: only the token can end this
+            let result = searcher.search(&node, SearchLimits::default());
+            stopper.join().unwrap();
+            assert!(node.legal_moves().contains(&result.best_move.unwrap()));
+            assert!(result.time < Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn test_node_limit() {
+        for node in positions().into_iter().take(4) {
+            let result = search(&node, SearchLimits::nodes(3000));
+            assert!(result.nodes <= 3000);
+            // same limit, same search, however fast the machine
+            let again = search(&node, SearchLimits::nodes(3000));
+            assert_eq!(SearchResult { time: result.time, ..again }, result);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_depth() {
+        let node = mancala::Node::default();
+        assert_eq!(adaptive_depth(10, &node), 10);
+        let board = [1, 2, 0, 1, 3, 1, 20, 2, 2, 0, 1, 0, 3, 12];
+        let late = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        // 16 of 48 stones left
+        assert_eq!(adaptive_depth(10, &late), 13);
+
+        let options = EngineOptions { adaptive_depth: true, ..EngineOptions::default() };
+        assert_eq!(Searcher::new(options).search(&late, SearchLimits::depth(4)).depth, 5);
+        assert_eq!(Searcher::new(options).search(&node, SearchLimits::depth(4)).depth, 4);
+    }
+
+    #[test]
+    fn test_contempt() {
+        let contempt = EngineOptions { contempt: 3, ..EngineOptions::default() };
+        // either side's only move ends the game in a draw
+        let board = [0, 0, 0, 0, 0, 1, 23, 0, 0, 0, 0, 0, 1, 23];
+        for (turn, score) in [(mancala::Player::White, -3), (mancala::Player::Black, 3)] {
+            let node = mancala::Node::from_board(&board, turn, mancala::RuleSet::default()).unwrap();
+            assert_eq!(search(&node, SearchLimits::depth(2)).score, 0);
+            assert_eq!(Searcher::new(contempt).search(&node, SearchLimits::depth(2)).score, score);
+        }
+
+        // [5, 4] draws, [4] loses by 2
+        let board = [0, 0, 0, 0, 1, 1, 22, 0, 0, 0, 1, 0, 1, 22];
+        let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        assert_eq!(search(&node, SearchLimits::depth(8)).best_move, Some(vec![5, 4]));
+        let result = Searcher::new(contempt).search(&node, SearchLimits::depth(8));
+        assert_eq!((result.best_move, result.score), (Some(vec![4]), -2));
+    }
+
+    #[test]
+    fn test_tie_break() {
+        // best play ends level either way; the tie-break decides which side White wants to run out
+        let board = [0, 0, 0, 1, 1, 1, 10, 0, 0, 0, 2, 0, 0, 11];
+        for (tie_break, best_move) in [(mancala::TieBreak::Emptier, vec![5, 3]), (mancala::TieBreak::Opponent, vec![5, 4])] {
+            let node = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet { tie_break, ..mancala::RuleSet::default() }).unwrap();
+            let result = search(&node, SearchLimits::depth(10));
+            assert_eq!((result.best_move, result.score, result.exact), (Some(best_move), 0, true));
+        }
+    }
+
+    #[test]
+    fn test_multipv() {
+        for node in positions().into_iter().take(5) {
+            let mut expected: Vec<_> = node.children().iter().map(|(_, child)| oracle(child, 3)).collect();
+            match node.get_turn() {
+                mancala::Player::White => expected.sort_by(|a, b| b.cmp(a)),
+                mancala::Player::Black => expected.sort()
+            }
+            expected.truncate(3);
+            let options = EngineOptions { multipv: 3, endgame_stones: 0, ..EngineOptions::default() };
+            let result = Searcher::new(options).search(&node, SearchLimits::depth(4));
+            assert_eq!(result.lines.iter().map(|line| line.score).collect::<Vec<_>>(), expected);
+            assert_eq!((result.score, &result.pv), (result.lines[0].score, &result.lines[0].pv));
+            assert_eq!(result.score, oracle(&node, 4));
+            for line in &result.lines {
+                assert!(line.pv.len() <= 4);
+                assert!(node.legal_moves().contains(&line.pv[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_expected_reply() {
+        for node in positions().into_iter().take(5) {
+            let result = search(&node, SearchLimits::depth(4));
+            let mut after = node.clone();
+            after.full_move(result.best_move.as_ref().unwrap()).unwrap();
+            if after.is_over() {
+                assert_eq!(result.expected_reply(), None);
+                continue;
+            }
+            let reply = result.expected_reply().unwrap();
+            assert_eq!(Some(reply), result.pv.get(1));
+            assert!(after.legal_moves().contains(reply));
+            // the opponent's best at the depth left scores the same
+            let mut line = after.clone();
+            line.full_move(reply).unwrap();
+            assert_eq!(oracle(&line, 2), result.score);
+        }
+    }
+
+    #[test]
+    fn test_random_margin() {
+        let node = mancala::Node::default();
+        let best = search(&node, SearchLimits::depth(4));
+        let mut moves = std::collections::HashSet::new();
+        for seed in 0..16 {
+            let options = EngineOptions { random_margin: Some(2), seed, ..EngineOptions::default() };
+            let result = Searcher::new(options).search(&node, SearchLimits::depth(4));
+            assert!(result.score >= best.score - 2);
+            assert_eq!((result.pv.first(), result.lines.len()), (result.best_move.as_ref(), 1));
+            // same seed, same game
+            assert_eq!(Searcher::new(options).search(&node, SearchLimits::depth(4)).best_move, result.best_move);
+            moves.insert(result.best_move.unwrap());
+
+            let options = EngineOptions { random_margin: Some(0), seed, ..EngineOptions::default() };
+            assert_eq!(Searcher::new(options).search(&node, SearchLimits::depth(4)).score, best.score);
+        }
+        assert!(moves.len() > 1);
+    }
+
+    #[test]
+    fn test_strength() {
+        let weakest = Handicap::for_rating(0);
+        assert_eq!((weakest.depth, weakest.mistake_size), (1, 9));
+        let strongest = Handicap::for_rating(MAX_STRENGTH + 500);
+        assert_eq!((strongest.depth, strongest.mistakes), (9, 0.0));
+        assert!(Handicap::for_rating(1000).mistakes < weakest.mistakes);
+
+        let node = mancala::Node::default();
+        let mut moves = std::collections::HashSet::new();
+        for seed in 0..16 {
+            let options = EngineOptions { strength: Some(0), seed, ..EngineOptions::default() };
+            let result = Searcher::new(options).search(&node, SearchLimits::depth(8));
+            assert_eq!(result.depth, 1);
+            assert_eq!(Searcher::new(options).search(&node, SearchLimits::depth(8)).best_move, result.best_move);
+            moves.insert(result.best_move.unwrap());
+        }
+        assert!(moves.len() > 1);
+        // the depth cap holds in the endgame too
+        let board = [0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 20];
+        let late = mancala::Node::from_board(&board, mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let options = EngineOptions { strength: Some(250), ..EngineOptions::default() };
+        assert!(Searcher::new(options).search(&late, SearchLimits::default()).depth <= 2);
+    }
+
+    #[test]
+    fn test_eval_noise() {
+        let node = mancala::Node::default();
+        let mut noisy = Searcher::new(EngineOptions { eval_noise: 2, ..EngineOptions::default() });
+        let mut offsets = std::collections::HashSet::new();
+        for (_, child) in node.children() {
+            let offset = stones(noisy.evaluate(&child)) - child.eval();
+            assert!((-2..=2).contains(&offset));
+            assert_eq!(noisy.evaluate(&child), noisy.evaluate(&child));
+            offsets.insert(offset);
+        }
+        assert!(offsets.len() > 1);
+        let result = noisy.search(&node, SearchLimits::depth(3));
+        let again = noisy.search(&node, SearchLimits::depth(3));
+        // the same answer again. the null-window searches can be cut off by what the first search
+        // left in the table, so it takes fewer nodes the second time, never more.
+        assert_eq!(SearchResult { time: result.time, nodes: result.nodes, tt_hits: result.tt_hits, ..again.clone() }, result);
+        assert!(again.nodes <= result.nodes && again.tt_hits > result.tt_hits);
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let node = mancala::Node::default();
+        for difficulty in Difficulty::ALL.into_iter().take(3) {
+            let result = Searcher::new(difficulty.options()).search(&node, difficulty.limits());
+            assert!(node.legal_moves().contains(&result.best_move.unwrap()));
+        }
+        assert!(Difficulty::Beginner.limits().depth < Difficulty::Medium.limits().depth);
+        assert_eq!(Difficulty::from_name("hard"), Some(Difficulty::Hard));
+    }
+
+    #[test]
+    fn test_quiescence() {
+        let mut differs = false;
+        for node in positions() {
+            let options = EngineOptions { endgame_stones: 0, quiescence: Some(2), ..EngineOptions::default() };
+            let quiet = Searcher::new(options).search(&node, SearchLimits::depth(2));
+            assert_eq!(quiet.score, quiescence_oracle(&node, 2, 2));
+            differs |= quiet.score != oracle(&node, 2);
+        }
+        // somewhere the fight goes on past the horizon
+        assert!(differs);
+    }
+
+    // full-width minimax, then at the horizon the best of standing pat and the forcing moves
+    fn quiescence_oracle(node: &mancala::Node, depth: usize, left: usize) -> mancala::Score {
+        let children = node.children_with_info();
+        if children.is_empty() {
+            return node.final_score();
+        }
+        let white = *node.get_turn() == mancala::Player::White;
+        let pick = |scores: Vec<mancala::Score>| if white { scores.into_iter().max().unwrap() } else { scores.into_iter().min().unwrap() };
+        if depth > 0 {
+            return pick(children.iter().map(|(_, child, _)| quiescence_oracle(child, depth - 1, left)).collect());
+        }
+        let mut scores = vec![node.eval()];
+        if left > 0 {
+            scores.extend(children.iter().filter(|(_, _, info)| info.captured > 0 || info.extra_turns > 0).map(|(_, child, _)| quiescence_oracle(child, 0, left - 1)));
+        }
+        pick(scores)
+    }
+
+    #[test]
+    fn test_evaluator() {
+        // sees nothing at all, so every position short of the end looks even
+        struct Blind;
+        impl Evaluator for Blind {
+            fn evaluate(&self, _: &mancala::Node) -> mancala::Score { 0 }
+        }
+        let node = mancala::Node::default();
+        let mut searcher = Searcher::default();
+        searcher.set_evaluator(Some(Arc::new(Blind)));
+        assert_eq!(searcher.search(&node, SearchLimits::depth(3)).score, 0);
+        searcher.set_evaluator(None);
+        assert_eq!(searcher.search(&node, SearchLimits::depth(3)).score, oracle(&node, 3));
+        // nothing the old evaluator left in the table gets into the new one's search
+        searcher.set_evaluator(Some(Arc::new(Blind)));
+        let blind = searcher.search(&node, SearchLimits::depth(3));
+        let mut fresh = Searcher::default();
+        fresh.set_evaluator(Some(Arc::new(Blind)));
+        assert_eq!(SearchResult { time: blind.time, ..fresh.search(&node, SearchLimits::depth(3)) }, blind);
+    }
+
+    #[test]
+    fn test_personality() {
+        assert_eq!(Personality::from_name("capture-hungry"), Some(Personality::CaptureHungry));
+        assert_eq!(Personality::from_name("timid"), None);
+        for personality in Personality::ALL {
+            let options = personality.apply(EngineOptions { endgame_stones: 0, ..EngineOptions::default() });
+            let style = options.style;
+            for node in positions().into_iter().take(6) {
+                let result = Searcher::new(options).search(&node, SearchLimits::depth(3));
+                let plain = Searcher::new(EngineOptions { style: Style::default(), ..options }).search(&node, SearchLimits::depth(3));
+                // the style only picks between moves within its slack, and picks the one it likes best
+                let color = sign(node.get_turn());
+                assert!(color * result.score >= color * plain.score - style.slack);
+                let children = node.children_with_info();
+                let bonus = |mv: &Option<mancala::Move>| children.iter().find(|(child_move, _, _)| Some(child_move) == mv.as_ref()).map(|(_, _, info)| style.bonus(info)).unwrap();
+                assert!(bonus(&result.best_move) >= bonus(&plain.best_move));
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_oracle() {
+        for node in positions() {
+            for depth in 1..=4 {
+                let expected = oracle(&node, depth);
+                assert_eq!(minimax(&node, depth).1, expected);
+                assert_eq!(search(&node, SearchLimits::depth(depth)).score, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fail_soft_bounds() {
+        for node in positions() {
+            let exact = Searcher::default().negamax(&node, 3, 0, -INFINITY, INFINITY).1;
+            assert_eq!(stones(exact), sign(node.get_turn()) * oracle(&node, 3));
+            for (alpha, beta) in [(exact - 1, exact + 1), (exact, exact + 5), (exact - 5, exact), (exact + 2, exact + 3), (exact - 3, exact - 2)] {
+                let score = Searcher::default().negamax(&node, 3, 0, alpha, beta).1;
+                if score <= alpha {
+                    assert!(exact <= score);
+                }
+                else if score >= beta {
+                    assert!(exact >= score);
+                }
+                else {
+                    assert_eq!(score, exact);
                 }
-                if min_score < *alpha { break; }
-                *beta = cmp::min(*beta, min_score);
             }
-            (Some(min_move), min_score)
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_killers() {
+        let mut searcher = Searcher::default();
+        searcher.store_killer(2, &vec![1]);
+        searcher.store_killer(2, &vec![3, 0]);
+        searcher.store_killer(2, &vec![3, 0]);
+        assert!(searcher.is_killer(2, &vec![1]));
+        assert!(searcher.is_killer(2, &vec![3, 0]));
+        assert!(!searcher.is_killer(1, &vec![1]));
+        searcher.store_killer(2, &vec![4]);
+        assert!(!searcher.is_killer(2, &vec![1]));
+
+        // killers carry over between iterations and positions without changing results
+        for node in positions() {
+            assert_eq!(searcher.search(&node, SearchLimits::depth(4)).score, oracle(&node, 4));
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut searcher = Searcher::default();
+        let white = mancala::Player::White;
+        searcher.record_history(&white, &vec![3, 1], 2);
+        searcher.record_history(&white, &vec![3], 3);
+        assert_eq!(searcher.history_score(&white, &vec![3, 5]), 13);
+        assert_eq!(searcher.history_score(&mancala::Player::Black, &vec![3]), 0);
+        assert_eq!(searcher.history_score(&white, &vec![12]), 0);
+
+        let node = mancala::Node::default();
+        assert_eq!(searcher.search(&node, SearchLimits::depth(5)).score, oracle(&node, 5));
+    }
+
+    #[test]
+    fn test_pv() {
+        for node in positions() {
+            let SearchResult { best_move, score, pv, .. } = search(&node, SearchLimits::depth(4));
+            if best_move.is_none() {
+                continue;
+            }
+            assert_eq!(pv.first(), best_move.as_ref());
+
+            // the line is playable and leads to the position whose evaluation is the search's score
+            let mut leaf = node.clone();
+            for mv in &pv {
+                assert!(leaf.legal_moves().contains(mv));
+                assert!(leaf.full_move(mv).is_ok());
+            }
+            let leaf_score = if leaf.is_over() { leaf.final_score() } else { leaf.eval() };
+            assert!(pv.len() == 4 || leaf.is_over());
+            assert_eq!(leaf_score, score);
+        }
+    }
+
+    #[test]
+    fn test_mtdf() {
+        let mut mtdf = Searcher::new(EngineOptions { algorithm: Algorithm::Mtdf, ..EngineOptions::default() });
+        for node in positions() {
+            let result = mtdf.search(&node, SearchLimits::depth(4));
+            assert_eq!(result.score, oracle(&node, 4));
+            if let Some(best_move) = result.best_move {
+                assert_eq!(result.pv.first(), Some(&best_move));
+                let mut child = node.clone();
+                assert!(child.full_move(&best_move).is_ok());
+            }
+        }
+
+        let node = mancala::Node::default();
+        let result = mtdf.search(&node, SearchLimits::depth(5));
+        assert!(result.tt_hits > 0);
+        assert_eq!(result.score, oracle(&node, 5));
+    }
+
+    #[test]
+    fn test_symmetric_keys() {
+        let evaluator = WeightedEval::default();
+        let mut searcher = Searcher::new(EngineOptions { eval_noise: 2, ..EngineOptions::default() });
+        for node in positions().into_iter().take(6) {
+            let mirrored = node.mirrored().unwrap();
+            assert_eq!(evaluator.evaluate(&mirrored), -evaluator.evaluate(&node));
+            let result = searcher.search(&node, SearchLimits::depth(4));
+            // the mirror image is all in the table already
+            let again = searcher.search(&mirrored, SearchLimits::depth(4));
+            assert_eq!(again.score, -result.score);
+            assert!(again.tt_hits > result.tt_hits);
+        }
+    }
+
+    #[test]
+    fn test_lazy_smp() {
+        for algorithm in [Algorithm::AlphaBeta, Algorithm::Mtdf] {
+            let mut searcher = Searcher::new(EngineOptions { algorithm, threads: 3, ..EngineOptions::default() });
+            for node in positions().into_iter().take(4) {
+                let result = searcher.search(&node, SearchLimits::depth(4));
+                assert_eq!(result.score, oracle(&node, 4));
+                assert_eq!(result.depth, 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_game_ending_in_own_store() {
+        // White's only move drops the last stone in its store and ends the game: the turn doesn't pass
+        let node = mancala::Node::from_board(&[0, 0, 0, 0, 0, 1, 20, 3, 0, 0, 0, 0, 0, 24], mancala::Player::White, mancala::RuleSet::default()).unwrap();
+        let result = search(&node, SearchLimits::depth(3));
+        assert_eq!((result.best_move, result.score, result.depth), (Some(vec![5]), 21 - 27, 1));
+    }
+}
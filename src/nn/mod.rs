@@ -0,0 +1,162 @@
+use super::eval::{Evaluator, STONE};
+use super::mancala::{Node, Player, Score};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"MKNN";
+const VERSION: u8 = 1;
+
+// one fully connected layer: outputs[o] = biases[o] + sum over i of weights[o * inputs + i] * inputs[i]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+// a small feedforward network judging positions. the inputs are the board's pockets in order
+// (pits and stores, as stone counts) followed by 1 if White is to move or -1 if Black is, so a
+// Kalah board takes 15 (other boards get cut or padded with zeros to fit). every layer but the
+// last is followed by a ReLU, and the last has a single output: the position's value in stones
+// from White's point of view.
+//
+// the file format, all little-endian: "MKNN", a version byte (1), a layer count byte, then for
+// each layer its input and output counts (u16 each), its weights row by row (one row of inputs
+// per output, as f32) and its biases (f32).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NnEval {
+    layers: Vec<Layer>,
+}
+
+impl NnEval {
+    // None unless each layer takes some inputs and feeds the next, and the last has one output
+    pub fn new(layers: Vec<Layer>) -> Option<Self> {
+        let sizes_fit = layers.iter().all(|layer| layer.inputs > 0 && layer.weights.len() == layer.inputs * layer.outputs && layer.biases.len() == layer.outputs);
+        let chained = layers.windows(2).all(|pair| pair[0].outputs == pair[1].inputs);
+        (sizes_fit && chained && layers.last()?.outputs == 1).then_some(NnEval { layers })
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.layers[0].inputs
+    }
+
+    // in stones, from White's point of view
+    pub fn forward(&self, node: &Node) -> f32 {
+        let side = match node.get_turn() {
+            Player::White => 1.0,
+            Player::Black => -1.0
+        };
+        let mut values: Vec<f32> = node.get_board().iter().map(|&stones| stones as f32).chain([side]).collect();
+        values.resize(self.inputs(), 0.0);
+        for (index, layer) in self.layers.iter().enumerate() {
+            let last = index + 1 == self.layers.len();
+            values = layer.weights.chunks(layer.inputs).zip(&layer.biases).map(|(row, bias)| {
+                let sum = bias + row.iter().zip(&values).map(|(weight, value)| weight * value).sum::<f32>();
+                if last { sum } else { sum.max(0.0) }
+            }).collect();
+        }
+        values[0]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.layers.len() as u8);
+        for layer in &self.layers {
+            bytes.extend_from_slice(&(layer.inputs as u16).to_le_bytes());
+            bytes.extend_from_slice(&(layer.outputs as u16).to_le_bytes());
+            for value in layer.weights.iter().chain(&layer.biases) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 6 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return None;
+        }
+        let mut rest = &bytes[6..];
+        let mut take = |count: usize| -> Option<&[u8]> {
+            let (taken, remaining) = rest.split_at_checked(count)?;
+            rest = remaining;
+            Some(taken)
+        };
+        let mut layers = Vec::new();
+        for _ in 0..bytes[5] {
+            let inputs = u16::from_le_bytes(take(2)?.try_into().ok()?) as usize;
+            let outputs = u16::from_le_bytes(take(2)?.try_into().ok()?) as usize;
+            let mut floats = |count: usize| -> Option<Vec<f32>> {
+                Some(take(4 * count)?.chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect())
+            };
+            let weights = floats(inputs * outputs)?;
+            let biases = floats(outputs)?;
+            layers.push(Layer { inputs, outputs, weights, biases });
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+        Self::new(layers)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a valid network file"))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+impl Evaluator for NnEval {
+    fn evaluate(&self, node: &Node) -> Score {
+        (self.forward(node) * STONE as f32).round() as Score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mancala::{BLACK_POCKET, WHITE_POCKET};
+
+    // a net that works out the store difference, through a hidden layer of two ReLUs
+    // (one for each sign of the difference)
+    fn store_difference() -> NnEval {
+        let mut hidden = vec![0.0; 2 * 15];
+        hidden[WHITE_POCKET] = 1.0;
+        hidden[BLACK_POCKET] = -1.0;
+        hidden[15 + WHITE_POCKET] = -1.0;
+        hidden[15 + BLACK_POCKET] = 1.0;
+        NnEval::new(vec![
+            Layer { inputs: 15, outputs: 2, weights: hidden, biases: vec![0.0; 2] },
+            Layer { inputs: 2, outputs: 1, weights: vec![1.0, -1.0], biases: vec![0.0] },
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_forward() {
+        let net = store_difference();
+        let node = Node::from_board(&[0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 23], Player::White, Default::default()).unwrap();
+        assert_eq!(net.forward(&node), -3.0);
+        assert_eq!(net.evaluate(&node), -3 * STONE);
+        assert!(NnEval::new(vec![Layer { inputs: 15, outputs: 2, weights: vec![0.0; 30], biases: vec![0.0; 2] }]).is_none());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let net = store_difference();
+        let bytes = net.to_bytes();
+        assert_eq!(NnEval::from_bytes(&bytes), Some(net));
+        assert_eq!(NnEval::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(NnEval::from_bytes(b"MKBK"), None);
+        // a layer without inputs would have no rows to work through
+        let empty = NnEval { layers: vec![Layer { inputs: 0, outputs: 1, weights: Vec::new(), biases: vec![0.0] }] };
+        assert_eq!(NnEval::from_bytes(&empty.to_bytes()), None);
+        let path = std::env::temp_dir().join(format!("mancala-rs-empty-{}.nn", std::process::id()));
+        empty.save(&path).unwrap();
+        assert_eq!(NnEval::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+}
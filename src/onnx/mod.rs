@@ -0,0 +1,312 @@
+use super::nn::{Layer, NnEval};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// networks trained elsewhere (PyTorch, Keras, ...) and exported to ONNX, read into an NnEval. only
+// what such a network needs is understood: a chain of fully connected layers (Gemm, or MatMul
+// then Add) with a Relu after each but the last, on float weights kept in the file. the input is
+// the NnEval one (the pockets and then 1 or -1 for the side to move) and the single output is in
+// stones from White's point of view. anything else is refused with what was found. the file is
+// protobuf, which is read here directly rather than through a runtime, so it needs nothing
+// beyond std.
+
+// ONNX's code for 32-bit floats in TensorProto.data_type
+const FLOAT: u64 = 1;
+
+// a protobuf field's value, by its wire type
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+fn varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Option<&'a [u8]> {
+    let (taken, rest) = bytes.split_at_checked(count)?;
+    *bytes = rest;
+    Some(taken)
+}
+
+// the fields of a protobuf message, by number, in the order they were written
+fn fields(mut bytes: &[u8]) -> Option<Vec<(u64, Value<'_>)>> {
+    let mut fields = Vec::new();
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes)?;
+        let value = match key & 7 {
+            0 => Value::Varint(varint(&mut bytes)?),
+            1 => Value::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().ok()?)),
+            2 => {
+                let length = varint(&mut bytes)? as usize;
+                Value::Bytes(take(&mut bytes, length)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().ok()?)),
+            _ => { return None; }
+        };
+        fields.push((key >> 3, value));
+    }
+    Some(fields)
+}
+
+fn messages<'a>(fields: &[(u64, Value<'a>)], number: u64) -> Vec<&'a [u8]> {
+    fields.iter().filter(|(field, _)| *field == number).filter_map(|(_, value)| match value {
+        Value::Bytes(bytes) => Some(*bytes),
+        _ => None
+    }).collect()
+}
+
+fn strings(fields: &[(u64, Value<'_>)], number: u64) -> Vec<String> {
+    messages(fields, number).into_iter().map(|bytes| String::from_utf8_lossy(bytes).into_owned()).collect()
+}
+
+fn string(fields: &[(u64, Value<'_>)], number: u64) -> String {
+    strings(fields, number).pop().unwrap_or_default()
+}
+
+// a float weight tensor (TensorProto)
+struct Tensor {
+    dims: Vec<usize>,
+    values: Vec<f32>,
+}
+
+fn tensor(bytes: &[u8]) -> Result<(String, Tensor), String> {
+    let fields = fields(bytes).ok_or("a weight tensor isn't valid protobuf")?;
+    let name = string(&fields, 8);
+    let mut dims = Vec::new();
+    let mut values = Vec::new();
+    let mut data_type = FLOAT;
+    for (number, value) in &fields {
+        match (number, value) {
+            (1, Value::Varint(dim)) => dims.push(*dim as usize),
+            (1, Value::Bytes(mut packed)) => while !packed.is_empty() {
+                dims.push(varint(&mut packed).ok_or("a weight tensor's dims aren't valid")? as usize);
+            },
+            (2, Value::Varint(code)) => data_type = *code,
+            (4, Value::Fixed32(bits)) => values.push(f32::from_bits(*bits)),
+            (4, Value::Bytes(packed)) | (9, Value::Bytes(packed)) => {
+                values.extend(packed.chunks_exact(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())));
+            }
+            (13, _) => { return Err(format!("the weights for {} are kept outside the file", name)); }
+            _ => {}
+        }
+    }
+    if data_type != FLOAT {
+        return Err(format!("the weights for {} aren't 32-bit floats", name));
+    }
+    if values.len() != dims.iter().product::<usize>() {
+        return Err(format!("the weights for {} don't fit their shape", name));
+    }
+    Ok((name, Tensor { dims, values }))
+}
+
+// a node's float attribute (alpha, beta) or int one (transA, transB)
+fn attribute(node: &[(u64, Value<'_>)], name: &str) -> Option<Value<'static>> {
+    messages(node, 5).into_iter().filter_map(fields).find(|attribute| string(attribute, 1) == name).and_then(|attribute| {
+        attribute.iter().find_map(|(number, value)| match (number, value) {
+            (2, Value::Fixed32(bits)) => Some(Value::Fixed32(*bits)),
+            (3, Value::Varint(int)) => Some(Value::Varint(*int)),
+            _ => None
+        })
+    })
+}
+
+// the network in an ONNX model (ModelProto) read from `bytes`, or what keeps it from being one
+pub fn from_bytes(bytes: &[u8]) -> Result<NnEval, String> {
+    let model = fields(bytes).ok_or("not an ONNX model")?;
+    let graph = fields(messages(&model, 7).pop().ok_or("the model has no graph")?).ok_or("the graph isn't valid protobuf")?;
+    let weights: HashMap<String, Tensor> = messages(&graph, 5).into_iter().map(tensor).collect::<Result<_, _>>()?;
+    // (older exporters list the weights among the inputs too)
+    let mut current = messages(&graph, 11).into_iter().filter_map(fields).map(|input| string(&input, 1)).find(|name| !weights.contains_key(name)).ok_or("the graph has no input")?;
+    let weight = |name: &str| weights.get(name).ok_or_else(|| format!("{} isn't a weight in the file", name));
+    let mut layers: Vec<Layer> = Vec::new();
+    // whether a Relu came after each layer
+    let mut relus: Vec<bool> = Vec::new();
+    for node in messages(&graph, 1) {
+        let node = fields(node).ok_or("a node isn't valid protobuf")?;
+        let op = string(&node, 4);
+        let inputs: Vec<String> = strings(&node, 1).into_iter().filter(|input| !input.is_empty()).collect();
+        if inputs.first() != Some(&current) {
+            return Err(format!("the {} node doesn't follow on from the one before: only a chain of layers is understood", op));
+        }
+        let float = |name: &str| match attribute(&node, name) {
+            Some(Value::Fixed32(bits)) => f32::from_bits(bits),
+            _ => 1.0
+        };
+        let int = |name: &str| match attribute(&node, name) {
+            Some(Value::Varint(int)) => int,
+            _ => 0
+        };
+        let biases = |outputs: usize, name: Option<&String>, scale: f32| -> Result<Vec<f32>, String> {
+            let Some(name) = name else {
+                return Ok(vec![0.0; outputs]);
+            };
+            match weight(name)?.values.as_slice() {
+                [bias] => Ok(vec![bias * scale; outputs]),
+                values if values.len() == outputs => Ok(values.iter().map(|bias| bias * scale).collect()),
+                _ => Err(format!("the biases {} don't fit the layer", name))
+            }
+        };
+        // a layer that a Relu hasn't come after yet, so its biases can still be added to
+        let open = relus.last() == Some(&false);
+        match op.as_str() {
+            "Gemm" | "MatMul" => {
+                let matrix = weight(inputs.get(1).ok_or_else(|| format!("the {} node has no weights", op))?)?;
+                let &[rows, columns] = matrix.dims.as_slice() else {
+                    return Err(format!("the weights of a {} node aren't a matrix", op));
+                };
+                if int("transA") != 0 {
+                    return Err("a Gemm with transA isn't understood".to_string());
+                }
+                let alpha = if op == "Gemm" { float("alpha") } else { 1.0 };
+                // NnEval keeps a row of weights per output: that's the matrix as it is with
+                // transB, and turned over without
+                let (layer_inputs, outputs, weights) = match op == "Gemm" && int("transB") != 0 {
+                    true => (columns, rows, matrix.values.iter().map(|weight| weight * alpha).collect()),
+                    false => (rows, columns, (0..columns * rows).map(|index| matrix.values[(index % rows) * columns + index / rows] * alpha).collect())
+                };
+                let biases = match op == "Gemm" {
+                    true => biases(outputs, inputs.get(2), float("beta"))?,
+                    false => vec![0.0; outputs]
+                };
+                layers.push(Layer { inputs: layer_inputs, outputs, weights, biases });
+                relus.push(false);
+            }
+            // the biases after a MatMul
+            "Add" if open => {
+                let layer = layers.last_mut().unwrap();
+                let added = biases(layer.outputs, inputs.get(1), 1.0)?;
+                layer.biases.iter_mut().zip(added).for_each(|(bias, added)| *bias += added);
+            }
+            "Relu" if open => { *relus.last_mut().unwrap() = true; }
+            "Identity" | "Flatten" => {}
+            _ => { return Err(format!("{} isn't understood here: only Gemm, MatMul, Add and Relu are", op)); }
+        }
+        current = strings(&node, 2).into_iter().next().ok_or_else(|| format!("the {} node has no output", op))?;
+    }
+    if relus.split_last().is_none_or(|(last, rest)| *last || rest.contains(&false)) {
+        return Err("it needs a Relu after every layer but the last, and none after that".to_string());
+    }
+    NnEval::new(layers).ok_or_else(|| "its layers don't fit together into one with a single output".to_string())
+}
+
+pub fn load(path: &Path) -> io::Result<NnEval> {
+    from_bytes(&fs::read(path)?).map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, format!("{} can't be used as a network: {}", path.display(), reason)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::{Node, Player, BLACK_POCKET, WHITE_POCKET};
+
+    // just enough protobuf writing to put models together
+    fn varint_bytes(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    fn int(number: u64, value: u64) -> Vec<u8> {
+        [varint_bytes(number << 3), varint_bytes(value)].concat()
+    }
+
+    fn message(number: u64, body: &[u8]) -> Vec<u8> {
+        [varint_bytes(number << 3 | 2), varint_bytes(body.len() as u64), body.to_vec()].concat()
+    }
+
+    fn weights(name: &str, dims: &[u64], values: &[f32]) -> Vec<u8> {
+        let raw: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let body = [dims.iter().map(|&dim| int(1, dim)).collect::<Vec<_>>().concat(), int(2, FLOAT), message(8, name.as_bytes()), message(9, &raw)].concat();
+        message(5, &body)
+    }
+
+    fn node(op: &str, inputs: &[&str], output: &str, trans_b: bool) -> Vec<u8> {
+        let mut body: Vec<u8> = inputs.iter().flat_map(|input| message(1, input.as_bytes())).collect();
+        body.extend(message(2, output.as_bytes()));
+        body.extend(message(4, op.as_bytes()));
+        if trans_b {
+            body.extend(message(5, &[message(1, b"transB"), int(3, 1), int(20, 2)].concat()));
+        }
+        message(1, &body)
+    }
+
+    fn model(parts: &[Vec<u8>]) -> Vec<u8> {
+        let graph = [parts.concat(), message(11, &message(1, b"board"))].concat();
+        [int(1, 8), message(7, &graph)].concat()
+    }
+
+    // the store difference through two ReLUs (as in the nn tests), with the hidden layer's
+    // weights as PyTorch keeps them: a row of inputs for each output
+    fn hidden() -> Vec<f32> {
+        let mut hidden = vec![0.0; 2 * 15];
+        hidden[WHITE_POCKET] = 1.0;
+        hidden[BLACK_POCKET] = -1.0;
+        hidden[15 + WHITE_POCKET] = -1.0;
+        hidden[15 + BLACK_POCKET] = 1.0;
+        hidden
+    }
+
+    #[test]
+    fn test_layers() {
+        // as torch.onnx.export writes nn.Linear layers
+        let gemm = model(&[
+            node("Gemm", &["board", "w1", "b1"], "h1", true),
+            node("Relu", &["h1"], "h2", false),
+            node("Gemm", &["h2", "w2", "b2"], "value", true),
+            weights("w1", &[2, 15], &hidden()),
+            weights("b1", &[2], &[0.0, 0.0]),
+            weights("w2", &[1, 2], &[1.0, -1.0]),
+            weights("b2", &[1], &[0.5]),
+        ]);
+        let net = from_bytes(&gemm).unwrap();
+        let position = Node::from_board(&[0, 2, 0, 1, 0, 1, 20, 1, 0, 2, 0, 0, 1, 23], Player::White, Default::default()).unwrap();
+        assert_eq!(net.forward(&position), -2.5);
+
+        // the same with MatMul and Add, the weights the other way round
+        let turned: Vec<f32> = (0..30).map(|index| hidden()[(index % 2) * 15 + index / 2]).collect();
+        let matmul = model(&[
+            node("MatMul", &["board", "m1"], "h1", false),
+            node("Add", &["h1", "b1"], "h2", false),
+            node("Relu", &["h2"], "h3", false),
+            node("MatMul", &["h3", "m2"], "h4", false),
+            node("Add", &["h4", "b2"], "value", false),
+            weights("m1", &[15, 2], &turned),
+            weights("b1", &[2], &[0.0, 0.0]),
+            weights("m2", &[2, 1], &[1.0, -1.0]),
+            weights("b2", &[1], &[0.5]),
+        ]);
+        assert_eq!(from_bytes(&matmul), Ok(net));
+    }
+
+    #[test]
+    fn test_refused() {
+        let layer = |last: Vec<u8>| model(&[node("Gemm", &["board", "w", "b"], "h", true), last, weights("w", &[1, 15], &[0.0; 15]), weights("b", &[1], &[0.0])]);
+        assert!(from_bytes(&layer(node("Identity", &["h"], "value", false))).is_ok());
+        assert!(from_bytes(&layer(node("Sigmoid", &["h"], "value", false))).unwrap_err().starts_with("Sigmoid isn't understood"));
+        assert!(from_bytes(&layer(node("Relu", &["h"], "value", false))).unwrap_err().contains("none after that"));
+        assert!(from_bytes(&layer(node("Relu", &["elsewhere"], "value", false))).unwrap_err().contains("chain of layers"));
+        assert!(from_bytes(b"\x0a\x05ab").is_err());
+        let path = std::env::temp_dir().join(format!("mancala-rs-{}.onnx", std::process::id()));
+        fs::write(&path, model(&[])).unwrap();
+        assert_eq!(load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+}
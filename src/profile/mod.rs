@@ -0,0 +1,144 @@
+use super::config::Config;
+use super::mancala::{Outcome, Player};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// how someone has done against the AI over every game they've finished with it, kept in a small
+// TOML file next to the config file between runs
+
+// the difficulty games are filed under when the AI wasn't playing one of the presets
+pub const CUSTOM: &str = "custom";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Results {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Results {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // the points scored, a draw counting half, as a percentage of the games
+    pub fn score(&self) -> f64 {
+        100.0 * (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    // games won in a row up to the latest one, and the most ever
+    pub streak: usize,
+    pub best_streak: usize,
+    // the accuracies (see analysis::Report::accuracy) of the games analyzed with --analyze,
+    // added up, and how many of them there were
+    pub accuracy_total: f64,
+    pub analyzed: usize,
+    // by difficulty name, or CUSTOM
+    pub results: BTreeMap<String, Results>,
+}
+
+impl Profile {
+    // profile.toml beside the config file (see Config::default_path)
+    pub fn default_path() -> Option<PathBuf> {
+        Some(Config::default_path()?.with_file_name("profile.toml"))
+    }
+
+    // a profile that hasn't been written yet is an empty one
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string())),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Profile::default()),
+            Err(error) => Err(error)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, toml::to_string(self).map_err(io::Error::other)?)
+    }
+
+    // a finished game `player` played against the AI at `difficulty`, with their accuracy if
+    // it was analyzed
+    pub fn add(&mut self, difficulty: &str, outcome: &Outcome, player: &Player, accuracy: Option<f64>) {
+        let results = self.results.entry(difficulty.to_string()).or_default();
+        match &outcome.winner {
+            Some(winner) if winner == player => {
+                results.wins += 1;
+                self.streak += 1;
+                self.best_streak = self.best_streak.max(self.streak);
+            }
+            Some(_) => {
+                results.losses += 1;
+                self.streak = 0;
+            }
+            None => {
+                results.draws += 1;
+                self.streak = 0;
+            }
+        }
+        if let Some(accuracy) = accuracy {
+            self.accuracy_total += accuracy;
+            self.analyzed += 1;
+        }
+    }
+
+    pub fn accuracy(&self) -> Option<f64> {
+        Some(self.accuracy_total / self.analyzed as f64).filter(|_| self.analyzed > 0)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.results.is_empty() {
+            return write!(f, "No games against the AI yet.");
+        }
+        for (difficulty, results) in &self.results {
+            writeln!(f, "{:8} {:3} games: +{} ={} -{} ({:.0}%)", difficulty, results.games(), results.wins, results.draws, results.losses, results.score())?;
+        }
+        write!(f, "Winning streak: {} (best {})", self.streak, self.best_streak)?;
+        if let Some(accuracy) = self.accuracy() {
+            write!(f, "\nAccuracy: {:.0}% on average over {} analyzed games", accuracy, self.analyzed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::TerminationReason;
+
+    #[test]
+    fn test_profile() {
+        let outcome = |winner: Option<Player>| Outcome { winner, score: 0, reason: TerminationReason::NoStones };
+        let mut profile = Profile::default();
+        assert_eq!(profile.to_string(), "No games against the AI yet.");
+        profile.add("easy", &outcome(Some(Player::White)), &Player::White, Some(80.0));
+        profile.add("easy", &outcome(Some(Player::White)), &Player::Black, None);
+        profile.add("hard", &outcome(Some(Player::Black)), &Player::Black, Some(60.0));
+        profile.add("hard", &outcome(Some(Player::White)), &Player::White, None);
+        profile.add(CUSTOM, &outcome(None), &Player::White, None);
+        assert_eq!(profile.results["easy"], Results { wins: 1, draws: 0, losses: 1 });
+        assert_eq!(profile.results["hard"].score(), 100.0);
+        assert_eq!((profile.streak, profile.best_streak), (0, 2));
+        assert_eq!(profile.accuracy(), Some(70.0));
+        assert!(profile.to_string().ends_with("Winning streak: 0 (best 2)\nAccuracy: 70% on average over 2 analyzed games"));
+
+        let path = std::env::temp_dir().join(format!("mancala-rs-profile-{}", std::process::id())).join("profile.toml");
+        assert_eq!(Profile::load(&path).unwrap(), Profile::default());
+        profile.save(&path).unwrap();
+        assert_eq!(Profile::load(&path).unwrap(), profile);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
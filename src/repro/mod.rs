@@ -0,0 +1,182 @@
+use super::engine::{Engine, InfoCallback, SearchInfo, SearchLimits, SearchResult};
+use super::game;
+use super::mancala::{Move, Node, Score};
+use super::minimax::{EngineOptions, Searcher};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+
+const VERSION: u32 = 1;
+
+// what one iteration of iterative deepening found. the score is from White's point of view.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Iteration {
+    pub depth: usize,
+    pub score: Score,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+}
+
+// a search written down so that it can be run again and checked: the position, the options and
+// limits it ran with, and what it found at every depth. searches run from scratch (an empty
+// table, no book, endgame database or custom evaluator), so with one thread and no time limit
+// a replay has to come out exactly the same; anything else means the search is nondeterministic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub node: Node,
+    pub options: EngineOptions,
+    pub limits: SearchLimits,
+    pub iterations: Vec<Iteration>,
+    pub best_move: Option<Move>,
+    // White's point of view
+    pub score: Score,
+    pub nodes: u64,
+}
+
+// an info callback that keeps each iteration for a bundle and then hands it on to `then`, and
+// where the iterations can be collected from once the search is over
+pub fn recorder(mut then: Option<InfoCallback>) -> (InfoCallback, mpsc::Receiver<Iteration>) {
+    let (sender, receiver) = mpsc::channel();
+    let callback = Box::new(move |info: &SearchInfo| {
+        let _ = sender.send(Iteration { depth: info.depth, score: info.score, pv: info.pv.clone(), nodes: info.nodes });
+        if let Some(then) = then.as_mut() {
+            then(info);
+        }
+    });
+    (callback, receiver)
+}
+
+// searches `node` from scratch, keeping each iteration's result
+fn run(node: &Node, options: EngineOptions, limits: SearchLimits) -> (Vec<Iteration>, SearchResult) {
+    let (callback, receiver) = recorder(None);
+    let mut searcher = Searcher::new(options);
+    searcher.set_info_callback(Some(callback));
+    let result = searcher.search(node, limits);
+    (receiver.try_iter().collect(), result)
+}
+
+// a search that has already been run, from scratch (see Searcher::search_from_scratch), with
+// the iterations a recorder kept
+pub fn record(node: &Node, options: EngineOptions, limits: SearchLimits, iterations: Vec<Iteration>, result: &SearchResult) -> Bundle {
+    Bundle {
+        version: VERSION,
+        node: node.clone(),
+        options,
+        limits,
+        iterations,
+        best_move: result.best_move.clone(),
+        score: result.score,
+        nodes: result.nodes,
+    }
+}
+
+pub fn capture(node: &Node, options: EngineOptions, limits: SearchLimits) -> Bundle {
+    let (iterations, result) = run(node, options, limits);
+    record(node, options, limits, iterations, &result)
+}
+
+fn format_pv(pv: &[Move]) -> String {
+    pv.iter().map(game::format_move).collect::<Vec<_>>().join(" ")
+}
+
+// where a replay came out differently from the bundle, one line each (nothing if it's identical).
+// with a time limit, how far the search gets depends on the machine, so only the iterations
+// both finished are compared.
+pub fn replay(bundle: &Bundle) -> Vec<String> {
+    let (iterations, result) = run(&bundle.node, bundle.options, bundle.limits);
+    let timed = bundle.limits.time_for_move().is_some();
+    let mut differences = Vec::new();
+    for (expected, found) in bundle.iterations.iter().zip(&iterations) {
+        if expected.depth != found.depth {
+            differences.push(format!("iteration at depth {} came out at depth {}", expected.depth, found.depth));
+            continue;
+        }
+        if expected.score != found.score {
+            differences.push(format!("depth {}: score {:+} now {:+}", expected.depth, expected.score, found.score));
+        }
+        if expected.pv != found.pv {
+            differences.push(format!("depth {}: pv {} now {}", expected.depth, format_pv(&expected.pv), format_pv(&found.pv)));
+        }
+        if expected.nodes != found.nodes {
+            differences.push(format!("depth {}: {} nodes now {}", expected.depth, expected.nodes, found.nodes));
+        }
+    }
+    if timed {
+        return differences;
+    }
+    if bundle.iterations.len() != iterations.len() {
+        differences.push(format!("{} iterations now {}", bundle.iterations.len(), iterations.len()));
+    }
+    if bundle.best_move != result.best_move {
+        let format = |mv: &Option<Move>| mv.as_ref().map_or("none".to_string(), game::format_move);
+        differences.push(format!("best move {} now {}", format(&bundle.best_move), format(&result.best_move)));
+    }
+    if bundle.score != result.score {
+        differences.push(format!("score {:+} now {:+}", bundle.score, result.score));
+    }
+    if bundle.nodes != result.nodes {
+        differences.push(format!("{} nodes now {}", bundle.nodes, result.nodes));
+    }
+    differences
+}
+
+impl Bundle {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let bundle: Bundle = serde_json::from_str(&fs::read_to_string(path)?).map_err(|error| invalid(error.to_string()))?;
+        if bundle.version != VERSION {
+            return Err(invalid(format!("repro bundle version {} isn't supported", bundle.version)));
+        }
+        if Node::from_board(bundle.node.get_board(), bundle.node.get_turn().clone(), *bundle.node.get_rules()).is_err() {
+            return Err(invalid("the bundle's board doesn't fit its rules".to_string()));
+        }
+        Ok(bundle)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self).map_err(io::Error::other)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let mut node = Node::default();
+        node.full_move(&vec![2]).unwrap();
+        let options = EngineOptions { eval_noise: 1, seed: 3, ..EngineOptions::default() };
+        let bundle = capture(&node, options, SearchLimits::depth(5));
+        assert_eq!(bundle.iterations.iter().map(|iteration| iteration.depth).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(replay(&bundle), Vec::<String>::new());
+
+        let path = std::env::temp_dir().join(format!("mancala-repro-{}.json", std::process::id()));
+        bundle.save(&path).unwrap();
+        let loaded = Bundle::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, bundle);
+
+        // as if an earlier run had gone differently
+        let mut changed = bundle.clone();
+        changed.iterations[2].nodes += 1;
+        changed.score += 1;
+        let differences = replay(&changed);
+        assert_eq!(differences.len(), 2);
+        assert!(differences[0].starts_with("depth 3:"));
+
+        // a search played by a searcher that had searched before, recorded as it went
+        let (callback, iterations) = recorder(None);
+        let mut searcher = Searcher::new(options);
+        searcher.search_from_scratch(true);
+        searcher.set_info_callback(Some(callback));
+        searcher.search(&Node::default(), SearchLimits::depth(5));
+        iterations.try_iter().for_each(drop);
+        let result = searcher.search(&node, SearchLimits::depth(5));
+        let played = record(&node, options, SearchLimits::depth(5), iterations.try_iter().collect(), &result);
+        assert_eq!(played, bundle);
+    }
+}
@@ -0,0 +1,57 @@
+// a small seedable pseudo-random number generator (SplitMix64), so that anything random in the
+// crate can be reproduced exactly from a seed without pulling in a dependency
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+// the SplitMix64 output function: a good 64-bit mix of its input
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = splitmix64(self.state);
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        result
+    }
+
+    // uniform in 0..n (n must be positive)
+    pub fn below(&mut self, n: usize) -> usize {
+        // the modulo bias is negligible for the small ranges we use
+        (self.next_u64() % n as u64) as usize
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(7).next_u64(), Rng::new(8).next_u64());
+        for _ in 0..1000 {
+            assert!(a.below(6) < 6);
+            let x = a.next_f64();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+}
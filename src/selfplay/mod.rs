@@ -0,0 +1,215 @@
+use super::engine::{PvLine, SearchLimits};
+use super::eval::EvalWeights;
+use super::game::Game;
+use super::mancala::{Move, Player, RuleSet, Score};
+use super::minimax::{EngineOptions, Searcher};
+use super::rng::{self, Rng};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// training data for evaluation models: every position of some self-play games, with the move
+// that was played there and how the game ended.
+//
+// a data file is a header and then records back to back until the end of the file:
+//   header: "MKSP", version (u8, 1), pockets on the board (u8, stores included)
+//   record: the board, one u8 of stones per pocket in the order of Node::get_board
+//           the side to move (u8, 0 for White and 1 for Black)
+//           the full move played: its number of sub-moves (u8), then the pocket of each (u8)
+//           the result: White's final margin in stones (i16, little-endian; negative if Black won)
+const MAGIC: &[u8; 4] = b"MKSP";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub board: Vec<Score>,
+    pub turn: Player,
+    pub chosen: Move,
+    // White minus Black at the end of the game
+    pub result: Score,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfPlayOptions {
+    pub games: usize,
+    pub depth: usize,
+    // random error added to each evaluation, in whole stones (see EngineOptions::eval_noise)
+    pub eval_noise: Score,
+    // how freely moves other than the best get played, in stones: a move is picked with
+    // probability proportional to exp(score / temperature). 0 always plays the best move.
+    pub temperature: f64,
+    pub seed: u64,
+    pub weights: EvalWeights,
+}
+
+impl Default for SelfPlayOptions {
+    fn default() -> Self {
+        SelfPlayOptions {
+            games: 100,
+            depth: 4,
+            eval_noise: 0,
+            temperature: 1.0,
+            seed: 0,
+            weights: EvalWeights::default(),
+        }
+    }
+}
+
+// one of the root lines, by temperature. scores are from White's point of view.
+fn choose(lines: &[PvLine], turn: &Player, temperature: f64, rng: &mut Rng) -> Option<Move> {
+    let sign = match turn {
+        Player::White => 1.0,
+        Player::Black => -1.0
+    };
+    let best = lines.iter().map(|line| sign * line.score as f64).fold(f64::NEG_INFINITY, f64::max);
+    if temperature <= 0.0 {
+        return lines.iter().find(|line| sign * line.score as f64 == best)?.pv.first().cloned();
+    }
+    // relative to the best, so that big scores don't overflow
+    let weights: Vec<f64> = lines.iter().map(|line| ((sign * line.score as f64 - best) / temperature).exp()).collect();
+    let mut pick = rng.next_f64() * weights.iter().sum::<f64>();
+    for (line, weight) in lines.iter().zip(&weights) {
+        pick -= weight;
+        if pick < 0.0 {
+            return line.pv.first().cloned();
+        }
+    }
+    lines.last()?.pv.first().cloned()
+}
+
+// plays `options.games` games of the engine against itself. every root move is searched, so
+// that the temperature has all of them to choose from.
+pub fn play(rules: RuleSet, options: &SelfPlayOptions) -> Vec<Game> {
+    (0..options.games as u64).map(|index| {
+        let seed = rng::splitmix64(options.seed.wrapping_add(index));
+        let mut searcher = Searcher::new(EngineOptions {
+            multipv: usize::MAX,
+            eval_noise: options.eval_noise,
+            seed,
+            weights: options.weights,
+            // solved endgames would all look the same to a model
+            endgame_stones: 0,
+            ..EngineOptions::default()
+        });
+        let mut rng = Rng::new(seed);
+        let mut game = Game::new(rules);
+        while game.outcome().is_none() {
+            let node = game.node().clone();
+            let result = searcher.search(&node, SearchLimits::depth(options.depth));
+            let Some(mv) = choose(&result.lines, node.get_turn(), options.temperature, &mut rng).or(result.best_move) else { break; };
+            game.play(&mv).expect("the engine plays legal moves");
+        }
+        game
+    }).collect()
+}
+
+// a record for every move of the finished games
+pub fn records(games: &[Game]) -> Vec<Record> {
+    let mut result = Vec::new();
+    for game in games {
+        let Some(outcome) = game.outcome() else { continue; };
+        result.extend(game.history().iter().zip(game.moves()).map(|(node, mv)| Record {
+            board: node.get_board().to_vec(),
+            turn: node.get_turn().clone(),
+            chosen: mv.clone(),
+            result: outcome.score,
+        }));
+    }
+    result
+}
+
+pub fn to_bytes(records: &[Record]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let size = records.first().map_or(0, |record| record.board.len());
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(u8::try_from(size).map_err(|_| invalid("board too big for a data file"))?);
+    for record in records {
+        if record.board.len() != size {
+            return Err(invalid("records from different boards"));
+        }
+        for &stones in &record.board {
+            bytes.push(u8::try_from(stones).map_err(|_| invalid("too many stones in a pocket for a data file"))?);
+        }
+        bytes.push(record.turn.index() as u8);
+        bytes.push(u8::try_from(record.chosen.len()).map_err(|_| invalid("move too long for a data file"))?);
+        for &pocket in &record.chosen {
+            bytes.push(u8::try_from(pocket).map_err(|_| invalid("board too big for a data file"))?);
+        }
+        bytes.extend_from_slice(&i16::try_from(record.result).map_err(|_| invalid("result too big for a data file"))?.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> io::Result<Vec<Record>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if bytes.len() < 6 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+        return Err(invalid("not a self-play data file"));
+    }
+    let size = bytes[5] as usize;
+    let truncated = || invalid("self-play data file is truncated");
+    let mut records = Vec::new();
+    let mut rest = &bytes[6..];
+    while !rest.is_empty() {
+        let (board, after) = rest.split_at_checked(size + 2).ok_or_else(truncated)?;
+        let turn = match board[size] {
+            0 => Player::White,
+            1 => Player::Black,
+            _ => return Err(invalid("bad side to move in self-play data"))
+        };
+        let length = board[size + 1] as usize;
+        let (chosen, after) = after.split_at_checked(length).ok_or_else(truncated)?;
+        let (result, after) = after.split_at_checked(2).ok_or_else(truncated)?;
+        records.push(Record {
+            board: board[..size].iter().map(|&stones| stones as Score).collect(),
+            turn,
+            chosen: chosen.iter().map(|&pocket| pocket as usize).collect(),
+            result: i16::from_le_bytes([result[0], result[1]]) as Score,
+        });
+        rest = after;
+    }
+    Ok(records)
+}
+
+pub fn save(path: &Path, records: &[Record]) -> io::Result<()> {
+    fs::write(path, to_bytes(records)?)
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    from_bytes(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::Node;
+
+    #[test]
+    fn test_play() {
+        let options = SelfPlayOptions { games: 3, depth: 2, temperature: 2.0, seed: 5, ..SelfPlayOptions::default() };
+        let games = play(RuleSet::default(), &options);
+        assert!(games.iter().all(|game| game.outcome().is_some()));
+        assert_eq!(games.iter().map(Game::record).collect::<Vec<_>>(), play(RuleSet::default(), &options).iter().map(Game::record).collect::<Vec<_>>());
+        // with a temperature the games don't all go the same way
+        assert_ne!(games[0].record(), games[1].record());
+        let cold = SelfPlayOptions { temperature: 0.0, ..options };
+        let games = play(RuleSet::default(), &cold);
+        assert_eq!(games[0].record(), games[1].record());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let games = play(RuleSet::default(), &SelfPlayOptions { games: 2, depth: 1, ..SelfPlayOptions::default() });
+        let records = records(&games);
+        assert_eq!(records.len(), games.iter().map(|game| game.moves().len()).sum::<usize>());
+        for record in &records {
+            let mut node = Node::from_board(&record.board, record.turn.clone(), RuleSet::default()).unwrap();
+            assert!(node.full_move(&record.chosen).is_ok());
+        }
+        let bytes = to_bytes(&records).unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), records);
+        assert!(from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(from_bytes(b"MKDB").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use super::endgame::{self, EndgameDb};
+use super::mancala::{Node, Player, Score};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"MKSV";
+const VERSION: u8 = 1;
+// proof and disproof numbers saturate here
+const INFINITE: u32 = u32::MAX;
+// the table is cleared of unsolved positions when it grows past this
+const MAX_ENTRIES: usize = 1 << 24;
+
+// proves the value of a whole game (the final White minus Black margin with perfect play) with
+// depth-first proof-number search. each proof answers one question, "can White finish at least
+// `threshold` ahead?", and the thresholds home in on the value by bisection. the proof-number
+// table doubles as the transposition table, and positions the endgame database knows (or that
+// are decided whatever happens to the stones left) are leaves.
+//
+// that's only sound if the game can't go round in circles and doesn't depend on history, so the
+// rules have to allow an endgame database and have no move limit.
+//
+// the work so far can be saved and picked up again: the bounds found, plus the table of the
+// proof in progress.
+pub struct Solver {
+    root: Node,
+    db: Option<Arc<EndgameDb>>,
+    // the value is in lower..=upper
+    lower: Score,
+    upper: Score,
+    // (proof number, disproof number) of positions for the current threshold
+    table: HashMap<u64, (u32, u32)>,
+    // positions expanded so far, over every run
+    pub nodes: u64,
+    budget: u64,
+}
+
+impl Solver {
+    // None if the rules don't allow it (see above)
+    pub fn new(root: Node) -> Option<Self> {
+        let rules = root.get_rules();
+        if !endgame::supports(rules) || rules.move_limit.is_some() {
+            return None;
+        }
+        let (lower, upper) = (root.eval() - root.stones_in_play(), root.eval() + root.stones_in_play());
+        Some(Solver { root, db: None, lower, upper, table: HashMap::new(), nodes: 0, budget: 0 })
+    }
+
+    pub fn set_endgame_db(&mut self, db: Option<Arc<EndgameDb>>) {
+        self.db = db;
+    }
+
+    // what's known about the value so far
+    pub fn bounds(&self) -> (Score, Score) {
+        (self.lower, self.upper)
+    }
+
+    pub fn value(&self) -> Option<Score> {
+        (self.lower == self.upper).then_some(self.lower)
+    }
+
+    // the question the proof in progress is answering
+    fn threshold(&self) -> Score {
+        // rounding up, so a proof always moves one of the bounds
+        self.lower + (self.upper - self.lower + 1) / 2
+    }
+
+    // keeps proving for up to `nodes` more expanded positions, or until the value is known
+    pub fn run(&mut self, nodes: u64) -> Option<Score> {
+        self.budget = self.nodes.saturating_add(nodes);
+        while self.value().is_none() && self.nodes < self.budget {
+            let threshold = self.threshold();
+            let root = self.root.clone();
+            self.mid(&root, threshold, INFINITE, INFINITE);
+            match self.table.get(&root.hash()) {
+                Some(&(0, _)) => { self.lower = threshold; }
+                Some(&(_, 0)) => { self.upper = threshold - 1; }
+                // out of budget: the table keeps the proof so far
+                _ => { break; }
+            }
+            self.table.clear();
+        }
+        self.value()
+    }
+
+    // Some(whether White finishes at least `threshold` ahead) if that's settled without searching
+    fn leaf(&self, node: &Node, threshold: Score) -> Option<bool> {
+        if node.is_over() {
+            return Some(node.final_score() >= threshold);
+        }
+        if let Some(value) = self.db.as_ref().and_then(|db| db.probe(node)) {
+            return Some(value >= threshold);
+        }
+        // however the stones left get shared out
+        if node.eval() - node.stones_in_play() >= threshold {
+            return Some(true);
+        }
+        if node.eval() + node.stones_in_play() < threshold {
+            return Some(false);
+        }
+        None
+    }
+
+    fn numbers(&self, node: &Node, threshold: Score) -> (u32, u32) {
+        match self.leaf(node, threshold) {
+            Some(true) => (0, INFINITE),
+            Some(false) => (INFINITE, 0),
+            None => self.table.get(&node.hash()).copied().unwrap_or((1, 1))
+        }
+    }
+
+    // the MID procedure of df-pn: works below `node` until its proof number reaches `max_proof`
+    // or its disproof number reaches `max_disproof`. White's moves are OR nodes, Black's AND nodes.
+    fn mid(&mut self, node: &Node, threshold: Score, max_proof: u32, max_disproof: u32) {
+        self.nodes += 1;
+        if self.table.len() > MAX_ENTRIES {
+            self.table.retain(|_, (proof, disproof)| *proof == 0 || *disproof == 0);
+        }
+        let children: Vec<Node> = node.children().into_iter().map(|(_, child)| child).collect();
+        let white = *node.get_turn() == Player::White;
+        loop {
+            let numbers: Vec<(u32, u32)> = children.iter().map(|child| self.numbers(child, threshold)).collect();
+            // in terms of the side to move: `ours` is what it has to bring down to zero
+            // (proof numbers for White, disproof numbers for Black), `theirs` the other
+            let (ours, theirs): (Vec<u32>, Vec<u32>) = numbers.iter().map(|&(proof, disproof)| if white { (proof, disproof) } else { (disproof, proof) }).unzip();
+            let best = (0..children.len()).min_by_key(|&index| ours[index]).expect("a position that isn't over has moves");
+            let min_ours = ours[best];
+            let sum_theirs = theirs.iter().fold(0u32, |sum, &value| sum.saturating_add(value));
+            let (proof, disproof) = if white { (min_ours, sum_theirs) } else { (sum_theirs, min_ours) };
+            self.table.insert(node.hash(), (proof, disproof));
+            if proof >= max_proof || disproof >= max_disproof || self.nodes >= self.budget {
+                return;
+            }
+            let (max_ours, max_theirs) = if white { (max_proof, max_disproof) } else { (max_disproof, max_proof) };
+            let second = (0..children.len()).filter(|&index| index != best).map(|index| ours[index]).min().unwrap_or(INFINITE);
+            let child_ours = max_ours.min(second.saturating_add(1));
+            let child_theirs = max_theirs.saturating_sub(sum_theirs).saturating_add(theirs[best]);
+            let (child_proof, child_disproof) = if white { (child_ours, child_theirs) } else { (child_theirs, child_ours) };
+            self.mid(&children[best], threshold, child_proof, child_disproof);
+        }
+    }
+
+    // magic, version, rules fingerprint, root key, bounds (i16 each), positions expanded,
+    // then the table: its length and (key, proof, disproof) per entry. all little-endian.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(37 + 16 * self.table.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&endgame::fingerprint(self.root.get_rules()));
+        bytes.extend_from_slice(&self.root.hash().to_le_bytes());
+        bytes.extend_from_slice(&(self.lower as i16).to_le_bytes());
+        bytes.extend_from_slice(&(self.upper as i16).to_le_bytes());
+        bytes.extend_from_slice(&self.nodes.to_le_bytes());
+        bytes.extend_from_slice(&(self.table.len() as u64).to_le_bytes());
+        // sorted, so the same state always makes the same file
+        let mut entries: Vec<_> = self.table.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| **key);
+        for (key, (proof, disproof)) in entries {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&proof.to_le_bytes());
+            bytes.extend_from_slice(&disproof.to_le_bytes());
+        }
+        fs::write(path, bytes)
+    }
+
+    // carries on with a saved run, which has to be for the same root position and rules
+    pub fn load(path: &Path, root: Node) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut solver = Self::new(root).ok_or_else(|| invalid("these rules can't be solved"))?;
+        let bytes = fs::read(path)?;
+        if bytes.len() < 41 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(invalid("not a solver state file"));
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("8 bytes"));
+        let i16_at = |offset: usize| i16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as Score;
+        if bytes[5..13] != endgame::fingerprint(solver.root.get_rules()) || u64_at(13) != solver.root.hash() {
+            return Err(invalid("solver state is for a different game"));
+        }
+        solver.lower = i16_at(21);
+        solver.upper = i16_at(23);
+        solver.nodes = u64_at(25);
+        let entries = u64_at(33) as usize;
+        if bytes.len() - 41 != 16 * entries {
+            return Err(invalid("solver state is truncated"));
+        }
+        for entry in bytes[41..].chunks_exact(16) {
+            let key = u64::from_le_bytes(entry[..8].try_into().expect("8 bytes"));
+            let proof = u32::from_le_bytes(entry[8..12].try_into().expect("4 bytes"));
+            let disproof = u32::from_le_bytes(entry[12..].try_into().expect("4 bytes"));
+            solver.table.insert(key, (proof, disproof));
+        }
+        Ok(solver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::{Layout, RuleSet};
+    use crate::minimax;
+
+    // deeper than any game of small() can go, so that minimax::minimax plays every line out
+    const DEPTH: usize = 64;
+
+    fn small() -> Node {
+        Node::with_stones(RuleSet { layout: Layout::kalah_sized(3), ..RuleSet::default() }, 2)
+    }
+
+    #[test]
+    fn test_small_kalah() {
+        let root = small();
+        let mut solver = Solver::new(root.clone()).unwrap();
+        assert_eq!(solver.run(u64::MAX), Some(minimax::minimax(&root, DEPTH).1));
+        // the endgame database only makes it quicker
+        let mut with_db = Solver::new(root.clone()).unwrap();
+        with_db.set_endgame_db(Some(Arc::new(EndgameDb::build(*root.get_rules(), 6).unwrap())));
+        assert_eq!(with_db.run(u64::MAX), solver.value());
+        assert!(with_db.nodes < solver.nodes);
+        assert!(Solver::new(Node::new(RuleSet { move_limit: Some(50), ..RuleSet::default() })).is_none());
+    }
+
+    #[test]
+    fn test_resume() {
+        let root = small();
+        let mut solver = Solver::new(root.clone()).unwrap();
+        let path = std::env::temp_dir().join(format!("mancala-solve-{}.state", std::process::id()));
+        // a few positions at a time, saving and loading in between
+        while solver.run(20).is_none() {
+            solver.save(&path).unwrap();
+            solver = Solver::load(&path, root.clone()).unwrap();
+        }
+        assert_eq!(solver.value(), Some(minimax::minimax(&root, DEPTH).1));
+        solver.save(&path).unwrap();
+        assert!(Solver::load(&path, Node::with_stones(*root.get_rules(), 3)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
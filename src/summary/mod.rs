@@ -0,0 +1,111 @@
+use super::engine::SearchResult;
+use super::game::Game;
+use super::mancala::{Player, Score};
+use std::fmt;
+use std::time::Duration;
+
+// how a game went, for the few lines printed after it: what each side did from the record, and
+// what the engine thought from its searches
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    // full moves, by the side that played them
+    pub moves: [usize; 2],
+    // moves that captured, and the stones they took (with the capturing stones, as MoveInfo
+    // counts them)
+    pub captures: [usize; 2],
+    pub captured: [Score; 2],
+    // the most extra sowings in a single move
+    pub longest_chain: [usize; 2],
+    // averages over the engine's moves, None if it didn't search for any
+    pub depth: Option<f64>,
+    pub time: Option<Duration>,
+    // the engine's score (White's) after each of its moves, with the move's number
+    pub evals: Vec<(usize, Score)>,
+}
+
+impl Summary {
+    // `searches` are the engine's, each with the number of the move it was for (counting from 0).
+    // any whose move isn't in the game any more, after an undo, are left out.
+    pub fn new(game: &Game, searches: &[(usize, SearchResult)]) -> Self {
+        let mut summary = Summary { moves: [0; 2], captures: [0; 2], captured: [0; 2], longest_chain: [0; 2], depth: None, time: None, evals: Vec::new() };
+        for (before, mv) in game.history().iter().zip(game.moves()) {
+            let side = before.get_turn().index();
+            summary.moves[side] += 1;
+            let Some((_, _, info)) = before.children_with_info().into_iter().find(|(child, _, _)| child == mv) else {
+                continue;
+            };
+            if info.captured > 0 {
+                summary.captures[side] += 1;
+                summary.captured[side] += info.captured;
+            }
+            summary.longest_chain[side] = summary.longest_chain[side].max(info.extra_turns);
+        }
+        // (a move searched for again after an undo counts once, with its latest search. book moves
+        // weren't searched, so they don't count at all.)
+        let mut kept: Vec<&(usize, SearchResult)> = Vec::new();
+        for search in searches.iter().rev() {
+            let (number, result) = search;
+            if result.nodes > 0 && result.best_move.is_some() && game.moves().get(*number) == result.best_move.as_ref() && kept.iter().all(|(kept, _)| kept != number) {
+                kept.push(search);
+            }
+        }
+        kept.sort_by_key(|(number, _)| *number);
+        if !kept.is_empty() {
+            summary.depth = Some(kept.iter().map(|(_, result)| result.depth as f64).sum::<f64>() / kept.len() as f64);
+            summary.time = Some(kept.iter().map(|(_, result)| result.time).sum::<Duration>() / kept.len() as u32);
+        }
+        summary.evals = kept.iter().map(|(number, result)| (*number, result.score)).collect();
+        summary
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sides = [Player::White, Player::Black];
+        writeln!(f, "{} moves: White {}, Black {}", self.moves.iter().sum::<usize>(), self.moves[0], self.moves[1])?;
+        let captures: Vec<String> = sides.iter().map(|side| format!("{} {} ({} stones)", side, self.captures[side.index()], self.captured[side.index()])).collect();
+        writeln!(f, "Captures: {}", captures.join(", "))?;
+        write!(f, "Longest extra-turn chain: White {}, Black {}", self.longest_chain[0], self.longest_chain[1])?;
+        if let (Some(depth), Some(time)) = (self.depth, self.time) {
+            write!(f, "\nAI: depth {:.1} and {:.2}s a move on average", depth, time.as_secs_f64())?;
+            let evals: Vec<String> = self.evals.iter().map(|(_, score)| format!("{:+}", score)).collect();
+            write!(f, "\nEval after each AI move: {}", evals.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SearchLimits;
+    use crate::mancala::RuleSet;
+    use crate::minimax::Searcher;
+
+    #[test]
+    fn test_summary() {
+        let mut game = Game::new(RuleSet::default());
+        game.play_record("2-5").unwrap();
+        let mut searcher = Searcher::default();
+        let mut searches = Vec::new();
+        for number in 1..5 {
+            let result = searcher.search(game.node(), SearchLimits::depth(3));
+            game.play(result.best_move.as_ref().unwrap()).unwrap();
+            searches.push((number, result));
+        }
+        let summary = Summary::new(&game, &searches);
+        assert_eq!(summary.moves, [3, 2]);
+        assert!(summary.longest_chain[0] >= 1);
+        assert_eq!(summary.depth, Some(3.0));
+        assert_eq!(summary.evals.iter().map(|(number, _)| *number).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        // a search for a move that was taken back doesn't count
+        game.undo();
+        let summary = Summary::new(&game, &searches);
+        assert_eq!((summary.moves, summary.evals.len()), ([2, 2], 3));
+        searches.push(searches[2].clone());
+        assert_eq!(Summary::new(&game, &searches).evals.len(), 3);
+        assert!(summary.to_string().starts_with("4 moves: White 2, Black 2\nCaptures: White "));
+        assert!(Summary::new(&Game::new(RuleSet::default()), &[]).to_string().ends_with("Longest extra-turn chain: White 0, Black 0"));
+    }
+}
@@ -0,0 +1,182 @@
+use super::mancala::{Move, Player, Score};
+use super::tt::Bound;
+use serde::Serialize;
+use std::fmt::Write;
+
+// a position the search visited. scores and the window are in stones, for the side to move there.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeNode {
+    pub id: usize,
+    // None for the root of an iteration (MTD(f) and multi-PV search the root more than once,
+    // so there can be several)
+    pub parent: Option<usize>,
+    // the full move that led here from the parent (empty at a root)
+    #[serde(rename = "move")]
+    pub mv: Move,
+    pub turn: Player,
+    // depth left to search
+    pub depth: usize,
+    // None when unbounded
+    pub alpha: Option<Score>,
+    pub beta: Option<Score>,
+    // a zero window, only asking whether the position beats alpha
+    pub null_window: bool,
+    pub score: Score,
+    // what the score says about the true value, given the window
+    pub bound: Bound,
+    // failed high: the moves after the last child were never searched
+    pub cutoff: bool,
+    // settled by the transposition table, without searching any further
+    pub tt_hit: bool,
+}
+
+// the part of the tree the last (deepest) iteration of a search explored, in the order the
+// positions were entered, up to a limit on the positions kept
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct SearchTree {
+    pub nodes: Vec<TreeNode>,
+    // positions past the limit were left out
+    pub truncated: bool,
+    #[serde(skip)]
+    limit: usize,
+    // the nodes being searched, innermost last
+    #[serde(skip)]
+    stack: Vec<usize>,
+    // the move to the next node entered
+    #[serde(skip)]
+    next: Move,
+}
+
+impl SearchTree {
+    pub fn new(limit: usize) -> Self {
+        SearchTree { limit, ..SearchTree::default() }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.stack.clear();
+        self.next.clear();
+        self.truncated = false;
+    }
+
+    pub(crate) fn next_move(&mut self, mv: &Move) {
+        self.next.clone_from(mv);
+    }
+
+    // starts a node below the innermost one being searched, reached by the move given to
+    // `next_move`. None once the tree is full, in which case the search below isn't recorded either.
+    pub(crate) fn enter(&mut self, turn: Player, depth: usize, alpha: Option<Score>, beta: Option<Score>, null_window: bool) -> Option<usize> {
+        if self.nodes.len() >= self.limit {
+            self.truncated = true;
+            return None;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(TreeNode {
+            id,
+            parent: self.stack.last().copied(),
+            mv: std::mem::take(&mut self.next),
+            turn,
+            depth,
+            alpha,
+            beta,
+            null_window,
+            score: 0,
+            bound: Bound::Exact,
+            cutoff: false,
+            tt_hit: false,
+        });
+        self.stack.push(id);
+        Some(id)
+    }
+
+    pub(crate) fn leave(&mut self, id: usize, score: Score, bound: Bound, tt_hit: bool) {
+        self.stack.pop();
+        let node = &mut self.nodes[id];
+        node.score = score;
+        node.bound = bound;
+        node.cutoff = bound == Bound::Lower && !tt_hit;
+        node.tt_hit = tt_hit;
+    }
+
+    pub fn children(&self, id: usize) -> impl Iterator<Item = &TreeNode> {
+        self.nodes.iter().filter(move |node| node.parent == Some(id))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a tree always serializes")
+    }
+
+    // Graphviz: each node is labelled with its move, score and window. cutoffs are red, table
+    // hits grey, and nodes that failed low have a dashed outline.
+    pub fn to_dot(&self) -> String {
+        let bound = |value: Option<Score>, infinite: &str| value.map_or(infinite.to_string(), |value| value.to_string());
+        let mut dot = String::from("digraph search {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for node in &self.nodes {
+            let mv = match node.mv.is_empty() {
+                true => "root".to_string(),
+                false => node.mv.iter().map(|pocket| pocket.to_string()).collect::<Vec<_>>().join(",")
+            };
+            let mut styles = Vec::new();
+            if node.tt_hit {
+                styles.push("filled");
+            }
+            if node.bound == Bound::Upper {
+                styles.push("dashed");
+            }
+            let mut style = format!(", style=\"{}\", color={}", styles.join(","), if node.cutoff { "red" } else { "black" });
+            if node.tt_hit {
+                style.push_str(", fillcolor=lightgrey");
+            }
+            let _ = writeln!(dot, "    n{} [label=\"{} ({})\\n{} d{} [{}, {}]\"{}];", node.id, mv, node.turn, node.score, node.depth, bound(node.alpha, "-inf"), bound(node.beta, "inf"), style);
+            if let Some(parent) = node.parent {
+                let _ = writeln!(dot, "    n{} -> n{};", parent, node.id);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SearchLimits;
+    use crate::mancala::Node;
+    use crate::minimax::Searcher;
+
+    #[test]
+    fn test_record() {
+        let node = Node::default();
+        let mut searcher = Searcher::default();
+        searcher.record_tree(Some(100_000));
+        let result = searcher.search(&node, SearchLimits::depth(4));
+        // recording doesn't change the search
+        assert_eq!(result.nodes, Searcher::default().search(&node, SearchLimits::depth(4)).nodes);
+        let tree = searcher.tree().unwrap();
+        assert!(!tree.truncated);
+        let root = &tree.nodes[0];
+        assert_eq!((root.parent, root.depth, root.score), (None, 4, result.score));
+        let moves: Vec<Move> = tree.children(0).map(|child| child.mv.clone()).collect();
+        assert!(moves.iter().all(|mv| node.legal_moves().contains(mv)));
+        for node in &tree.nodes {
+            assert!(node.parent.is_none_or(|parent| parent < node.id));
+            if node.cutoff {
+                assert!(node.beta.is_some_and(|beta| node.score >= beta));
+            }
+        }
+        assert!(tree.nodes.iter().any(|node| node.cutoff) && tree.nodes.iter().any(|node| node.null_window));
+
+        let json: serde_json::Value = serde_json::from_str(&tree.to_json()).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), tree.nodes.len());
+        assert_eq!(json["nodes"][1]["move"], serde_json::json!(tree.nodes[1].mv));
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert_eq!(dot.matches(" -> ").count(), tree.nodes.len() - 1);
+
+        searcher.record_tree(Some(10));
+        searcher.search(&node, SearchLimits::depth(4));
+        let tree = searcher.tree().unwrap();
+        assert_eq!(tree.nodes.len(), 10);
+        assert!(tree.truncated);
+    }
+}
@@ -0,0 +1,252 @@
+use super::mancala;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+// what a stored score says about the true value of a position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bound {
+    Exact,
+    // the search failed high: the true value is at least the score
+    Lower,
+    // the search failed low: the true value is at most the score
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: usize,
+    // from the point of view of the side to move
+    pub score: mancala::Score,
+    pub bound: Bound,
+    // position of the best move in the node's children() order. full moves can be arbitrarily
+    // long chains of sub-moves, so the index is what fits in an entry.
+    pub best_move: Option<usize>,
+    // the subtree never hit the depth limit (every line reached the end of the game),
+    // so the score also holds for deeper searches
+    pub solved: bool,
+}
+
+impl Entry {
+    // whether the entry settles a search of this depth with this window.
+    // deeper entries are not used for shallower searches: that would make a search's result
+    // depend on what the table happened to see before, instead of matching a plain fixed-depth search.
+    // solved entries are good for any depth at least as big as theirs.
+    pub fn cutoff(&self, depth: usize, alpha: mancala::Score, beta: mancala::Score) -> bool {
+        if self.depth != depth && !(self.solved && depth >= self.depth) {
+            return false;
+        }
+        match self.bound {
+            Bound::Exact => true,
+            Bound::Lower => self.score >= beta,
+            Bound::Upper => self.score <= alpha
+        }
+    }
+
+    // the entry without its key, packed into 64 bits, along with the search it came from:
+    // score (16) | depth (8) | bound (2) | solved (1) | move index (8) | has move (1) | generation (8).
+    // None if a field doesn't fit, in which case the entry just isn't stored.
+    fn pack(&self, generation: u8) -> Option<u64> {
+        let score = i16::try_from(self.score).ok()? as u16 as u64;
+        let depth = u8::try_from(self.depth).ok()? as u64;
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3
+        };
+        let best_move = match self.best_move {
+            Some(index) => 0x100 | u8::try_from(index).ok()? as u64,
+            None => 0
+        };
+        Some(score | depth << 16 | bound << 24 | (self.solved as u64) << 26 | best_move << 27 | (generation as u64) << 36)
+    }
+
+    fn unpack(key: u64, data: u64) -> Option<Self> {
+        let bound = match (data >> 24) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            // never written
+            _ => { return None; }
+        };
+        let best_move = (data >> 27) & 0x1ff;
+        Some(Entry {
+            key,
+            depth: ((data >> 16) & 0xff) as usize,
+            score: (data & 0xffff) as u16 as i16 as mancala::Score,
+            bound,
+            best_move: (best_move & 0x100 != 0).then_some((best_move & 0xff) as usize),
+            solved: (data >> 26) & 1 != 0,
+        })
+    }
+}
+
+fn generation_of(data: u64) -> u8 {
+    (data >> 36) as u8
+}
+
+// entries in the default table
+pub const DEFAULT_ENTRIES: usize = 1 << 16;
+
+// the memory each entry takes
+pub const ENTRY_BYTES: usize = 16;
+
+// the most entries that fit in this many megabytes (at least one). it's a power of two, so it
+// can be less than the megabytes would allow.
+pub fn entries_for_megabytes(megabytes: usize) -> usize {
+    let entries = (megabytes << 20) / ENTRY_BYTES;
+    match entries {
+        0 => 1,
+        _ => 1 << entries.ilog2()
+    }
+}
+
+// what happens when a new entry lands in a slot holding a different position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Replacement {
+    // the new entry always wins
+    Always,
+    // an entry searched deeper than the new one stays, unless it's from an earlier search (see
+    // `TranspositionTable::new_search`): deep results are the expensive ones, but old ones
+    // would otherwise pile up over a long game and crowd out everything current
+    #[default]
+    DepthPreferred,
+}
+
+// a fixed-size hash table of search results, indexed by Zobrist key, that any number of
+// threads can read and write at once without locking. each slot is two atomic words: the
+// packed entry, and the key xor-ed with it. a slot torn by two concurrent writes fails the
+// key check and reads as a miss.
+pub struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+    replacement: Replacement,
+    // of the current search, stamped on every entry stored
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    // the size is rounded up to a power of two
+    pub fn new(entries: usize) -> Self {
+        Self::with_replacement(entries, Replacement::default())
+    }
+
+    pub fn with_replacement(entries: usize, replacement: Replacement) -> Self {
+        TranspositionTable {
+            slots: (0..entries.max(1).next_power_of_two()).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+            replacement,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn entries(&self) -> usize {
+        self.slots.len()
+    }
+
+    // marks the entries stored so far as old, so that, with depth-preferred replacement, the
+    // next search can overwrite them whatever their depth. the generation wraps around after 256
+    // searches, by which time the entries of any one old search are long gone.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.slots.len() - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let [checked_key, data] = &self.slots[self.index(key)];
+        let data = data.load(Ordering::Relaxed);
+        if checked_key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Entry::unpack(key, data)
+    }
+
+    pub fn store(&self, entry: Entry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let Some(data) = entry.pack(generation) else { return; };
+        let [checked_key, slot_data] = &self.slots[self.index(entry.key)];
+        if self.replacement == Replacement::DepthPreferred {
+            let old_data = slot_data.load(Ordering::Relaxed);
+            let old_key = checked_key.load(Ordering::Relaxed) ^ old_data;
+            let keep = old_key != entry.key && generation_of(old_data) == generation
+                && Entry::unpack(old_key, old_data).is_some_and(|old| old.depth > entry.depth);
+            if keep {
+                return;
+            }
+        }
+        checked_key.store(entry.key ^ data, Ordering::Relaxed);
+        slot_data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for [checked_key, data] in &self.slots {
+            checked_key.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_probe() {
+        let table = TranspositionTable::with_replacement(16, Replacement::Always);
+        let entry = Entry { key: 0xdead_beef_0000_0003, depth: 7, score: -42, bound: Bound::Upper, best_move: Some(4), solved: true };
+        table.store(entry.clone());
+        assert_eq!(table.probe(entry.key), Some(entry.clone()));
+        // same slot, different key
+        assert_eq!(table.probe(0x0000_0000_0000_0003), None);
+
+        let other = Entry { key: 0x13, depth: 0, score: 5, bound: Bound::Exact, best_move: None, solved: false };
+        table.store(other.clone());
+        assert_eq!(table.probe(other.key), Some(other));
+        assert_eq!(table.probe(entry.key), None);
+
+        table.clear();
+        assert_eq!(table.probe(0x13), None);
+        assert_eq!(table.probe(0), None);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let deep = Entry { key: 0x21, depth: 6, score: 3, bound: Bound::Exact, best_move: Some(1), solved: false };
+        // same slot, shallower
+        let shallow = Entry { key: 0x31, depth: 2, ..deep.clone() };
+        let always = TranspositionTable::with_replacement(16, Replacement::Always);
+        always.store(deep.clone());
+        always.store(shallow.clone());
+        assert_eq!(always.probe(shallow.key), Some(shallow.clone()));
+
+        let table = TranspositionTable::new(16);
+        table.store(deep.clone());
+        table.store(shallow.clone());
+        assert_eq!((table.probe(deep.key), table.probe(shallow.key)), (Some(deep.clone()), None));
+        // the same position is always updated
+        let updated = Entry { depth: 1, ..deep.clone() };
+        table.store(updated.clone());
+        assert_eq!(table.probe(deep.key), Some(updated));
+        table.store(deep.clone());
+        // but in a new search old entries make way
+        table.new_search();
+        table.store(shallow.clone());
+        assert_eq!((table.probe(deep.key), table.probe(shallow.key)), (None, Some(shallow)));
+    }
+
+    #[test]
+    fn test_megabytes() {
+        assert_eq!(entries_for_megabytes(0), 1);
+        assert_eq!(entries_for_megabytes(1), 1 << 16);
+        assert_eq!(entries_for_megabytes(3), 1 << 17);
+        assert_eq!(TranspositionTable::new(entries_for_megabytes(1)).entries(), 1 << 16);
+    }
+}
@@ -0,0 +1,407 @@
+use super::clock::{self, Clock, OnTimeout};
+use super::display::{self, Style, View};
+use super::engine::{Engine, SearchLimits, SearchResult};
+use super::game::{self, Game};
+use super::mancala::{Player, Pocket};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::process::{Command, Stdio};
+
+// a full-screen game in the terminal: the board on the left, the moves so far on the right and the
+// engine's latest thoughts underneath. it draws with plain escape sequences and puts the terminal
+// into raw mode through stty, so it needs a Unix-like terminal but nothing beyond std.
+
+// a key press, as a terminal in raw mode sends it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Char(char),
+    // a left click, counted from 0 at the top left of the screen
+    Click { column: usize, row: usize },
+    // ctrl-c or ctrl-d, which raw mode delivers as bytes instead of a signal or the end of input
+    Interrupt,
+    Other,
+}
+
+impl Key {
+    // the key at the start of `bytes` and how many bytes it used
+    pub fn parse(bytes: &[u8]) -> Option<(Key, usize)> {
+        match bytes {
+            [] => None,
+            // mouse reports in the SGR form: ESC [ < button ; column ; row, then M for a press
+            // and m for a release
+            [0x1b, b'[', b'<', rest @ ..] => {
+                let Some(end) = rest.iter().position(|&byte| byte == b'M' || byte == b'm') else {
+                    return Some((Key::Other, bytes.len()));
+                };
+                let fields: Vec<usize> = String::from_utf8_lossy(&rest[..end]).split(';').filter_map(|field| field.parse().ok()).collect();
+                let key = match (fields.as_slice(), rest[end]) {
+                    (&[0, column, row], b'M') if column > 0 && row > 0 => Key::Click { column: column - 1, row: row - 1 },
+                    _ => Key::Other
+                };
+                Some((key, 3 + end + 1))
+            }
+            [0x1b, b'[' | b'O', b'C', ..] => Some((Key::Right, 3)),
+            [0x1b, b'[' | b'O', b'D', ..] => Some((Key::Left, 3)),
+            [0x1b, b'[' | b'O', _, ..] => Some((Key::Other, 3)),
+            [0x1b, ..] => Some((Key::Escape, 1)),
+            [b'\r' | b'\n', ..] => Some((Key::Enter, 1)),
+            [3 | 4, ..] => Some((Key::Interrupt, 1)),
+            [byte, ..] if byte.is_ascii_graphic() => Some((Key::Char(byte.to_ascii_lowercase() as char), 1)),
+            [_, ..] => Some((Key::Other, 1))
+        }
+    }
+}
+
+// the terminal in raw mode on the alternate screen, reporting mouse clicks, until this is dropped
+struct RawMode {
+    saved: String,
+}
+
+// (the line editor's raw mode goes through here too)
+pub(crate) fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed: the TUI needs a terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl RawMode {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        // reads give up after half a second without a key, so a running clock gets redrawn
+        stty(&["raw", "-echo", "min", "0", "time", "5"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1000h\x1b[?1006h");
+        io::stdout().flush()?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[?1006l\x1b[?1000l\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+// gap between the board and the move list
+const GUTTER: usize = 4;
+// lines above the board
+const HEADER: usize = 2;
+// where the first pit of a row starts in the box-drawn board, and how far apart they are
+const PIT_COLUMN: usize = 6;
+const PIT_WIDTH: usize = 5;
+
+// `left` and `right` as columns next to each other, `left` padded to `width`
+pub fn side_by_side(left: &[String], right: &[String], width: usize) -> Vec<String> {
+    (0..left.len().max(right.len())).map(|row| {
+        let left = left.get(row).map_or("", String::as_str);
+        match right.get(row) {
+            Some(right) => format!("{}{}{}", left, " ".repeat((width + GUTTER).saturating_sub(display::visible_width(left))), right),
+            None => left.to_string()
+        }
+    }).collect()
+}
+
+// the last `rows` full moves, numbered, with who played them, and the index of the first one
+fn history(game: &Game, rows: usize) -> (Vec<String>, usize) {
+    let mut lines = vec!["Moves".to_string()];
+    let moves: Vec<String> = game.history().iter().zip(game.moves()).enumerate().map(|(index, (before, mv))| {
+        format!("{:3}. {} {}", index + 1, before.get_turn(), game::format_move(mv))
+    }).collect();
+    let first = moves.len().saturating_sub(rows);
+    lines.extend(moves[first..].iter().cloned());
+    (lines, first)
+}
+
+fn engine_line(result: Option<&SearchResult>) -> String {
+    match result {
+        Some(result) => {
+            let pv: Vec<String> = result.pv.iter().map(game::format_move).collect();
+            format!("Engine: depth {} score {:+} ({:.0}% for White), pv {}", result.depth, result.score, result.win_prob * 100.0, pv.join(" "))
+        }
+        None => "Engine: -".to_string()
+    }
+}
+
+// what a click on part of the screen does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Pit(Pocket),
+    // show the position after this many moves
+    Replay(usize),
+}
+
+// where the pits and moves were last drawn
+#[derive(Debug, Clone, PartialEq)]
+struct Area {
+    row: usize,
+    columns: Range<usize>,
+    target: Target,
+}
+
+// what the TUI keeps between screens
+struct Screen<'a> {
+    humans: [bool; 2],
+    colors: bool,
+    // index into the mover's pits, in the order they're shown
+    selected: usize,
+    message: String,
+    latest: Option<SearchResult>,
+    // looking back at the position after this many moves instead of playing on
+    replay: Option<usize>,
+    areas: Vec<Area>,
+    clock: Option<Clock>,
+    game: &'a mut Game,
+}
+
+impl Screen<'_> {
+    fn view(&self, game: &Game) -> View {
+        let node = game.node();
+        // the one person's side nearest them, or with two (or none) whoever is to move
+        let bottom = match self.humans {
+            [true, false] => Player::White,
+            [false, true] => Player::Black,
+            _ => node.get_turn().clone()
+        };
+        let mut view = View { bottom, style: Style::Unicode, colors: self.colors, labels: true, ..View::default() };
+        if let (Some(before), Some(mv)) = (game.history().last(), game.moves().last()) {
+            view = view.with_last_move(before, mv);
+        }
+        view
+    }
+
+    // the pits the player to move can choose between, in the order they're shown
+    fn choices(&self) -> Vec<Pocket> {
+        let node = self.game.node();
+        display::pit_order(node, &self.view(self.game), node.get_turn())
+    }
+
+    // the game as it's being looked at: the real one, or its first moves when replaying
+    fn shown(&self) -> Game {
+        match self.replay {
+            Some(moves) => {
+                let record: Vec<String> = self.game.moves()[..moves].iter().map(game::format_move).collect();
+                Game::from_record_at(self.game.start().clone(), &record.join(" ")).expect("the game's own moves replay")
+            }
+            None => self.game.clone()
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let game = self.shown();
+        let node = game.node();
+        let view = self.view(&game);
+        let mut left: Vec<String> = display::render(node, &view).lines().map(str::to_string).collect();
+        let width = left.iter().map(|line| display::visible_width(line)).max().unwrap_or(0);
+        // a pointer under (or over) the chosen pit, for the player at the keyboard
+        if self.humans[node.get_turn().index()] && game.outcome().is_none() && self.replay.is_none() {
+            let pointer = format!("{}^^^", " ".repeat(PIT_COLUMN + PIT_WIDTH * self.selected));
+            match *node.get_turn() == view.bottom {
+                true => left.insert(left.len() - 1, pointer),
+                false => left.insert(0, pointer)
+            }
+        }
+        let (moves, first) = history(self.game, left.len() - 1);
+        // what's under the mouse: the pits in the rows inside the board's top and bottom borders
+        // and the moves down the right
+        self.areas.clear();
+        let rows = |border: &str| left.iter().position(|line| line.starts_with(border));
+        for (row, player) in [(rows("┌").map(|row| row + 1), view.bottom.toggled()), (rows("└").map(|row| row - 1), view.bottom.clone())] {
+            let Some(row) = row else { continue; };
+            for (index, pocket) in display::pit_order(node, &view, &player).into_iter().enumerate() {
+                let start = PIT_COLUMN + PIT_WIDTH * index;
+                self.areas.push(Area { row: HEADER + row, columns: start..start + PIT_WIDTH - 1, target: Target::Pit(pocket) });
+            }
+        }
+        for (line, index) in (1..moves.len()).zip(first..) {
+            let start = width + GUTTER;
+            self.areas.push(Area { row: HEADER + line, columns: start..start + moves[line].len(), target: Target::Replay(index + 1) });
+        }
+        let mut lines = vec!["mancala-rs".to_string(), String::new()];
+        lines.extend(side_by_side(&left, &moves, width));
+        lines.push(String::new());
+        if let Some(clock) = &self.clock {
+            lines.push(clock.to_string());
+        }
+        lines.push(engine_line(self.latest.as_ref()));
+        lines.push(match self.replay {
+            Some(moves) => format!("After move {}: click the board or press esc to go back to the game.", moves),
+            None => self.message.clone()
+        });
+        lines.push("left/right, a-f or a click: choose a pit   enter: play it   u: undo   q: quit".to_string());
+        print!("\x1b[H\x1b[2J{}", lines.join("\r\n"));
+        io::stdout().flush()
+    }
+
+    // what was drawn at a spot on the screen
+    fn target(&self, column: usize, row: usize) -> Option<Target> {
+        self.areas.iter().find(|area| area.row == row && area.columns.contains(&column)).map(|area| area.target)
+    }
+
+    // plays the chosen pit for the player to move
+    fn play(&mut self, pocket: Pocket) {
+        self.message = match self.game.sub_move(pocket) {
+            Ok(_) => String::new(),
+            Err(_) => format!("Can't sow from {}.", display::pit_label(self.game.node(), pocket))
+        };
+    }
+
+    // back to the last position where someone at the keyboard was to move, if there is one
+    fn undo(&mut self) -> bool {
+        let mut rewound = self.game.clone();
+        while rewound.undo().is_some() {
+            if self.humans[rewound.node().get_turn().index()] {
+                *self.game = rewound;
+                self.message = String::new();
+                return true;
+            }
+        }
+        self.message = "Nothing to undo.".to_string();
+        false
+    }
+}
+
+fn outcome_message(game: &Game) -> Option<String> {
+    let outcome = game.outcome()?;
+    let result = match outcome.winner {
+        Some(winner) => format!("{} wins", winner),
+        None => "Draw".to_string()
+    };
+    Some(format!("Game over ({}): {}. Press q to leave.", outcome.reason, result))
+}
+
+// runs the game until it's over and dismissed, or someone quits. `humans` says which sides are
+// played at the keyboard (by Player::index); the engine plays the others.
+// `on_timeout` says what happens when a clock runs out.
+pub fn run(game: &mut Game, engine: &mut dyn Engine, limits: SearchLimits, humans: [bool; 2], colors: bool, clock: Option<Clock>, on_timeout: OnTimeout) -> io::Result<()> {
+    let _raw = RawMode::enter()?;
+    let mut screen = Screen { humans, colors, selected: 0, message: String::new(), latest: None, replay: None, areas: Vec::new(), clock, game };
+    let mut input = io::stdin();
+    let mut buffer = [0u8; 64];
+    loop {
+        let turn = screen.game.node().get_turn().clone();
+        if let Some(clock) = screen.clock.as_mut() {
+            match screen.game.outcome() {
+                Some(_) => clock.stop(),
+                None => clock.start(&turn)
+            }
+        }
+        // (checked every time round, and reads give up often enough for it to be on time)
+        if screen.clock.as_ref().and_then(Clock::flagged).is_some() && screen.game.outcome().is_none() {
+            let played = clock::time_up(screen.game, on_timeout, humans[turn.index()]);
+            screen.message = match played {
+                Some(mv) => format!("{}'s time is up: played {} for them.", turn, game::format_move(&mv)),
+                None => String::new()
+            };
+            continue;
+        }
+        if let Some(message) = outcome_message(screen.game) {
+            screen.message = message;
+        }
+        else if !humans[screen.game.node().get_turn().index()] && screen.replay.is_none() {
+            screen.message = "Thinking...".to_string();
+            screen.draw()?;
+            let result = engine.search(screen.game.node(), screen.clock.as_ref().map_or(limits, |clock| clock.limits(&turn, limits)));
+            // a move found too late isn't played
+            if screen.clock.as_ref().and_then(Clock::flagged).is_some() {
+                continue;
+            }
+            if let Some(mv) = result.best_move.clone() {
+                screen.game.play(&mv).map_err(|_| io::Error::other("the engine chose an illegal move"))?;
+            }
+            screen.latest = Some(result);
+            screen.message = String::new();
+            continue;
+        }
+        screen.selected = screen.selected.min(screen.choices().len().saturating_sub(1));
+        screen.draw()?;
+        // nothing read means no key in time: just draw again
+        let read = input.read(&mut buffer)?;
+        let mut bytes = &buffer[..read];
+        while let Some((key, used)) = Key::parse(bytes) {
+            bytes = &bytes[used..];
+            let over = screen.game.outcome().is_some();
+            let choices = screen.choices();
+            let target = match key {
+                Key::Click { column, row } => screen.target(column, row),
+                _ => None
+            };
+            match (key, target) {
+                (Key::Char('q') | Key::Interrupt, _) => { return Ok(()); }
+                (_, Some(Target::Replay(moves))) => { screen.replay = Some(moves); }
+                (Key::Escape | Key::Click { .. }, _) if screen.replay.is_some() => { screen.replay = None; }
+                _ if screen.replay.is_some() || over => {}
+                (_, Some(Target::Pit(pocket))) => {
+                    if let Some(index) = choices.iter().position(|&choice| choice == pocket) {
+                        screen.selected = index;
+                    }
+                    screen.play(pocket);
+                }
+                (Key::Left, _) => { screen.selected = screen.selected.saturating_sub(1); }
+                (Key::Right, _) => { screen.selected = (screen.selected + 1).min(choices.len().saturating_sub(1)); }
+                (Key::Enter, _) => {
+                    if let Some(&pocket) = choices.get(screen.selected) {
+                        screen.play(pocket);
+                    }
+                }
+                (Key::Char('u'), _) => {
+                    // the engine's thoughts were about the line that's gone
+                    let undone = screen.undo();
+                    if undone {
+                        engine.takeback();
+                        screen.latest = None;
+                    }
+                }
+                (Key::Char(letter), _) if letter.is_ascii_lowercase() => {
+                    let node = screen.game.node();
+                    match node.pits(node.get_turn()).nth((letter as u8 - b'a') as usize) {
+                        Some(pocket) => {
+                            screen.selected = choices.iter().position(|&choice| choice == pocket).unwrap_or(0);
+                            screen.play(pocket);
+                        }
+                        None => { screen.message = format!("There's no pit {} on your side.", letter.to_ascii_uppercase()); }
+                    }
+                }
+                _ => {}
+            }
+            // the engine moves before any more keys are taken
+            if !humans[screen.game.node().get_turn().index()] && screen.replay.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        let keys: Vec<Key> = {
+            let mut bytes: &[u8] = b"\x1b[Cc\r\x1bOD\x03\x1b[A\x1b[<0;12;5M\x1b[<0;12;5m\x1b";
+            let mut keys = Vec::new();
+            while let Some((key, used)) = Key::parse(bytes) {
+                keys.push(key);
+                bytes = &bytes[used..];
+            }
+            keys
+        };
+        assert_eq!(keys, vec![Key::Right, Key::Char('c'), Key::Enter, Key::Left, Key::Interrupt, Key::Other, Key::Click { column: 11, row: 4 }, Key::Other, Key::Escape]);
+        assert_eq!(Key::parse(b"\x1b[<0;1"), Some((Key::Other, 6)));
+        assert_eq!(Key::parse(b"C"), Some((Key::Char('c'), 1)));
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let left = vec!["ab".to_string(), "\x1b[36mc\x1b[0m".to_string(), "d".to_string()];
+        let right = vec!["Moves".to_string(), "1. 2".to_string()];
+        assert_eq!(side_by_side(&left, &right, 3), vec!["ab     Moves", "\x1b[36mc\x1b[0m      1. 2", "d"]);
+    }
+}
@@ -0,0 +1,719 @@
+use super::arena::{self, GameSettings};
+use super::engine::SearchLimits;
+use super::eval::{EvalWeights, Term, WeightedEval, WinModel, STONE, TERMS};
+use super::game::Game;
+use super::endgame::EndgameDb;
+use super::mancala::{Outcome, Player, RuleSet, Score};
+use super::minimax::{EngineOptions, Searcher};
+use super::rng;
+use super::selfplay::{self, SelfPlayOptions};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+
+// one position from a finished game, for fitting evaluation weights to how games turned out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    // every WeightedEval term of the position, in Term::ALL order
+    pub terms: [Score; TERMS],
+    // White's points from the game: 1 for a win, 0.5 for a draw
+    pub result: f64,
+}
+
+pub fn white_points(outcome: &Outcome) -> f64 {
+    match outcome.winner {
+        Some(Player::White) => 1.0,
+        Some(Player::Black) => 0.0,
+        None => 0.5
+    }
+}
+
+// the positions of every finished game, each labelled with how its game ended
+pub fn samples(games: &[Game]) -> Vec<Sample> {
+    let mut every_term = EvalWeights::default();
+    for term in Term::ALL {
+        every_term.set(term, 1);
+    }
+    let eval = WeightedEval::new(every_term);
+    let mut result = Vec::new();
+    for game in games {
+        let Some(outcome) = game.outcome() else { continue; };
+        let points = white_points(&outcome);
+        result.extend(game.history().iter().map(|node| Sample { terms: eval.terms(node), result: points }));
+    }
+    result
+}
+
+// plays a game between two engines from the start, White first
+pub fn play_game(white: &mut Searcher, black: &mut Searcher, rules: RuleSet, limits: SearchLimits) -> Game {
+    play_adjudicated(white, black, rules, limits, &Adjudication::OFF, None)
+}
+
+// when a match game can be called early, to get through big test runs quicker: once both
+// engines' scores have put the same side at least `threshold` stones ahead for `moves` moves in
+// a row (so each engine has had its say), once both have proven the same result, or once the
+// endgame database knows it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjudication {
+    // None to only stop for proven results
+    pub threshold: Option<Score>,
+    pub moves: usize,
+}
+
+impl Adjudication {
+    pub const OFF: Adjudication = Adjudication { threshold: None, moves: usize::MAX };
+
+    // the score (White's point of view) to call the game at, given every search's score so far
+    // and whether it was proven
+    pub fn verdict(&self, scores: &[(Score, bool)]) -> Option<Score> {
+        match scores {
+            [.., (first, true), (second, true)] if first == second => Some(*second),
+            _ => self.threshold.filter(|_| self.moves >= 2 && scores.len() >= self.moves).and_then(|threshold| {
+                let recent = &scores[scores.len() - self.moves..];
+                let last = recent[recent.len() - 1].0;
+                recent.iter().all(|(score, _)| score.signum() == last.signum() && score.abs() >= threshold).then_some(last)
+            })
+        }
+    }
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication { threshold: Some(10), moves: 4 }
+    }
+}
+
+pub fn play_adjudicated(white: &mut Searcher, black: &mut Searcher, rules: RuleSet, limits: SearchLimits, adjudication: &Adjudication, db: Option<&EndgameDb>) -> Game {
+    let settings = GameSettings { adjudication: *adjudication, ..GameSettings::default() };
+    arena::play_out(Game::new(rules), white, black, [limits; 2], &settings, db, |_, _| {})
+}
+
+// an engine for the games tuning plays, judging by `weights`. it picks at random between moves
+// within a stone of the best, so games with different seeds don't all come out the same, and it
+// doesn't solve endgames: that would make up most of the time and teach the evaluation nothing.
+pub fn tuning_engine(weights: EvalWeights, seed: u64) -> Searcher {
+    Searcher::new(EngineOptions {
+        weights,
+        random_margin: Some(1),
+        seed,
+        endgame_stones: 0,
+        ..EngineOptions::default()
+    })
+}
+
+// games of the engine against itself, each with its own seed
+pub fn self_play(rules: RuleSet, games: usize, depth: usize, seed: u64) -> Vec<Game> {
+    (0..games as u64).map(|index| {
+        let seed = seed.wrapping_add(2 * index);
+        play_game(&mut tuning_engine(EvalWeights::default(), seed), &mut tuning_engine(EvalWeights::default(), seed + 1), rules, SearchLimits::depth(depth))
+    }).collect()
+}
+
+// a tuner's progress (SpsaState, RlState), as JSON
+fn load_state<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    serde_json::from_str(&fs::read_to_string(path)?).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+}
+
+fn save_state<T: Serialize>(state: &T, path: &Path) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(state).map_err(io::Error::other)?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuneOptions {
+    // gradient descent steps over the whole training set
+    pub iterations: usize,
+    // how far each step moves the weights, in stones per unit of gradient
+    pub learning_rate: f64,
+    // how sure an evaluation of one stone makes White of winning: the predicted points are
+    // 1 / (1 + e^(-scale * stones))
+    pub scale: f64,
+    // the terms to fit; the others keep their starting weights
+    pub terms: Vec<Term>,
+}
+
+impl Default for TuneOptions {
+    fn default() -> Self {
+        TuneOptions {
+            iterations: 500,
+            learning_rate: 2.0,
+            scale: 0.25,
+            terms: Term::ALL.to_vec(),
+        }
+    }
+}
+
+fn predict(weights: &[f64; TERMS], sample: &Sample, scale: f64) -> f64 {
+    let stones: f64 = weights.iter().zip(sample.terms).map(|(weight, value)| weight * value as f64).sum();
+    1.0 / (1.0 + (-scale * stones).exp())
+}
+
+// weights in stones rather than hundredths, so the gradient doesn't get rounded
+fn as_stones(weights: &EvalWeights) -> [f64; TERMS] {
+    Term::ALL.map(|term| weights.get(term) as f64 / STONE as f64)
+}
+
+fn from_stones(weights: &[f64; TERMS]) -> EvalWeights {
+    let mut result = EvalWeights::default();
+    for (term, weight) in Term::ALL.into_iter().zip(weights) {
+        result.set(term, (weight * STONE as f64).round() as Score);
+    }
+    result
+}
+
+// mean squared error between the points predicted from the evaluation and the real ones
+pub fn loss(samples: &[Sample], weights: &EvalWeights, scale: f64) -> f64 {
+    let weights = as_stones(weights);
+    let total: f64 = samples.iter().map(|sample| (sample.result - predict(&weights, sample, scale)).powi(2)).sum();
+    total / samples.len().max(1) as f64
+}
+
+// "Texel tuning": fits the weights to the samples by gradient descent on `loss`, starting from `start`
+pub fn fit(samples: &[Sample], start: &EvalWeights, options: &TuneOptions) -> EvalWeights {
+    let mut weights = as_stones(start);
+    let tuned: Vec<usize> = options.terms.iter().filter_map(|term| Term::ALL.iter().position(|other| other == term)).collect();
+    let count = samples.len().max(1) as f64;
+    for _ in 0..options.iterations {
+        let mut gradient = [0.0; TERMS];
+        for sample in samples {
+            let predicted = predict(&weights, sample, options.scale);
+            let slope = -2.0 * (sample.result - predicted) * predicted * (1.0 - predicted) * options.scale;
+            for &index in &tuned {
+                gradient[index] += slope * sample.terms[index] as f64 / count;
+            }
+        }
+        for &index in &tuned {
+            weights[index] -= options.learning_rate * gradient[index];
+        }
+    }
+    let mut result = *start;
+    for &index in &tuned {
+        result.set(Term::ALL[index], from_stones(&weights).get(Term::ALL[index]));
+    }
+    result
+}
+
+// settings for tuning by self-play with SPSA (simultaneous perturbation stochastic approximation):
+// each step nudges every weight up or down at random, plays the nudged-up engine against the
+// nudged-down one, and moves the weights towards whichever won. it only needs game results, so it
+// works for anything that changes how the engine plays, but it takes many games.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpsaOptions {
+    // game pairs per step, each pair with the colours swapped
+    pub pairs: usize,
+    pub depth: usize,
+    // step sizes, in stones: `a` scales how far the weights move, `c` how far they're nudged.
+    // both shrink as the steps go by (with the usual exponents, 0.602 and 0.101), and `stability`
+    // holds `a` back for the first few hundred steps.
+    pub a: f64,
+    pub c: f64,
+    pub stability: f64,
+    // the terms to tune. the store weight is left out by default: scaling every weight at once
+    // hardly changes how the engine plays, so one of them has to stay put.
+    pub terms: Vec<Term>,
+    pub adjudication: Adjudication,
+}
+
+impl Default for SpsaOptions {
+    fn default() -> Self {
+        SpsaOptions {
+            pairs: 4,
+            depth: 4,
+            a: 0.1,
+            c: 0.2,
+            stability: 100.0,
+            terms: Term::ALL.into_iter().filter(|&term| term != Term::Store).collect(),
+            adjudication: Adjudication::default(),
+        }
+    }
+}
+
+// where an SPSA run has got to. it's saved after every step, so a run can be stopped at any
+// time and picked up again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpsaState {
+    pub steps: u64,
+    pub seed: u64,
+    // the weights in stones, unrounded
+    pub weights: [f64; TERMS],
+}
+
+impl SpsaState {
+    pub fn new(start: &EvalWeights, seed: u64) -> Self {
+        SpsaState { steps: 0, seed, weights: as_stones(start) }
+    }
+
+    pub fn eval_weights(&self) -> EvalWeights {
+        from_stones(&self.weights)
+    }
+
+    // plays one step's games and updates the weights. returns the nudged-up engine's points
+    // minus the nudged-down one's.
+    pub fn step(&mut self, rules: RuleSet, options: &SpsaOptions) -> f64 {
+        let k = self.steps as f64 + 1.0;
+        let a = options.a / (k + options.stability).powf(0.602);
+        let c = options.c / k.powf(0.101);
+        let step_seed = rng::splitmix64(self.seed ^ self.steps);
+        let mut rng = rng::Rng::new(step_seed);
+        let mut delta = [0.0; TERMS];
+        for term in &options.terms {
+            let index = Term::ALL.iter().position(|other| other == term).expect("every term is in ALL");
+            delta[index] = if rng.below(2) == 0 { -1.0 } else { 1.0 };
+        }
+        let nudged = |sign: f64| -> [f64; TERMS] { std::array::from_fn(|index| self.weights[index] + sign * c * delta[index]) };
+        let (plus, minus) = (from_stones(&nudged(1.0)), from_stones(&nudged(-1.0)));
+        let mut difference = 0.0;
+        for pair in 0..options.pairs as u64 {
+            let seed = step_seed.wrapping_add(2 * pair);
+            let limits = SearchLimits::depth(options.depth);
+            let points = white_points(&play_adjudicated(&mut tuning_engine(plus, seed), &mut tuning_engine(minus, seed + 1), rules, limits, &options.adjudication, None).outcome().expect("games are played to the end"));
+            difference += 2.0 * points - 1.0;
+            let points = white_points(&play_adjudicated(&mut tuning_engine(minus, seed), &mut tuning_engine(plus, seed + 1), rules, limits, &options.adjudication, None).outcome().expect("games are played to the end"));
+            difference -= 2.0 * points - 1.0;
+        }
+        for (weight, delta) in self.weights.iter_mut().zip(delta) {
+            *weight += a * difference / (2.0 * c * options.pairs.max(1) as f64) * delta;
+        }
+        self.steps += 1;
+        difference
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load_state(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_state(self, path)
+    }
+}
+
+// settings for evolving weights: every generation plays a round robin, the best `survivors` go
+// through unchanged and the rest of the population is replaced by mutated copies of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvolveOptions {
+    pub population: usize,
+    pub survivors: usize,
+    // games each pair of weights plays per generation (colours alternate)
+    pub games: usize,
+    pub depth: usize,
+    // the most a mutation moves a weight, in hundredths of a stone
+    pub mutation: Score,
+    // the matches of a generation are spread over this many threads
+    pub threads: usize,
+    // the terms that mutate. the store weight is left out by default, as for SPSA.
+    pub terms: Vec<Term>,
+    pub adjudication: Adjudication,
+}
+
+impl Default for EvolveOptions {
+    fn default() -> Self {
+        EvolveOptions {
+            population: 8,
+            survivors: 2,
+            games: 2,
+            depth: 3,
+            mutation: 30,
+            threads: 1,
+            terms: Term::ALL.into_iter().filter(|&term| term != Term::Store).collect(),
+            adjudication: Adjudication::default(),
+        }
+    }
+}
+
+// how a generation went
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub number: u64,
+    // the one that scored the most points
+    pub champion: EvalWeights,
+    // its share of the points it could have won
+    pub score: f64,
+}
+
+// a population of weights evolving by playing each other. the matches and mutations all come
+// from the seed, so a run can be repeated (whatever the number of threads).
+#[derive(Debug, Clone)]
+pub struct Evolution {
+    pub population: Vec<EvalWeights>,
+    pub generations: u64,
+    rng: rng::Rng,
+    seed: u64,
+}
+
+impl Evolution {
+    // `start` and mutations of it
+    pub fn new(start: &EvalWeights, options: &EvolveOptions, seed: u64) -> Self {
+        let mut evolution = Evolution { population: vec![*start], generations: 0, rng: rng::Rng::new(seed), seed };
+        while evolution.population.len() < options.population.max(2) {
+            let child = evolution.mutate(start, options);
+            evolution.population.push(child);
+        }
+        evolution
+    }
+
+    fn mutate(&mut self, weights: &EvalWeights, options: &EvolveOptions) -> EvalWeights {
+        let mut child = *weights;
+        for &term in &options.terms {
+            let change = self.rng.below(2 * options.mutation as usize + 1) as Score - options.mutation;
+            child.set(term, child.get(term) + change);
+        }
+        child
+    }
+
+    // plays a round robin, then breeds the next generation
+    pub fn generation(&mut self, rules: RuleSet, options: &EvolveOptions) -> Generation {
+        let size = self.population.len();
+        let pairings: Vec<(usize, usize)> = (0..size).flat_map(|first| (first + 1..size).map(move |second| (first, second))).collect();
+        let generation_seed = rng::splitmix64(self.seed ^ self.generations);
+        let population = &self.population;
+        // each pairing's points for its first player, out of `options.games`
+        let play = |index: usize| -> f64 {
+            let (first, second) = pairings[index];
+            let mut points = 0.0;
+            for game in 0..options.games as u64 {
+                let seed = generation_seed.wrapping_add((index as u64) << 16 | game << 1);
+                let (mut white, mut black) = (tuning_engine(population[first], seed), tuning_engine(population[second], seed + 1));
+                let first_is_white = game % 2 == 0;
+                if !first_is_white {
+                    std::mem::swap(&mut white, &mut black);
+                }
+                let outcome = play_adjudicated(&mut white, &mut black, rules, SearchLimits::depth(options.depth), &options.adjudication, None).outcome().expect("games are played to the end");
+                points += if first_is_white { white_points(&outcome) } else { 1.0 - white_points(&outcome) };
+            }
+            points
+        };
+        let threads = options.threads.max(1);
+        let results: Vec<f64> = thread::scope(|scope| {
+            let count = pairings.len();
+            let workers: Vec<_> = (0..threads).map(|thread| {
+                let play = &play;
+                scope.spawn(move || (thread..count).step_by(threads).map(|index| (index, play(index))).collect::<Vec<_>>())
+            }).collect();
+            let mut results = vec![0.0; pairings.len()];
+            for worker in workers {
+                for (index, points) in worker.join().expect("match thread panicked") {
+                    results[index] = points;
+                }
+            }
+            results
+        });
+        let mut points = vec![0.0; size];
+        for (&(first, second), result) in pairings.iter().zip(results) {
+            points[first] += result;
+            points[second] += options.games as f64 - result;
+        }
+        // best first, the older one first on a tie
+        let mut ranking: Vec<usize> = (0..size).collect();
+        ranking.sort_by(|a, b| points[*b].total_cmp(&points[*a]));
+        let champion = self.population[ranking[0]];
+        let survivors: Vec<EvalWeights> = ranking.iter().take(options.survivors.clamp(1, size)).map(|&index| self.population[index]).collect();
+        let mut next = survivors.clone();
+        while next.len() < size {
+            let parent = survivors[self.rng.below(survivors.len())];
+            next.push(self.mutate(&parent, options));
+        }
+        self.population = next;
+        self.generations += 1;
+        Generation {
+            number: self.generations,
+            champion,
+            score: points[ranking[0]] / ((size - 1) * options.games).max(1) as f64,
+        }
+    }
+}
+
+// a position for fitting a WinModel: the search score and stones in play there, and White's points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WinSample {
+    pub score: Score,
+    pub stones_left: Score,
+    pub result: f64,
+}
+
+// every position of the finished games, scored by a search of `depth`
+pub fn win_samples(games: &[Game], depth: usize) -> Vec<WinSample> {
+    let mut searcher = Searcher::new(EngineOptions { endgame_stones: 0, ..EngineOptions::default() });
+    let mut result = Vec::new();
+    for game in games {
+        let Some(outcome) = game.outcome() else { continue; };
+        let points = white_points(&outcome);
+        for node in game.history() {
+            let score = searcher.search(node, SearchLimits::depth(depth)).score;
+            result.push(WinSample { score, stones_left: node.stones_in_play(), result: points });
+        }
+    }
+    result
+}
+
+// fits a WinModel to the samples by Newton's method on the log loss (it's a logistic regression
+// on two features, so a handful of iterations is plenty), starting from `start`. `iterations` is
+// an upper bound: it stops early once a step doesn't help.
+pub fn fit_win_model(samples: &[WinSample], start: &WinModel, iterations: usize) -> WinModel {
+    let mut model = *start;
+    for _ in 0..iterations {
+        let mut gradient = [0.0; 2];
+        let mut hessian = [[0.0; 2]; 2];
+        for sample in samples {
+            let features = [sample.score as f64, sample.score as f64 / (sample.stones_left.max(0) as f64 + 1.0)];
+            let predicted = model.probability(sample.score, sample.stones_left);
+            for i in 0..2 {
+                gradient[i] += (predicted - sample.result) * features[i];
+                for j in 0..2 {
+                    hessian[i][j] += predicted * (1.0 - predicted) * features[i] * features[j];
+                }
+            }
+        }
+        let determinant = hessian[0][0] * hessian[1][1] - hessian[0][1] * hessian[1][0];
+        if determinant.abs() < 1e-12 {
+            break;
+        }
+        let step = [(hessian[1][1] * gradient[0] - hessian[0][1] * gradient[1]) / determinant, (hessian[0][0] * gradient[1] - hessian[1][0] * gradient[0]) / determinant];
+        // a full step can overshoot from far away, so back off until it helps
+        let loss = win_loss(samples, &model);
+        let mut size = 1.0;
+        let mut next = WinModel { slope: model.slope - step[0], endgame: model.endgame - step[1] };
+        while win_loss(samples, &next) > loss && size > 1e-6 {
+            size /= 2.0;
+            next = WinModel { slope: model.slope - size * step[0], endgame: model.endgame - size * step[1] };
+        }
+        if win_loss(samples, &next) > loss {
+            break;
+        }
+        model = next;
+    }
+    model
+}
+
+// the log loss of the model's predictions
+pub fn win_loss(samples: &[WinSample], model: &WinModel) -> f64 {
+    let total: f64 = samples.iter().map(|sample| {
+        let predicted = model.probability(sample.score, sample.stones_left).clamp(1e-9, 1.0 - 1e-9);
+        -(sample.result * predicted.ln() + (1.0 - sample.result) * (1.0 - predicted).ln())
+    }).sum();
+    total / samples.len().max(1) as f64
+}
+
+// `first`'s share of the points from `pairs` pairs of games against `second`, with the colours
+// swapped within each pair
+pub fn match_points(first: &EvalWeights, second: &EvalWeights, rules: RuleSet, pairs: usize, depth: usize, adjudication: &Adjudication, seed: u64) -> f64 {
+    let limits = SearchLimits::depth(depth);
+    let mut points = 0.0;
+    for pair in 0..pairs as u64 {
+        let seed = seed.wrapping_add(2 * pair);
+        let outcome = play_adjudicated(&mut tuning_engine(*first, seed), &mut tuning_engine(*second, seed + 1), rules, limits, adjudication, None).outcome().expect("games are played to the end");
+        points += white_points(&outcome);
+        let outcome = play_adjudicated(&mut tuning_engine(*second, seed), &mut tuning_engine(*first, seed + 1), rules, limits, adjudication, None).outcome().expect("games are played to the end");
+        points += 1.0 - white_points(&outcome);
+    }
+    points / (2 * pairs).max(1) as f64
+}
+
+// settings for the reinforcement learning loop: every generation plays self-play games with the
+// best weights so far, fits new weights to how those games went, and keeps the new weights only
+// if they beat the old ones in a gating match
+#[derive(Debug, Clone, PartialEq)]
+pub struct RlOptions {
+    // how the games are played (the weights and seed are filled in every generation)
+    pub self_play: SelfPlayOptions,
+    pub fit: TuneOptions,
+    // game pairs in the gating match
+    pub gating_pairs: usize,
+    pub gating_depth: usize,
+    // the share of the gating match new weights need to replace the best ones
+    pub accept: f64,
+    pub adjudication: Adjudication,
+}
+
+impl Default for RlOptions {
+    fn default() -> Self {
+        RlOptions {
+            self_play: SelfPlayOptions::default(),
+            fit: TuneOptions::default(),
+            gating_pairs: 10,
+            gating_depth: 4,
+            accept: 0.55,
+            adjudication: Adjudication::default(),
+        }
+    }
+}
+
+// how a generation of the loop went
+#[derive(Debug, Clone)]
+pub struct RlGeneration {
+    pub number: u64,
+    // the self-play games it learned from
+    pub games: Vec<Game>,
+    // the weights fitted to them, and their share of the gating match
+    pub candidate: EvalWeights,
+    pub score: f64,
+    pub accepted: bool,
+}
+
+// where the loop has got to. like SpsaState it's saved after every generation, so it can be
+// stopped and picked up again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RlState {
+    pub generations: u64,
+    pub seed: u64,
+    pub best: EvalWeights,
+    // generations whose candidate replaced the best weights
+    pub accepted: u64,
+}
+
+impl RlState {
+    pub fn new(start: &EvalWeights, seed: u64) -> Self {
+        RlState { generations: 0, seed, best: *start, accepted: 0 }
+    }
+
+    pub fn generation(&mut self, rules: RuleSet, options: &RlOptions) -> RlGeneration {
+        let generation_seed = rng::splitmix64(self.seed ^ self.generations);
+        let games = selfplay::play(rules, &SelfPlayOptions { seed: generation_seed, weights: self.best, ..options.self_play });
+        let candidate = fit(&samples(&games), &self.best, &options.fit);
+        let score = match candidate == self.best {
+            true => 0.5,
+            false => match_points(&candidate, &self.best, rules, options.gating_pairs, options.gating_depth, &options.adjudication, generation_seed)
+        };
+        let accepted = candidate != self.best && score >= options.accept;
+        if accepted {
+            self.best = candidate;
+            self.accepted += 1;
+        }
+        self.generations += 1;
+        RlGeneration { number: self.generations, games, candidate, score, accepted }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        load_state(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_state(self, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mancala::TerminationReason;
+
+    #[test]
+    fn test_fit() {
+        let games = self_play(RuleSet::default(), 6, 2, 1);
+        assert!(games.iter().all(|game| game.outcome().is_some()));
+        // the seeds make the games different, and the same every time
+        assert_ne!(games[0].record(), games[1].record());
+        assert_eq!(self_play(RuleSet::default(), 1, 2, 1)[0].record(), games[0].record());
+
+        let samples = samples(&games);
+        assert_eq!(samples.len(), games.iter().map(|game| game.moves().len()).sum::<usize>());
+        let start = EvalWeights { store: 10, ..EvalWeights::default() };
+        let options = TuneOptions { iterations: 100, ..TuneOptions::default() };
+        let tuned = fit(&samples, &start, &options);
+        assert!(loss(&samples, &tuned, options.scale) < loss(&samples, &start, options.scale));
+        // a term that isn't being fitted stays put
+        let fixed = fit(&samples, &start, &TuneOptions { terms: vec![Term::OwnSide], ..options });
+        assert_eq!(fixed.store, 10);
+    }
+
+    #[test]
+    fn test_adjudication() {
+        let rules = RuleSet::default();
+        let engine = || Searcher::new(EngineOptions { endgame_stones: 0, ..EngineOptions::default() });
+        let limits = SearchLimits::depth(3);
+        let full = play_adjudicated(&mut engine(), &mut engine(), rules, limits, &Adjudication::OFF, None);
+        assert_ne!(full.outcome().unwrap().reason, TerminationReason::Adjudication);
+        assert_eq!(full.record(), play_game(&mut engine(), &mut engine(), rules, limits).record());
+        // agreeing on a one stone lead for two moves is soon enough
+        let early = play_adjudicated(&mut engine(), &mut engine(), rules, limits, &Adjudication { threshold: Some(1), moves: 2 }, None);
+        assert_eq!(early.outcome().unwrap().reason, TerminationReason::Adjudication);
+        assert!(early.moves().len() < full.moves().len());
+        assert!(full.record().starts_with(&early.record()));
+        // the database calls it as soon as the stones left are few enough
+        let db = EndgameDb::build(rules, 8).unwrap();
+        let known = play_adjudicated(&mut engine(), &mut engine(), rules, limits, &Adjudication::OFF, Some(&db));
+        let outcome = known.outcome().unwrap();
+        assert_eq!(outcome.reason, TerminationReason::Adjudication);
+        assert_eq!(Some(outcome.score), db.probe(known.node()));
+        assert!(known.node().stones_in_play() <= 8);
+    }
+
+    #[test]
+    fn test_spsa() {
+        let options = SpsaOptions { pairs: 1, depth: 2, ..SpsaOptions::default() };
+        let mut state = SpsaState::new(&EvalWeights::default(), 3);
+        for _ in 0..3 {
+            let difference = state.step(RuleSet::default(), &options);
+            assert!((-2.0..=2.0).contains(&difference));
+        }
+        assert_eq!(state.steps, 3);
+        assert_eq!(state.eval_weights().store, EvalWeights::default().store);
+
+        // picking a run up from its checkpoint carries on exactly as if it hadn't stopped
+        let path = std::env::temp_dir().join(format!("mancala-spsa-{}.json", std::process::id()));
+        state.save(&path).unwrap();
+        let mut resumed = SpsaState::load(&path).unwrap();
+        assert_eq!(resumed, state);
+        state.step(RuleSet::default(), &options);
+        resumed.step(RuleSet::default(), &options);
+        assert_eq!(resumed, state);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_evolution() {
+        let options = EvolveOptions { population: 4, depth: 2, ..EvolveOptions::default() };
+        let mut evolution = Evolution::new(&EvalWeights::default(), &options, 5);
+        assert_eq!(evolution.population.len(), 4);
+        assert_eq!(evolution.population[0], EvalWeights::default());
+        let mut threaded = evolution.clone();
+        let generation = evolution.generation(RuleSet::default(), &options);
+        assert_eq!(generation.number, 1);
+        assert!((0.0..=1.0).contains(&generation.score));
+        // the champion survives, and the store weight never mutates
+        assert_eq!(evolution.population[0], generation.champion);
+        assert!(evolution.population.iter().all(|weights| weights.store == EvalWeights::default().store));
+        // threads don't change anything
+        let threaded_generation = threaded.generation(RuleSet::default(), &EvolveOptions { threads: 3, ..options });
+        assert_eq!(threaded_generation, generation);
+        assert_eq!(threaded.population, evolution.population);
+    }
+
+    #[test]
+    fn test_fit_win_model() {
+        let games = self_play(RuleSet::default(), 6, 2, 4);
+        let samples = win_samples(&games, 2);
+        assert_eq!(samples.len(), games.iter().map(|game| game.moves().len()).sum::<usize>());
+        let start = WinModel { slope: 1.0, endgame: 0.0 };
+        let fitted = fit_win_model(&samples, &start, 5);
+        assert!(win_loss(&samples, &fitted) < win_loss(&samples, &start));
+    }
+
+    #[test]
+    fn test_rl() {
+        let options = RlOptions {
+            self_play: SelfPlayOptions { games: 4, depth: 2, ..SelfPlayOptions::default() },
+            fit: TuneOptions { iterations: 50, ..TuneOptions::default() },
+            gating_pairs: 1,
+            gating_depth: 2,
+            // always take the candidate, so the test sees the best weights change
+            accept: 0.0,
+            adjudication: Adjudication::default(),
+        };
+        let mut state = RlState::new(&EvalWeights::default(), 2);
+        let mut again = state.clone();
+        let generation = state.generation(RuleSet::default(), &options);
+        assert_eq!(generation.number, 1);
+        assert_eq!(generation.games.len(), 4);
+        assert!((0.0..=1.0).contains(&generation.score));
+        assert_eq!(generation.accepted, generation.candidate != EvalWeights::default());
+        assert_eq!(state.best, generation.candidate);
+        again.generation(RuleSet::default(), &options);
+        assert_eq!(again, state);
+        // nothing can pass a gate it would need more than every point for
+        let mut strict = RlState::new(&EvalWeights::default(), 2);
+        assert!(!strict.generation(RuleSet::default(), &RlOptions { accept: 1.1, ..options }).accepted);
+        assert_eq!(strict.best, EvalWeights::default());
+    }
+}